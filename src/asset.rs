@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+
+/// TeX の画像パスを実際のファイルや URL に対応付ける
+///
+/// `\graphicspath` と拡張子の補完は parser 側で行い, 候補を順に `resolve` に渡す.
+/// 最初に `Some` を返した候補が採用される.
+pub trait AssetResolver {
+    fn resolve(&self, candidate: &str) -> Option<String>;
+}
+
+impl<F> AssetResolver for F
+where
+    F: Fn(&str) -> Option<String>,
+{
+    fn resolve(&self, candidate: &str) -> Option<String> {
+        self(candidate)
+    }
+}
+
+/// ディレクトリ以下に実在するファイルへ解決する
+#[derive(Debug, Clone)]
+pub struct DirectoryResolver {
+    root: PathBuf,
+}
+
+impl DirectoryResolver {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl AssetResolver for DirectoryResolver {
+    fn resolve(&self, candidate: &str) -> Option<String> {
+        let path = self.root.join(candidate);
+        if path.is_file() {
+            Some(path.to_string_lossy().into_owned())
+        } else {
+            None
+        }
+    }
+}
+
+// 拡張子が省略されたときに試す順序 (ブラウザで表示できる形式を優先する)
+const GRAPHICS_EXTENSIONS: [&str; 7] = ["svg", "png", "jpg", "jpeg", "gif", "pdf", "eps"];
+
+/// 解決を試みる候補パスを優先順に並べる
+pub(super) fn candidates(path: &str, graphics_paths: &[String]) -> Vec<String> {
+    let has_extension = path
+        .rsplit('/')
+        .next()
+        .is_some_and(|name| name.contains('.'));
+
+    let prefixes = std::iter::once("").chain(graphics_paths.iter().map(|x| x.as_str()));
+
+    prefixes
+        .flat_map(|prefix| {
+            let base = format!("{}{}", prefix, path);
+            if has_extension {
+                vec![base]
+            } else {
+                GRAPHICS_EXTENSIONS
+                    .iter()
+                    .map(|ext| format!("{}.{}", base, ext))
+                    .collect()
+            }
+        })
+        .collect()
+}
+
+/// `\graphicspath{{figs/}{images/}}` の指定を集める
+pub(super) fn graphics_paths(input: &str) -> Vec<String> {
+    const COMMAND: &str = "\\graphicspath";
+
+    let mut paths = Vec::new();
+    for (i, _) in input.match_indices(COMMAND) {
+        let rest = input[i + COMMAND.len()..].trim_start();
+        let Some(rest) = rest.strip_prefix('{') else {
+            continue;
+        };
+
+        let mut rest = rest.trim_start();
+        while let Some(inner) = rest.strip_prefix('{') {
+            let Some(end) = inner.find('}') else {
+                break;
+            };
+            paths.push(inner[..end].to_string());
+            rest = inner[end + 1..].trim_start();
+        }
+    }
+
+    paths
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn 拡張子がある場合はそのまま() {
+        assert_eq!(
+            candidates("a/b.png", &["figs/".to_string()]),
+            vec!["a/b.png", "figs/a/b.png"]
+        );
+    }
+
+    #[test]
+    fn 拡張子がない場合は補完する() {
+        let cs = candidates("plot", &[]);
+        assert_eq!(cs.first().map(|x| x.as_str()), Some("plot.svg"));
+        assert!(cs.contains(&"plot.pdf".to_string()));
+        assert_eq!(cs.len(), GRAPHICS_EXTENSIONS.len());
+    }
+
+    #[test]
+    fn graphics_paths() {
+        assert_eq!(
+            super::graphics_paths(r"\graphicspath{ {figs/}{./img/} }本文\graphicspath{{x/}}"),
+            vec!["figs/", "./img/", "x/"]
+        );
+        assert!(super::graphics_paths(r"\graphicspath").is_empty());
+    }
+}
@@ -0,0 +1,488 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// `.bib` ファイルから読んだ文献の集まり
+///
+/// `ParseOptions::bibliography` に渡すと, 文書中の `\cite` がこの文献で解決される.
+#[derive(Debug, Clone, Default)]
+pub struct Bibliography {
+    entries: BTreeMap<String, BibEntry>,
+}
+
+impl Bibliography {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_bib(source: &str) -> Result<Self, BibError> {
+        let mut bibliography = Self::new();
+        bibliography.add_bib(source)?;
+        Ok(bibliography)
+    }
+
+    /// ファイルから読む. 複数のファイルは `add_bib` で加える
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BibError> {
+        Self::from_bib(&std::fs::read_to_string(path)?)
+    }
+
+    /// 文献を加える. 同じキーは後から加えたものが優先される
+    ///
+    /// `@string` の定義はこのソースの中でだけ有効.
+    pub fn add_bib(&mut self, source: &str) -> Result<(), BibError> {
+        for entry in BibReader::new(source).read()? {
+            self.entries.insert(entry.key.clone(), entry);
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&BibEntry> {
+        self.entries.get(key)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &BibEntry> {
+        self.entries.values()
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum BibError {
+    #[error("Failed to read the bibliography: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid bibliography at line {line}: {message}")]
+    Syntax { line: usize, message: String },
+}
+
+/// `@article{key, ...}` などの一項目
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct BibEntry {
+    /// `article` のような小文字の種類
+    kind: String,
+    key: String,
+    /// 小文字のフィールド名から, `@string` と `#` を展開した値
+    fields: BTreeMap<String, String>,
+}
+
+impl BibEntry {
+    pub(crate) fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub(crate) fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub(crate) fn fields(&self) -> &BTreeMap<String, String> {
+        &self.fields
+    }
+
+    /// 値を TeX の波括弧などを除いた文字列にして返す
+    pub(crate) fn field(&self, name: &str) -> Option<String> {
+        self.fields.get(name).map(|x| clean_text(x))
+    }
+
+    /// 著者 (なければ編者) の姓
+    pub(crate) fn last_names(&self) -> Vec<String> {
+        let names = self
+            .fields
+            .get("author")
+            .or_else(|| self.fields.get("editor"));
+        names
+            .map(|x| split_names(x).iter().map(|x| last_name(x)).collect())
+            .unwrap_or_default()
+    }
+
+    /// 著者 (なければ編者) の表記どおりの名前
+    pub(crate) fn full_names(&self) -> Vec<String> {
+        let names = self
+            .fields
+            .get("author")
+            .or_else(|| self.fields.get("editor"));
+        names
+            .map(|x| split_names(x).iter().map(|x| full_name(x)).collect())
+            .unwrap_or_default()
+    }
+
+    /// 発行年. biblatex の `date` からも読む
+    pub(crate) fn year(&self) -> Option<String> {
+        self.field("year").or_else(|| {
+            self.field("date")
+                .map(|x| x.chars().take_while(|c| c.is_ascii_digit()).collect())
+        })
+    }
+}
+
+struct BibReader {
+    chars: Vec<char>,
+    pos: usize,
+    strings: BTreeMap<String, String>,
+}
+
+impl BibReader {
+    fn new(source: &str) -> Self {
+        let months = [
+            "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+        ];
+        // 月の略称は BibTeX が定義済み
+        let strings = months
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (m.to_string(), (i + 1).to_string()))
+            .collect();
+
+        Self {
+            chars: source.chars().collect(),
+            pos: 0,
+            strings,
+        }
+    }
+
+    fn read(mut self) -> Result<Vec<BibEntry>, BibError> {
+        let mut entries = Vec::new();
+
+        // `@` の外はコメント
+        while let Some(start) = self.chars[self.pos..].iter().position(|&c| c == '@') {
+            self.pos += start + 1;
+            let kind = self.read_identifier().to_lowercase();
+            self.skip_whitespaces();
+            let close = match self.next() {
+                Some('{') => '}',
+                Some('(') => ')',
+                _ => return Err(self.error("expected `{` or `(` after the entry type")),
+            };
+
+            match kind.as_str() {
+                "comment" | "preamble" => {
+                    self.pos -= 1;
+                    self.read_balanced()?;
+                }
+                "string" => {
+                    let (name, value) = self.read_field()?;
+                    self.strings.insert(name, value);
+                    self.expect(close)?;
+                }
+                _ => entries.push(self.read_entry(kind, close)?),
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn read_entry(&mut self, kind: String, close: char) -> Result<BibEntry, BibError> {
+        self.skip_whitespaces();
+        let mut key = String::new();
+        while let Some(c) = self.peek() {
+            if c == ',' || c == close || c.is_whitespace() {
+                break;
+            }
+            key.push(c);
+            self.pos += 1;
+        }
+        if key.is_empty() {
+            return Err(self.error("missing citation key"));
+        }
+
+        let mut fields = BTreeMap::new();
+        loop {
+            self.skip_whitespaces();
+            match self.next() {
+                Some(',') => {}
+                Some(c) if c == close => break,
+                _ => return Err(self.error(&format!("expected `,` or `{}` in `{}`", close, key))),
+            }
+            self.skip_whitespaces();
+            // 最後の項目の後の `,` は許す
+            if self.peek() == Some(close) {
+                self.pos += 1;
+                break;
+            }
+            let (name, value) = self.read_field()?;
+            fields.insert(name, value);
+        }
+
+        Ok(BibEntry { kind, key, fields })
+    }
+
+    /// `name = "a" # b # {c}` を読む
+    fn read_field(&mut self) -> Result<(String, String), BibError> {
+        self.skip_whitespaces();
+        let name = self.read_identifier().to_lowercase();
+        if name.is_empty() {
+            return Err(self.error("expected a field name"));
+        }
+        self.skip_whitespaces();
+        self.expect('=')?;
+
+        let mut value = String::new();
+        loop {
+            self.skip_whitespaces();
+            value.push_str(&self.read_piece()?);
+            self.skip_whitespaces();
+            if self.peek() == Some('#') {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+
+        Ok((name, value))
+    }
+
+    fn read_piece(&mut self) -> Result<String, BibError> {
+        match self.peek() {
+            Some('{') => {
+                let s = self.read_balanced()?;
+                Ok(s[1..s.len() - 1].to_string())
+            }
+            Some('"') => {
+                self.pos += 1;
+                let mut s = String::new();
+                let mut depth = 0;
+                loop {
+                    match self.next() {
+                        Some('"') if depth == 0 => return Ok(s),
+                        Some(c) => {
+                            match c {
+                                '{' => depth += 1,
+                                '}' => depth -= 1,
+                                _ => {}
+                            }
+                            s.push(c);
+                        }
+                        None => return Err(self.error("unterminated string")),
+                    }
+                }
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let mut s = String::new();
+                while let Some(c) = self.peek().filter(|c| c.is_ascii_digit()) {
+                    s.push(c);
+                    self.pos += 1;
+                }
+                Ok(s)
+            }
+            _ => {
+                let name = self.read_identifier().to_lowercase();
+                if name.is_empty() {
+                    return Err(self.error("expected a value"));
+                }
+                self.strings
+                    .get(&name)
+                    .cloned()
+                    .ok_or_else(|| self.error(&format!("undefined string `{}`", name)))
+            }
+        }
+    }
+
+    /// `{` から対応する `}` までを括弧ごと読む
+    fn read_balanced(&mut self) -> Result<String, BibError> {
+        let (open, close) = match self.peek() {
+            Some('(') => ('(', ')'),
+            _ => ('{', '}'),
+        };
+        let mut s = String::new();
+        let mut depth = 0;
+        while let Some(c) = self.next() {
+            s.push(c);
+            if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(s);
+                }
+            }
+        }
+        Err(self.error("unbalanced braces"))
+    }
+
+    fn read_identifier(&mut self) -> String {
+        let mut s = String::new();
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_alphanumeric() || "_-:.+/".contains(*c))
+        {
+            s.push(c);
+            self.pos += 1;
+        }
+        s
+    }
+
+    fn skip_whitespaces(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), BibError> {
+        self.skip_whitespaces();
+        if self.next() == Some(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", c)))
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn error(&self, message: &str) -> BibError {
+        let end = self.pos.min(self.chars.len());
+        BibError::Syntax {
+            line: self.chars[..end].iter().filter(|&&c| c == '\n').count() + 1,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// 波括弧の外の ` and ` で名前を分ける
+fn split_names(s: &str) -> Vec<String> {
+    split_top_level(s, |words| words == "and")
+        .into_iter()
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// 波括弧の外の空白で区切った語のうち `is_sep` を満たす語で分ける
+fn split_top_level(s: &str, is_sep: impl Fn(&str) -> bool) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    for word in top_level_words(s) {
+        if is_sep(&word) {
+            parts.push(String::new());
+            continue;
+        }
+        let part = parts.last_mut().unwrap();
+        if !part.is_empty() {
+            part.push(' ');
+        }
+        part.push_str(&word);
+    }
+    parts
+}
+
+fn top_level_words(s: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut depth = 0;
+    for c in s.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            c if c.is_whitespace() && depth == 0 => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// `Knuth, Donald E.` や `Donald E. Knuth` の姓
+fn last_name(name: &str) -> String {
+    let last = match name.split_once(',') {
+        Some((last, _)) => last.to_string(),
+        None => top_level_words(name).pop().unwrap_or_default(),
+    };
+    clean_text(last.trim())
+}
+
+/// `Knuth, Donald E.` を `Donald E. Knuth` の順にする
+fn full_name(name: &str) -> String {
+    let name = match name.split_once(',') {
+        Some((last, first)) => format!("{} {}", first.trim(), last.trim()),
+        None => name.to_string(),
+    };
+    clean_text(name.trim())
+}
+
+/// 波括弧と制御綴の `\` を外し, `~` と `--` を文字にする
+pub(super) fn clean_text(s: &str) -> String {
+    // `\TeX` は `TeX`, `\&` は `&` のように, 制御綴の `\` だけを除く
+    let mut chars = s.chars().peekable();
+    let mut text = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => {}
+            '\\' => text.extend(chars.next_if(|x| !x.is_ascii_alphabetic())),
+            _ => text.push(c),
+        }
+    }
+    let s = text;
+    let s = s.replace("---", "—").replace("--", "–").replace('~', " ");
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn 項目と文字列() {
+        let bib = Bibliography::from_bib(
+            r#"
+            % comment
+            @string{ aw = "Addison-" # {Wesley} }
+            @comment{ ignored }
+            @Book{knuth84,
+              author    = {Knuth, Donald E.},
+              title     = "The {\TeX}book",
+              publisher = aw # { Professional},
+              year      = 1984,
+              month     = feb,
+            }
+            @article(lamport,
+              author = {Leslie Lamport and {The LaTeX3 Project}},
+              date = {1994-06},
+              pages = {1--10})
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(bib.len(), 2);
+        let knuth = bib.get("knuth84").unwrap();
+        assert_eq!(knuth.kind(), "book");
+        assert_eq!(knuth.field("title").as_deref(), Some("The TeXbook"));
+        assert_eq!(
+            knuth.field("publisher").as_deref(),
+            Some("Addison-Wesley Professional")
+        );
+        assert_eq!(knuth.field("month").as_deref(), Some("2"));
+        assert_eq!(knuth.last_names(), vec!["Knuth"]);
+        assert_eq!(knuth.full_names(), vec!["Donald E. Knuth"]);
+
+        let lamport = bib.get("lamport").unwrap();
+        assert_eq!(lamport.last_names(), vec!["Lamport", "The LaTeX3 Project"]);
+        assert_eq!(lamport.year().as_deref(), Some("1994"));
+        assert_eq!(lamport.field("pages").as_deref(), Some("1–10"));
+    }
+
+    #[test]
+    fn 構文の誤り() {
+        let error = Bibliography::from_bib("@book{a,\n title = undefined }").unwrap_err();
+        assert!(matches!(error, BibError::Syntax { line: 2, .. }));
+        assert!(Bibliography::from_bib("@book{a, title = {x}").is_err());
+    }
+}
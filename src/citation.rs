@@ -0,0 +1,403 @@
+use crate::bib::{self, BibEntry, Bibliography};
+use crate::diagnostic::Diagnostic;
+use crate::node::Node;
+use crate::result_map::ResultMap;
+use std::collections::HashMap;
+
+/// 引用の表記の形式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CitationStyle {
+    /// 最初に引用された順の番号 `[1]`
+    #[default]
+    Numeric,
+    /// 著者と年 `(Knuth, 1984)`
+    AuthorYear,
+}
+
+/// `\cite[p.~3]{a,b}` などによる引用
+#[derive(Debug, Hash)]
+pub(super) struct CitationInfo {
+    command: String,
+    keys: Vec<String>,
+    prenote: Option<String>,
+    postnote: Option<String>,
+    text: Option<String>,
+}
+
+impl CitationInfo {
+    /// natbib と同じく, 省略可能引数が一つなら後注, 二つなら前注と後注とする
+    pub(crate) fn new(command: String, keys: Vec<String>, notes: Vec<String>) -> Self {
+        let mut notes = notes.into_iter().map(|x| bib::clean_text(&x));
+        let (prenote, postnote) = match (notes.next(), notes.next()) {
+            (Some(post), None) => (None, Some(post)),
+            (pre, post) => (pre, post),
+        };
+        let empty = |x: Option<String>| x.filter(|x| !x.is_empty());
+
+        Self {
+            command,
+            keys,
+            prenote: empty(prenote),
+            postnote: empty(postnote),
+            text: None,
+        }
+    }
+
+    pub(crate) fn match_command(name: &str) -> bool {
+        matches!(name, "cite" | "citep" | "citet" | "nocite")
+    }
+
+    pub(crate) fn command(&self) -> &str {
+        &self.command
+    }
+
+    pub(crate) fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    pub(crate) fn prenote(&self) -> Option<&str> {
+        self.prenote.as_deref()
+    }
+
+    pub(crate) fn postnote(&self) -> Option<&str> {
+        self.postnote.as_deref()
+    }
+
+    /// 文書中での表記
+    pub(crate) fn display_text(&self) -> String {
+        self.text.clone().unwrap_or_else(|| "[?]".to_string())
+    }
+}
+
+/// 文献一覧の一項目
+#[derive(Debug)]
+pub(super) struct BibItem {
+    pub key: String,
+    /// 番号または `Knuth 1984` のような著者と年
+    pub label: String,
+    pub kind: String,
+    /// `著者. 題名. 掲載誌, 年.` の形に整えた文字列
+    pub text: String,
+    pub fields: Vec<(String, String)>,
+}
+
+/// 引用に表記を与え, 文献一覧を作る
+///
+/// 一覧には引用された文献と `\nocite` の文献 (`\nocite{*}` であればすべて) を,
+/// 番号形式では引用順, 著者年形式では著者と年の順に並べる.
+/// `\nocite` は本文から取り除く.
+pub(super) fn resolve(
+    rmap: &mut ResultMap,
+    bibliography: Option<&Bibliography>,
+    style: CitationStyle,
+) -> (Vec<BibItem>, Vec<Diagnostic>) {
+    let empty = Bibliography::new();
+    let bibliography = bibliography.unwrap_or(&empty);
+
+    let keys: Vec<_> = rmap
+        .iter()
+        .filter(|(_, node)| matches!(node, Node::Citation(_)))
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    // 引用された順
+    let mut cited: Vec<&BibEntry> = Vec::new();
+    let mut nocite_all = false;
+    let mut diagnostics = Vec::new();
+    for key in &keys {
+        let Some(Node::Citation(info)) = rmap.get(key) else {
+            continue;
+        };
+        for name in &info.keys {
+            if info.command == "nocite" && name == "*" {
+                nocite_all = true;
+                continue;
+            }
+            match bibliography.get(name) {
+                Some(entry) => {
+                    if !cited.iter().any(|x| x.key() == name) {
+                        cited.push(entry);
+                    }
+                }
+                None => diagnostics.push(Diagnostic::warning(
+                    "unknown_citation",
+                    format!("\\{}{{{}}} cites an unknown key", info.command, name),
+                    Some(key.clone()),
+                )),
+            }
+        }
+    }
+    if nocite_all {
+        for entry in bibliography.iter() {
+            if !cited.iter().any(|x| x.key() == entry.key()) {
+                cited.push(entry);
+            }
+        }
+    }
+
+    rmap.detach(|node| matches!(node, Node::Citation(x) if x.command == "nocite"));
+
+    if style == CitationStyle::AuthorYear {
+        cited.sort_by_key(|x| (x.last_names().join(" "), x.year().unwrap_or_default()));
+    }
+    let labels: HashMap<&str, String> = cited
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let label = match style {
+                CitationStyle::Numeric => (i + 1).to_string(),
+                CitationStyle::AuthorYear => format!("{} {}", authors(entry), year(entry)),
+            };
+            (entry.key(), label)
+        })
+        .collect();
+
+    for key in &keys {
+        let Some(Node::Citation(info)) = rmap.get_mut(key) else {
+            continue;
+        };
+        let entries: Vec<_> = info.keys.iter().map(|x| bibliography.get(x)).collect();
+        info.text = Some(format_citation(info, &entries, &labels, style));
+    }
+
+    let items = cited
+        .into_iter()
+        .map(|entry| BibItem {
+            key: entry.key().to_string(),
+            label: labels[entry.key()].clone(),
+            kind: entry.kind().to_string(),
+            text: format_entry(entry),
+            fields: entry
+                .fields()
+                .keys()
+                .filter_map(|name| Some((name.clone(), entry.field(name)?)))
+                .collect(),
+        })
+        .collect();
+
+    (items, diagnostics)
+}
+
+/// 引用の表記を作る
+///
+/// 番号形式は `[1, 2, p. 3]`, `\citet` は `Knuth [1]`.
+/// 著者年形式は `\citep` が `(Knuth, 1984, p. 3)`, `\cite` と `\citet` が `Knuth (1984)`.
+fn format_citation(
+    info: &CitationInfo,
+    entries: &[Option<&BibEntry>],
+    labels: &HashMap<&str, String>,
+    style: CitationStyle,
+) -> String {
+    let pre = info.prenote.as_ref().map(|x| format!("{} ", x));
+    let post = info.postnote.as_ref().map(|x| format!(", {}", x));
+    let notes = |s: String, open: &str, close: &str| {
+        format!(
+            "{}{}{}{}{}",
+            open,
+            pre.as_deref().unwrap_or_default(),
+            s,
+            post.as_deref().unwrap_or_default(),
+            close
+        )
+    };
+
+    match (style, info.command.as_str()) {
+        (CitationStyle::Numeric, command) => {
+            let numbers: Vec<_> = entries
+                .iter()
+                .map(|x| x.map_or("?".to_string(), |x| labels[x.key()].clone()))
+                .collect();
+            let cite = notes(numbers.join(", "), "[", "]");
+            if command == "citet" {
+                let names: Vec<_> = entries
+                    .iter()
+                    .map(|x| x.map_or("?".to_string(), authors))
+                    .collect();
+                format!("{} {}", names.join(", "), cite)
+            } else {
+                cite
+            }
+        }
+        (CitationStyle::AuthorYear, "citep") => {
+            let items: Vec<_> = entries
+                .iter()
+                .map(|x| x.map_or("?".to_string(), |x| format!("{}, {}", authors(x), year(x))))
+                .collect();
+            notes(items.join("; "), "(", ")")
+        }
+        // natbib と同じく, 前注は最初の文献の年に, 後注は最後の文献の年に付ける
+        (CitationStyle::AuthorYear, _) => {
+            let last = entries.len().saturating_sub(1);
+            let items: Vec<_> = entries
+                .iter()
+                .enumerate()
+                .map(|(i, x)| {
+                    x.map_or("?".to_string(), |x| {
+                        format!(
+                            "{} ({}{}{})",
+                            authors(x),
+                            pre.as_deref().filter(|_| i == 0).unwrap_or_default(),
+                            year(x),
+                            post.as_deref().filter(|_| i == last).unwrap_or_default(),
+                        )
+                    })
+                })
+                .collect();
+            items.join("; ")
+        }
+    }
+}
+
+/// `Knuth`, `Knuth and Lamport`, `Knuth et al.`
+fn authors(entry: &BibEntry) -> String {
+    let names = entry.last_names();
+    match names.as_slice() {
+        [] => entry
+            .field("title")
+            .unwrap_or_else(|| entry.key().to_string()),
+        [a] => a.clone(),
+        [a, b] => format!("{} and {}", a, b),
+        [a, ..] => format!("{} et al.", a),
+    }
+}
+
+fn year(entry: &BibEntry) -> String {
+    entry.year().unwrap_or_else(|| "n.d.".to_string())
+}
+
+/// `Donald E. Knuth. The TeXbook. Addison-Wesley, 1984.` の形にする
+fn format_entry(entry: &BibEntry) -> String {
+    let mut parts = Vec::new();
+
+    let names = entry.full_names();
+    if !names.is_empty() {
+        parts.push(match names.as_slice() {
+            [a] => a.clone(),
+            [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
+            [] => unreachable!(),
+        });
+    }
+    parts.extend(entry.field("title"));
+
+    let mut venue = Vec::new();
+    venue.extend(
+        entry
+            .field("journal")
+            .or_else(|| entry.field("journaltitle"))
+            .or_else(|| entry.field("booktitle")),
+    );
+    match (entry.field("volume"), entry.field("number")) {
+        (Some(volume), Some(number)) => venue.push(format!("{}({})", volume, number)),
+        (Some(volume), None) => venue.push(volume),
+        _ => {}
+    }
+    venue.extend(entry.field("pages"));
+    venue.extend(entry.field("publisher"));
+    venue.extend(entry.year());
+    if !venue.is_empty() {
+        parts.push(venue.join(", "));
+    }
+
+    parts
+        .into_iter()
+        .map(|x| {
+            if x.ends_with('.') {
+                x
+            } else {
+                format!("{}.", x)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bib() -> Bibliography {
+        Bibliography::from_bib(
+            r"@book{knuth, author = {Knuth, Donald E.}, title = {The TeXbook},
+                publisher = {Addison-Wesley}, year = 1984}
+              @article{lamport, author = {Leslie Lamport and Ann Smith and Bob Jones},
+                title = {Paper}, journal = {J. Things}, volume = 3, number = 2, year = 1994}",
+        )
+        .unwrap()
+    }
+
+    fn format(command: &str, keys: &[&str], notes: &[&str], style: CitationStyle) -> String {
+        let bib = bib();
+        let info = CitationInfo::new(
+            command.to_string(),
+            keys.iter().map(|x| x.to_string()).collect(),
+            notes.iter().map(|x| x.to_string()).collect(),
+        );
+        let entries: Vec<_> = keys.iter().map(|x| bib.get(x)).collect();
+        let labels = HashMap::from([("knuth", "1".to_string()), ("lamport", "2".to_string())]);
+        format_citation(&info, &entries, &labels, style)
+    }
+
+    #[test]
+    fn 番号形式() {
+        use CitationStyle::Numeric;
+        assert_eq!(
+            format("cite", &["knuth", "lamport"], &[], Numeric),
+            "[1, 2]"
+        );
+        assert_eq!(format("cite", &["knuth"], &["p. 3"], Numeric), "[1, p. 3]");
+        assert_eq!(
+            format("citep", &["knuth"], &["see", "ch. 2"], Numeric),
+            "[see 1, ch. 2]"
+        );
+        assert_eq!(
+            format("citet", &["lamport"], &[], Numeric),
+            "Lamport et al. [2]"
+        );
+        assert_eq!(format("cite", &["knuth", "none"], &[], Numeric), "[1, ?]");
+    }
+
+    #[test]
+    fn 著者年形式() {
+        use CitationStyle::AuthorYear;
+        assert_eq!(
+            format("citep", &["knuth", "lamport"], &["p. 3"], AuthorYear),
+            "(Knuth, 1984; Lamport et al., 1994, p. 3)"
+        );
+        assert_eq!(format("citet", &["knuth"], &[], AuthorYear), "Knuth (1984)");
+        assert_eq!(
+            format("cite", &["knuth"], &["p. 3"], AuthorYear),
+            "Knuth (1984, p. 3)"
+        );
+        assert_eq!(
+            format("citet", &["knuth"], &["see", "p. 3"], AuthorYear),
+            "Knuth (see 1984, p. 3)"
+        );
+        assert_eq!(
+            format("citet", &["knuth"], &["see", ""], AuthorYear),
+            "Knuth (see 1984)"
+        );
+        assert_eq!(
+            format("citet", &["knuth", "lamport"], &["see", "p. 3"], AuthorYear),
+            "Knuth (see 1984); Lamport et al. (1994, p. 3)"
+        );
+        assert_eq!(
+            format("cite", &["knuth", "lamport"], &["p. 3"], AuthorYear),
+            "Knuth (1984); Lamport et al. (1994, p. 3)"
+        );
+    }
+
+    #[test]
+    fn 文献の表記() {
+        let bib = bib();
+        assert_eq!(
+            format_entry(bib.get("knuth").unwrap()),
+            "Donald E. Knuth. The TeXbook. Addison-Wesley, 1984."
+        );
+        assert_eq!(
+            format_entry(bib.get("lamport").unwrap()),
+            "Leslie Lamport, Ann Smith and Bob Jones. Paper. J. Things, 3(2), 1994."
+        );
+    }
+}
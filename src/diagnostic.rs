@@ -0,0 +1,68 @@
+use crate::key::Key;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Diagnostic {
+    severity: Severity,
+    code: &'static str,
+    message: String,
+    key: Option<Key>,
+    offset: Option<usize>,
+}
+
+impl Diagnostic {
+    pub(crate) fn error(code: &'static str, message: impl Into<String>, key: Option<Key>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            key,
+            offset: None,
+        }
+    }
+
+    pub(crate) fn warning(
+        code: &'static str,
+        message: impl Into<String>,
+        key: Option<Key>,
+    ) -> Self {
+        Self {
+            severity: Severity::Warning,
+            code,
+            message: message.into(),
+            key,
+            offset: None,
+        }
+    }
+
+    /// 数式などの中身の先頭からの文字数
+    pub(crate) fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub(crate) fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub(crate) fn code(&self) -> &'static str {
+        self.code
+    }
+
+    pub(crate) fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub(crate) fn key(&self) -> Option<&Key> {
+        self.key.as_ref()
+    }
+
+    pub(crate) fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Severity {
+    Error,
+    Warning,
+}
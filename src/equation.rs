@@ -0,0 +1,294 @@
+use crate::node::Node;
+use crate::result_map::ResultMap;
+
+/// 番号付きの数式として読む環境
+pub(super) fn is_math_env(name: &str) -> bool {
+    matches!(
+        name.trim_end_matches('*'),
+        "equation" | "align" | "gather" | "multline" | "eqnarray" | "flalign"
+    )
+}
+
+/// `\\` で区切った行ごとに番号を振る環境
+fn is_multirow_env(name: &str) -> bool {
+    matches!(
+        name.trim_end_matches('*'),
+        "align" | "gather" | "eqnarray" | "flalign"
+    )
+}
+
+/// ディスプレイ数式の中で番号を振られる一行
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub(super) struct Equation {
+    label: Option<String>,
+    tag: Option<String>,
+    numbered: bool,
+    number: Option<String>,
+}
+
+impl Equation {
+    fn new(numbered: bool) -> Self {
+        Self {
+            label: None,
+            tag: None,
+            numbered,
+            number: None,
+        }
+    }
+
+    pub(crate) fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    pub(crate) fn number(&self) -> Option<&str> {
+        self.number.as_deref()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.label.is_none() && self.tag.is_none()
+    }
+}
+
+/// ディスプレイ数式の中身から `\label`, `\nonumber`, `\notag` を取り除き, 行ごとの番号の情報を返す
+///
+/// `\tag` は表示に必要なので中身に残す.
+/// `env` が None のとき (`\[..\]` や `$$..$$`) は `\tag` のある場合だけ番号を振る.
+pub(super) fn extract(content: &str, env: Option<&str>) -> (String, Vec<Equation>) {
+    let numbered = env.is_some_and(|x| !x.ends_with('*'));
+    let multirow = env.is_some_and(is_multirow_env);
+
+    let chars: Vec<char> = content.chars().collect();
+    let mut out = String::new();
+    let mut rows = vec![Equation::new(numbered)];
+    let mut row_start = 0;
+    // 波括弧と内側の環境の深さ. 内側の `\\` では行を分けない
+    let mut depth = 0_usize;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c != '\\' {
+            match c {
+                '{' => depth += 1,
+                '}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            out.push(c);
+            i += 1;
+            continue;
+        }
+
+        let name: String = chars[i + 1..]
+            .iter()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .collect();
+        let end = i + 1 + name.len();
+        let row = rows.last_mut().unwrap();
+
+        match name.as_str() {
+            "" => {
+                out.push('\\');
+                if let Some(&next) = chars.get(i + 1) {
+                    out.push(next);
+                    if next == '\\' && multirow && depth == 0 {
+                        rows.push(Equation::new(numbered));
+                        row_start = out.len();
+                    }
+                }
+                i += 2;
+                continue;
+            }
+            "label" => {
+                if let Some((label, end)) = read_arg(&chars, end) {
+                    row.label = Some(label);
+                    i = skip_space(&chars, end, &out);
+                    continue;
+                }
+            }
+            "nonumber" | "notag" => {
+                row.numbered = false;
+                i = skip_space(&chars, end, &out);
+                continue;
+            }
+            "tag" => {
+                let star = chars.get(end) == Some(&'*');
+                let arg_start = if star { end + 1 } else { end };
+                if let Some((tag, end)) = read_arg(&chars, arg_start) {
+                    row.tag = Some(tag);
+                    out.extend(&chars[i..end]);
+                    i = end;
+                    continue;
+                }
+            }
+            "begin" => depth += 1,
+            "end" => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+
+        out.extend(&chars[i..end]);
+        i = end;
+    }
+
+    // 最後の `\\` の後の空の行は数えない
+    if rows.len() > 1 && out[row_start..].trim().is_empty() && rows.last().unwrap().is_empty() {
+        rows.pop();
+    }
+    // multline は全体で一つの番号
+    if env.is_some_and(|x| x.trim_end_matches('*') == "multline") {
+        let numbered = rows.iter().all(|x| x.numbered);
+        let label = rows.iter().find_map(|x| x.label.clone());
+        let tag = rows.iter().find_map(|x| x.tag.clone());
+        rows = vec![Equation {
+            label,
+            tag,
+            numbered,
+            number: None,
+        }];
+    }
+
+    if rows.iter().all(|x| !x.numbered && x.is_empty()) {
+        rows.clear();
+    }
+
+    (out.trim().to_string(), rows)
+}
+
+/// `{...}` の中身と, 閉じ括弧の次の位置を返す
+fn read_arg(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let mut i = start;
+    while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+        i += 1;
+    }
+    if chars.get(i) != Some(&'{') {
+        return None;
+    }
+
+    let mut depth = 0;
+    for (j, &c) in chars.iter().enumerate().skip(i) {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    let arg: String = chars[i + 1..j].iter().collect();
+                    return Some((arg.trim().to_string(), j + 1));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// 取り除いた箇所の前後の空白が重ならないようにする
+fn skip_space(chars: &[char], mut i: usize, out: &str) -> usize {
+    if out.is_empty() || out.ends_with(char::is_whitespace) {
+        while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+            i += 1;
+        }
+    }
+    i
+}
+
+/// 数式に番号を振る
+///
+/// `\tag` があればそれを番号とし, なければ文書全体で通し番号を振る.
+pub(super) fn assign_numbers(rmap: &mut ResultMap) {
+    let keys: Vec<_> = rmap
+        .iter()
+        .filter(|(_, node)| matches!(node, Node::MathExpr(me) if !me.equations().is_empty()))
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    let mut counter = 0_usize;
+    for key in keys {
+        let Some(Node::MathExpr(me)) = rmap.get_mut(&key) else {
+            continue;
+        };
+        for eq in me.equations_mut() {
+            eq.number = if let Some(tag) = &eq.tag {
+                Some(tag.clone())
+            } else if eq.numbered {
+                counter += 1;
+                Some(counter.to_string())
+            } else {
+                None
+            };
+        }
+    }
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 中身と, 各行の (ラベル, 番号が付くか)
+    fn rows(content: &str, env: Option<&str>) -> (String, Vec<(String, bool)>) {
+        let (content, rows) = extract(content, env);
+        let rows = rows
+            .into_iter()
+            .map(|x| {
+                let numbered = x.numbered || x.tag.is_some();
+                (x.label.unwrap_or_default(), numbered)
+            })
+            .collect();
+        (content, rows)
+    }
+
+    #[test]
+    fn equation() {
+        assert_eq!(
+            rows(r"a = b \label{eq:a}", Some("equation")),
+            ("a = b".to_string(), vec![("eq:a".into(), true)])
+        );
+        assert_eq!(
+            rows(r"a = b \notag", Some("equation")),
+            ("a = b".to_string(), vec![])
+        );
+        assert_eq!(
+            rows(r"a = b", Some("equation*")),
+            ("a = b".to_string(), vec![])
+        );
+    }
+
+    #[test]
+    fn align_は行ごと() {
+        assert_eq!(
+            rows(
+                r"a &= b \label{x} \\ c &= d \nonumber \\ \begin{matrix} 1 \\ 2 \end{matrix} \\",
+                Some("align")
+            ),
+            (
+                r"a &= b \\ c &= d \\ \begin{matrix} 1 \\ 2 \end{matrix} \\".to_string(),
+                vec![("x".into(), true), ("".into(), false), ("".into(), true)]
+            )
+        );
+    }
+
+    #[test]
+    fn tag() {
+        assert_eq!(
+            rows(r"a \tag{$*$} \label{t}", None),
+            (r"a \tag{$*$}".to_string(), vec![("t".into(), true)])
+        );
+        assert_eq!(
+            rows(r"a \\ b \tag*{A}", Some("align*")),
+            (
+                r"a \\ b \tag*{A}".to_string(),
+                vec![("".into(), false), ("".into(), true)]
+            )
+        );
+        assert_eq!(rows(r"a", None), ("a".to_string(), vec![]));
+    }
+
+    #[test]
+    fn multline_は一つの番号() {
+        assert_eq!(
+            rows(r"a + b \\ + c \label{m}", Some("multline")),
+            (r"a + b \\ + c".to_string(), vec![("m".into(), true)])
+        );
+    }
+}
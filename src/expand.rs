@@ -0,0 +1,454 @@
+use crate::diagnostic::Diagnostic;
+use crate::key::Key;
+use crate::macros::Macros;
+use crate::node::Node;
+use crate::result_map::ResultMap;
+
+/// 展開の上限
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Limits {
+    /// 入れ子になった展開の深さ
+    pub depth: usize,
+    /// 展開の回数と出力の字句数の合計
+    pub tokens: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum ExpansionError {
+    /// 展開が深くなりすぎた. 自分自身を呼ぶ定義など
+    TooDeep(String),
+    TooManyTokens,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// `\alpha` や `\,` など. `\` を含む
+    Control(String),
+    Char(char),
+}
+
+impl Token {
+    fn is_space(&self) -> bool {
+        matches!(self, Token::Char(c) if c.is_whitespace())
+    }
+}
+
+/// 利用者の定義した命令を展開する
+///
+/// `#1`..`#9` を引数で置き換え, 展開結果の中の命令もさらに展開する.
+pub(super) fn expand(
+    content: &str,
+    macros: &Macros,
+    limits: Limits,
+) -> Result<String, ExpansionError> {
+    let mut expander = Expander {
+        macros,
+        limits,
+        count: 0,
+    };
+    let mut out = Vec::new();
+    expander.expand(&tokenize(content), 0, &mut out)?;
+    Ok(detokenize(&out))
+}
+
+/// 数式の中の命令を展開する
+///
+/// 上限を超えた数式は展開せずにそのままにする.
+pub(super) fn expand_math(
+    rmap: &mut ResultMap,
+    macros: &Macros,
+    limits: Limits,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    if macros.is_empty() {
+        return diagnostics;
+    }
+
+    let keys: Vec<_> = rmap
+        .iter()
+        .filter(|(_, node)| matches!(node, Node::MathExpr(_)))
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    for key in keys {
+        let Some(Node::MathExpr(me)) = rmap.get_mut(&key) else {
+            continue;
+        };
+        match expand(me.content_str(), macros, limits) {
+            Ok(content) if content != me.content_str() => me.replace_content(content),
+            Ok(_) => {}
+            Err(e) => diagnostics.push(limit_diagnostic(e, limits, Some(key))),
+        }
+    }
+
+    diagnostics
+}
+
+pub(super) fn limit_diagnostic(e: ExpansionError, limits: Limits, key: Option<Key>) -> Diagnostic {
+    let message = match e {
+        ExpansionError::TooDeep(name) => format!(
+            "expansion of {} exceeded the depth limit of {}",
+            name, limits.depth
+        ),
+        ExpansionError::TooManyTokens => format!(
+            "macro expansion exceeded the limit of {} tokens",
+            limits.tokens
+        ),
+    };
+    Diagnostic::warning("macro_expansion_limit", message, key)
+}
+
+/// `\newenvironment` で定義された環境を, 開始と終了の定義で置き換える
+///
+/// 置き換えた結果に現れる環境も, 上限の深さまで繰り返し置き換える.
+pub(super) fn expand_environments(
+    input: &str,
+    macros: &Macros,
+    limits: Limits,
+) -> Result<String, ExpansionError> {
+    let mut tokens = tokenize(input);
+    let mut added = 0;
+    let mut last = String::new();
+
+    for _ in 0..=limits.depth {
+        let mut out = Vec::new();
+        let mut changed = false;
+        let mut i = 0;
+
+        while i < tokens.len() {
+            let env = match &tokens[i] {
+                Token::Control(name) if name == "\\begin" || name == "\\end" => {
+                    read_arg(&tokens, i + 1).and_then(|(arg, end)| {
+                        let env = detokenize(&arg);
+                        macros.environment(env.trim()).map(|def| (def, end))
+                    })
+                }
+                _ => None,
+            };
+            let Some((def, end)) = env else {
+                out.push(tokens[i].clone());
+                i += 1;
+                continue;
+            };
+
+            let begin = tokens[i] == Token::Control("\\begin".to_string());
+            i = end;
+            let body = if begin {
+                let args = read_args(&tokens, &mut i, def.args(), def.default());
+                substitute(&tokenize(def.begin()), &args)
+            } else {
+                tokenize(def.end())
+            };
+
+            added += body.len();
+            if added > limits.tokens {
+                return Err(ExpansionError::TooManyTokens);
+            }
+            out.extend(body);
+            changed = true;
+            last = def.name().to_string();
+        }
+
+        if !changed {
+            return Ok(detokenize(&out));
+        }
+        tokens = out;
+    }
+
+    Err(ExpansionError::TooDeep(last))
+}
+
+struct Expander<'a> {
+    macros: &'a Macros,
+    limits: Limits,
+    count: usize,
+}
+
+impl Expander<'_> {
+    fn expand(
+        &mut self,
+        tokens: &[Token],
+        depth: usize,
+        out: &mut Vec<Token>,
+    ) -> Result<(), ExpansionError> {
+        let mut i = 0;
+        while i < tokens.len() {
+            self.count += 1;
+            if self.count > self.limits.tokens {
+                return Err(ExpansionError::TooManyTokens);
+            }
+
+            let def = match &tokens[i] {
+                Token::Control(name) => self.macros.get(name),
+                Token::Char(_) => None,
+            };
+            let Some(def) = def else {
+                out.push(tokens[i].clone());
+                i += 1;
+                continue;
+            };
+            if depth >= self.limits.depth {
+                return Err(ExpansionError::TooDeep(def.name().to_string()));
+            }
+
+            i += 1;
+            let args = read_args(tokens, &mut i, def.args(), def.default());
+            let body = substitute(&tokenize(def.body()), &args);
+            self.expand(&body, depth + 1, out)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// 引数を読む. 省略可能な第1引数がなければ既定値を使う
+fn read_args(
+    tokens: &[Token],
+    i: &mut usize,
+    count: usize,
+    default: Option<&str>,
+) -> Vec<Vec<Token>> {
+    let mut args = Vec::new();
+
+    if let Some(default) = default {
+        let start = skip_spaces(tokens, *i);
+        match read_optional(tokens, start) {
+            Some((arg, end)) => {
+                args.push(arg);
+                *i = end;
+            }
+            None => args.push(tokenize(default)),
+        }
+    }
+
+    while args.len() < count {
+        let start = skip_spaces(tokens, *i);
+        match read_arg(tokens, start) {
+            Some((arg, end)) => {
+                args.push(arg);
+                *i = end;
+            }
+            None => break,
+        }
+    }
+
+    args
+}
+
+fn skip_spaces(tokens: &[Token], mut i: usize) -> usize {
+    while tokens.get(i).is_some_and(Token::is_space) {
+        i += 1;
+    }
+    i
+}
+
+/// `{...}` の中身か1字句を読む
+fn read_arg(tokens: &[Token], start: usize) -> Option<(Vec<Token>, usize)> {
+    match tokens.get(start)? {
+        Token::Char('{') => {
+            let mut depth = 0;
+            for (j, token) in tokens.iter().enumerate().skip(start) {
+                match token {
+                    Token::Char('{') => depth += 1,
+                    Token::Char('}') => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some((tokens[start + 1..j].to_vec(), j + 1));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            None
+        }
+        Token::Char('}') => None,
+        token => Some((vec![token.clone()], start + 1)),
+    }
+}
+
+/// `[...]` の中身を読む. 波括弧の中の `]` では閉じない
+fn read_optional(tokens: &[Token], start: usize) -> Option<(Vec<Token>, usize)> {
+    if tokens.get(start) != Some(&Token::Char('[')) {
+        return None;
+    }
+
+    let mut depth = 0;
+    for (j, token) in tokens.iter().enumerate().skip(start + 1) {
+        match token {
+            Token::Char('{') => depth += 1,
+            Token::Char('}') => depth -= 1,
+            Token::Char(']') if depth == 0 => {
+                return Some((tokens[start + 1..j].to_vec(), j + 1));
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// `#1`..`#9` を引数で, `##` を `#` で置き換える
+fn substitute(body: &[Token], args: &[Vec<Token>]) -> Vec<Token> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < body.len() {
+        if body[i] == Token::Char('#') {
+            match body.get(i + 1) {
+                Some(Token::Char(c @ '1'..='9')) => {
+                    let n = *c as usize - '1' as usize;
+                    out.extend(args.get(n).into_iter().flatten().cloned());
+                    i += 2;
+                    continue;
+                }
+                Some(Token::Char('#')) => {
+                    out.push(Token::Char('#'));
+                    i += 2;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        out.push(body[i].clone());
+        i += 1;
+    }
+
+    out
+}
+
+fn tokenize(s: &str) -> Vec<Token> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i] != '\\' {
+            tokens.push(Token::Char(chars[i]));
+            i += 1;
+            continue;
+        }
+
+        let len = chars[i + 1..]
+            .iter()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .count()
+            .max(1);
+        let end = (i + 1 + len).min(chars.len());
+        tokens.push(Token::Control(chars[i..end].iter().collect()));
+        i = end;
+    }
+
+    tokens
+}
+
+fn detokenize(tokens: &[Token]) -> String {
+    let mut s = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Control(name) => {
+                s.push_str(name);
+                // 制御語の直後に英字が来るときは空白で区切る
+                let is_word = name.ends_with(|c: char| c.is_ascii_alphabetic());
+                if is_word
+                    && matches!(tokens.get(i + 1), Some(Token::Char(c)) if c.is_ascii_alphabetic())
+                {
+                    s.push(' ');
+                }
+            }
+            Token::Char(c) => s.push(*c),
+        }
+    }
+
+    s
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: Limits = Limits {
+        depth: 16,
+        tokens: 1000,
+    };
+
+    fn macros(sources: &[&str]) -> Macros {
+        let mut macros = Macros::default();
+        for source in sources {
+            macros.define_source(source);
+        }
+        macros
+    }
+
+    #[test]
+    fn 引数の置き換え() {
+        let macros = macros(&[
+            r"\newcommand{\R}{\mathbb{R}}",
+            r"\newcommand{\pair}[2]{(#1, #2)}",
+            r"\def\norm#1{\lVert#1\rVert}",
+        ]);
+        assert_eq!(
+            expand(r"\pair{x}{\R} \in \R^2", &macros, LIMITS),
+            Ok(r"(x, \mathbb{R}) \in \mathbb{R}^2".to_string())
+        );
+        assert_eq!(
+            expand(r"\norm x + \norm{\frac{a}{b}}", &macros, LIMITS),
+            Ok(r"\lVert x\rVert + \lVert\frac{a}{b}\rVert".to_string())
+        );
+    }
+
+    #[test]
+    fn 省略可能な引数() {
+        let macros = macros(&[r"\newcommand{\seq}[2][n]{#2_{#1}}"]);
+        assert_eq!(
+            expand(r"\seq{a} + \seq[k]{b}", &macros, LIMITS),
+            Ok(r"a_{n} + b_{k}".to_string())
+        );
+    }
+
+    #[test]
+    fn 入れ子の展開() {
+        let macros = macros(&[
+            r"\newcommand{\abs}[1]{\left|#1\right|}",
+            r"\newcommand{\dist}[2]{\abs{#1 - #2}}",
+        ]);
+        assert_eq!(
+            expand(r"\dist{x}{y}", &macros, LIMITS),
+            Ok(r"\left|x - y\right|".to_string())
+        );
+    }
+
+    #[test]
+    fn 上限() {
+        let macros = macros(&[r"\newcommand{\loop}{\loop}", r"\def\twice#1{#1#1}"]);
+        assert_eq!(
+            expand(r"\loop", &macros, LIMITS),
+            Err(ExpansionError::TooDeep(r"\loop".to_string()))
+        );
+        let input = format!("{}ab{}", r"\twice{".repeat(10), "}".repeat(10));
+        assert_eq!(
+            expand(&input, &macros, LIMITS),
+            Err(ExpansionError::TooManyTokens)
+        );
+    }
+
+    #[test]
+    fn 環境の置き換え() {
+        let macros = macros(&[
+            r"\newenvironment{claim}[1][]{\textbf{Claim #1.} \itshape}{\par}",
+            r"\newenvironment{loop}{\begin{loop}}{}",
+        ]);
+        assert_eq!(
+            expand_environments(
+                r"\begin{claim}[A] x \end{claim} \begin{claim}y\end{claim}",
+                &macros,
+                LIMITS
+            ),
+            Ok(r"\textbf{Claim A.} \itshape x \par \textbf{Claim .} \itshape y\par".to_string())
+        );
+        assert_eq!(
+            expand_environments(r"\begin{loop}", &macros, LIMITS),
+            Err(ExpansionError::TooDeep("loop".to_string()))
+        );
+    }
+}
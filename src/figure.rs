@@ -0,0 +1,290 @@
+use crate::asset::{self, AssetResolver};
+use crate::diagnostic::Diagnostic;
+use crate::key::Key;
+use crate::node::Node;
+use crate::result_map::ResultMap;
+use crate::tex_chars::TexChars;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+pub(super) fn is_float_env(name: &str) -> bool {
+    matches!(name, "figure" | "figure*" | "table" | "table*")
+}
+
+pub(super) fn is_subfloat_env(name: &str) -> bool {
+    matches!(name, "subfigure" | "subtable")
+}
+
+#[derive(Debug)]
+pub(super) struct FigureInfo {
+    env: String,
+    placement: Option<String>,
+    images: Vec<ImageInfo>,
+    content: Vec<Key>,
+    caption: Option<Vec<Key>>,
+    short_caption: Option<String>,
+    label: Option<String>,
+    number: Option<String>,
+    subfigures: Vec<FigureInfo>,
+}
+
+// 中身とキャプションの Key は出現位置で変わるので, 個数だけを含める.
+// 中身そのものは ResultMap で `child_keys` の値から求める
+impl Hash for FigureInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.env.hash(state);
+        self.placement.hash(state);
+        self.images.hash(state);
+        self.content.len().hash(state);
+        self.caption.as_ref().map(|x| x.len()).hash(state);
+        self.short_caption.hash(state);
+        self.label.hash(state);
+        self.number.hash(state);
+        self.subfigures.hash(state);
+    }
+}
+
+impl FigureInfo {
+    pub(crate) fn new(env: String, placement: Option<String>) -> Self {
+        Self {
+            env,
+            placement,
+            images: Vec::new(),
+            content: Vec::new(),
+            caption: None,
+            short_caption: None,
+            label: None,
+            number: None,
+            subfigures: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push_image(&mut self, image: ImageInfo) {
+        self.images.push(image);
+    }
+
+    pub(crate) fn push_content(&mut self, key: Key) {
+        self.content.push(key);
+    }
+
+    pub(crate) fn push_subfigure(&mut self, figure: FigureInfo) {
+        self.subfigures.push(figure);
+    }
+
+    pub(crate) fn set_caption(&mut self, short_caption: Option<String>, caption: Vec<Key>) {
+        self.short_caption = short_caption;
+        self.caption = Some(caption);
+    }
+
+    pub(crate) fn set_label(&mut self, label: String) {
+        self.label = Some(label);
+    }
+
+    pub(crate) fn env(&self) -> &str {
+        &self.env
+    }
+
+    pub(crate) fn placement(&self) -> Option<&str> {
+        self.placement.as_deref()
+    }
+
+    pub(crate) fn images(&self) -> &[ImageInfo] {
+        &self.images
+    }
+
+    pub(crate) fn content(&self) -> &[Key] {
+        &self.content
+    }
+
+    pub(crate) fn caption(&self) -> Option<&[Key]> {
+        self.caption.as_deref()
+    }
+
+    pub(crate) fn short_caption(&self) -> Option<&str> {
+        self.short_caption.as_deref()
+    }
+
+    pub(crate) fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    pub(crate) fn number(&self) -> Option<&str> {
+        self.number.as_deref()
+    }
+
+    pub(crate) fn set_number(&mut self, number: Option<String>) {
+        self.number = number;
+    }
+
+    pub(crate) fn subfigures(&self) -> &[FigureInfo] {
+        &self.subfigures
+    }
+
+    pub(crate) fn subfigures_mut(&mut self) -> &mut [FigureInfo] {
+        &mut self.subfigures
+    }
+
+    /// キャプションと中身 (サブ図を含む) の Key
+    pub(crate) fn child_keys(&self) -> Vec<Key> {
+        let mut keys = self.content.clone();
+        keys.extend(self.caption.iter().flatten().cloned());
+        for sub in &self.subfigures {
+            keys.extend(sub.child_keys());
+        }
+        keys
+    }
+
+    pub(crate) fn retain_keys(&mut self, f: &mut impl FnMut(&Key) -> bool) {
+        self.content.retain(|k| f(k));
+        if let Some(caption) = &mut self.caption {
+            caption.retain(|k| f(k));
+        }
+        for sub in &mut self.subfigures {
+            sub.retain_keys(f);
+        }
+    }
+
+    fn images_mut(&mut self) -> Vec<&mut ImageInfo> {
+        let mut images: Vec<_> = self.images.iter_mut().collect();
+        for sub in &mut self.subfigures {
+            images.extend(sub.images_mut());
+        }
+        images
+    }
+}
+
+#[derive(Debug, Hash)]
+pub(super) struct ImageInfo {
+    path: String,
+    options: BTreeMap<String, String>,
+    resolved: Option<String>,
+}
+
+impl ImageInfo {
+    pub(crate) fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub(crate) fn options(&self) -> &BTreeMap<String, String> {
+        &self.options
+    }
+
+    pub(crate) fn resolved(&self) -> Option<&str> {
+        self.resolved.as_deref()
+    }
+}
+
+/// `\includegraphics[opts]{path}` を読む
+pub(super) fn read_includegraphics(cs: &mut TexChars) -> ImageInfo {
+    cs.consume_command_name();
+    cs.consume_star();
+    let options = cs
+        .read_optional()
+        .map(|x| parse_key_values(&x.into_raw_string()))
+        .unwrap_or_default();
+    let path = cs
+        .read_group()
+        .map(|x| x.into_raw_string().trim().to_string())
+        .unwrap_or_default();
+
+    ImageInfo {
+        path,
+        options,
+        resolved: None,
+    }
+}
+
+/// `width=0.5\linewidth, angle=90, draft` のような key=value のリストを読む
+pub(super) fn parse_key_values(s: &str) -> BTreeMap<String, String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut depth = 0;
+    for c in s.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(std::mem::take(&mut item));
+                continue;
+            }
+            _ => {}
+        }
+        item.push(c);
+    }
+    items.push(item);
+
+    items
+        .iter()
+        .filter(|x| !x.trim().is_empty())
+        .map(|x| match x.split_once('=') {
+            Some((k, v)) => (k.trim().to_string(), v.trim().to_string()),
+            None => (x.trim().to_string(), String::new()),
+        })
+        .collect()
+}
+
+/// 画像のパスを解決する
+///
+/// 解決できなかった画像は診断として報告する.
+pub(super) fn resolve_images(
+    rmap: &mut ResultMap,
+    graphics_paths: &[String],
+    resolver: &dyn AssetResolver,
+) -> Vec<Diagnostic> {
+    let keys: Vec<_> = rmap
+        .iter()
+        .filter(|(_, node)| matches!(node, Node::Figure(_) | Node::Image(_)))
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    let mut diagnostics = Vec::new();
+    for key in keys {
+        let images = match rmap.get_mut(&key) {
+            Some(Node::Figure(info)) => info.images_mut(),
+            Some(Node::Image(image)) => vec![image],
+            _ => continue,
+        };
+
+        for image in images {
+            image.resolved = asset::candidates(&image.path, graphics_paths)
+                .iter()
+                .find_map(|x| resolver.resolve(x));
+
+            if image.resolved.is_none() {
+                diagnostics.push(Diagnostic::error(
+                    "unresolved_image",
+                    format!("Image not found: {}", image.path),
+                    Some(key.clone()),
+                ));
+            }
+        }
+    }
+
+    diagnostics
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn parse_key_values() {
+        let kvs = super::parse_key_values(r"width=0.5\linewidth, trim={1 2 3 4}, draft");
+        assert_eq!(kvs.get("width").map(|x| x.as_str()), Some(r"0.5\linewidth"));
+        assert_eq!(kvs.get("trim").map(|x| x.as_str()), Some("{1 2 3 4}"));
+        assert_eq!(kvs.get("draft").map(|x| x.as_str()), Some(""));
+    }
+
+    #[test]
+    fn read_includegraphics() {
+        let mut cs = TexChars::from_str(r"\includegraphics[scale=2]{ figs/a }残り").unwrap();
+        let image = super::read_includegraphics(&mut cs);
+
+        assert_eq!(image.path(), "figs/a");
+        assert_eq!(image.options().get("scale").map(|x| x.as_str()), Some("2"));
+        assert_eq!(cs.into_content_string(), "残り");
+    }
+}
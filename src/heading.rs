@@ -0,0 +1,234 @@
+use crate::key::Key;
+use crate::node::Node;
+use crate::result_map::ResultMap;
+use crate::tex_chars::TexChars;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+#[derive(Debug)]
+pub(super) struct HeadingInfo {
+    level: HeadingLevel,
+    starred: bool,
+    short_title: Option<String>,
+    title: Vec<Key>,
+    number: Option<String>,
+    anchor: String,
+}
+
+// 見出しの Key は出現位置で変わるので含めない. 見出しの中身は ResultMap で子の値から求める
+impl Hash for HeadingInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.level.hash(state);
+        self.starred.hash(state);
+        self.short_title.hash(state);
+        self.number.hash(state);
+        self.anchor.hash(state);
+    }
+}
+
+impl HeadingInfo {
+    pub(crate) fn retain_keys(&mut self, f: &mut impl FnMut(&Key) -> bool) {
+        self.title.retain(|k| f(k));
+    }
+
+    pub(crate) fn new(
+        level: HeadingLevel,
+        starred: bool,
+        short_title: Option<String>,
+        title: Vec<Key>,
+    ) -> Self {
+        Self {
+            level,
+            starred,
+            short_title,
+            title,
+            number: None,
+            anchor: String::new(),
+        }
+    }
+
+    pub(crate) fn level(&self) -> &HeadingLevel {
+        &self.level
+    }
+
+    pub(crate) fn short_title(&self) -> Option<&str> {
+        self.short_title.as_deref()
+    }
+
+    pub(crate) fn title(&self) -> &[Key] {
+        &self.title
+    }
+
+    pub(crate) fn number(&self) -> Option<&str> {
+        self.number.as_deref()
+    }
+
+    pub(crate) fn anchor(&self) -> &str {
+        &self.anchor
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub(super) enum HeadingLevel {
+    Chapter,
+    Section,
+    Subsection,
+    Subsubsection,
+    Paragraph,
+}
+
+impl HeadingLevel {
+    pub(crate) fn match_begin(cs: &TexChars) -> Option<Self> {
+        use HeadingLevel::*;
+
+        match cs.peek_command_name()?.as_str() {
+            "chapter" => Some(Chapter),
+            "section" => Some(Section),
+            "subsection" => Some(Subsection),
+            "subsubsection" => Some(Subsubsection),
+            "paragraph" => Some(Paragraph),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn depth(&self) -> usize {
+        use HeadingLevel::*;
+
+        match self {
+            Chapter => 0,
+            Section => 1,
+            Subsection => 2,
+            Subsubsection => 3,
+            Paragraph => 4,
+        }
+    }
+
+    fn is_numbered(&self) -> bool {
+        // article クラスの secnumdepth (=3) に合わせる
+        !matches!(self, HeadingLevel::Paragraph)
+    }
+}
+
+/// 見出しに番号とアンカーを振る
+///
+/// ResultMap の Key は出現順に振られているので, Key 順に走査すれば文書順になる.
+pub(super) fn assign_numbers(rmap: &mut ResultMap) {
+    let headings: Vec<_> = rmap
+        .iter()
+        .filter_map(|(key, node)| match node {
+            Node::Heading(info) => {
+                Some((key.clone(), info.level, info.starred, info.title.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let mut counters = [0_usize; 5];
+    let mut slugs = HashMap::new();
+
+    for (key, level, starred, title) in headings {
+        let number = if !starred && level.is_numbered() {
+            let depth = level.depth();
+            counters[depth] += 1;
+            counters[depth + 1..].iter_mut().for_each(|c| *c = 0);
+
+            // chapter が一度も現れなければ section から数える
+            let from = if counters[0] > 0 { 0 } else { 1 };
+            let number = counters[from.min(depth)..=depth]
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(".");
+            Some(number)
+        } else {
+            None
+        };
+
+        let text = title
+            .iter()
+            .map(|k| rmap.plain_text_at(k))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let anchor = unique_slug(slugify(&text), &mut slugs);
+
+        if let Some(Node::Heading(info)) = rmap.get_mut(&key) {
+            info.number = number;
+            info.anchor = anchor;
+        }
+    }
+}
+
+pub(super) fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "section".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// 使われていないアンカーにする. `used` は使ったアンカーから, 次に試す番号
+///
+/// `intro-1` のように番号を付けたものも記録し, 元から `intro-1` の見出しとも重ならないようにする.
+pub(super) fn unique_slug(slug: String, used: &mut HashMap<String, usize>) -> String {
+    let mut count = used.get(&slug).copied().unwrap_or(0);
+    loop {
+        let candidate = if count == 0 {
+            slug.clone()
+        } else {
+            format!("{}-{}", slug, count)
+        };
+        count += 1;
+        if !used.contains_key(&candidate) {
+            used.insert(slug, count);
+            used.entry(candidate.clone()).or_insert(1);
+            return candidate;
+        }
+    }
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugify() {
+        assert_eq!(super::slugify("Introduction"), "introduction");
+        assert_eq!(super::slugify("Sheaves on X"), "sheaves-on-x");
+        assert_eq!(super::slugify("  A, B & C!  "), "a-b-c");
+        assert_eq!(super::slugify("層の定義"), "層の定義");
+        assert_eq!(super::slugify("$$"), "section");
+    }
+
+    #[test]
+    fn 重複するスラッグには連番を付ける() {
+        let mut used = HashMap::new();
+        assert_eq!(unique_slug("intro".to_string(), &mut used), "intro");
+        assert_eq!(unique_slug("intro".to_string(), &mut used), "intro-1");
+        assert_eq!(unique_slug("intro".to_string(), &mut used), "intro-2");
+        assert_eq!(unique_slug("other".to_string(), &mut used), "other");
+
+        // 番号を付けたものと元からある名前が重ならない
+        let mut used = HashMap::new();
+        assert_eq!(unique_slug("intro".to_string(), &mut used), "intro");
+        assert_eq!(unique_slug("intro".to_string(), &mut used), "intro-1");
+        assert_eq!(unique_slug("intro-1".to_string(), &mut used), "intro-1-1");
+        assert_eq!(unique_slug("intro".to_string(), &mut used), "intro-2");
+
+        let mut used = HashMap::new();
+        assert_eq!(unique_slug("intro-1".to_string(), &mut used), "intro-1");
+        assert_eq!(unique_slug("intro".to_string(), &mut used), "intro");
+        assert_eq!(unique_slug("intro".to_string(), &mut used), "intro-2");
+    }
+}
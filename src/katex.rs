@@ -0,0 +1,227 @@
+/// KaTeX が対応している制御綴 (先頭の `\` を除く)
+///
+/// 二分探索のため辞書順に並べる. `\mathscr` のように拡張が必要なものは含めない.
+#[rustfmt::skip]
+const SUPPORTED_COMMANDS: &[&str] = &[
+    " ", "!", "#", "$", "%", "&", ",", ":", ";", ">", "AA", "Alpha", "Arrowvert", "Bbb", "Beta",
+    "Big", "Bigg", "Biggl", "Biggm", "Biggr", "Bigl", "Bigm", "Bigr", "Box", "Bumpeq", "Cap", "Chi",
+    "Colonapprox", "Coloneq", "Coloneqq", "Colonsim", "Cup", "Dagger", "Delta", "Diamond",
+    "Downarrow", "Epsilon", "Eta", "Finv", "Game", "Gamma", "Harr", "Huge", "Im", "Iota", "Kappa",
+    "LARGE", "Lambda", "Large", "Larr", "Leftarrow", "Leftrightarrow", "Lleftarrow",
+    "Longleftarrow", "Longleftrightarrow", "Longrightarrow", "Lrarr", "Lsh", "Mu", "Nu", "O", "OE",
+    "Omega", "Omicron", "P", "Phi", "Pi", "Pr", "Psi", "Rarr", "Re", "Rho", "Rightarrow",
+    "Rrightarrow", "Rsh", "S", "Sigma", "Subset", "Supset", "Tau", "Theta", "Uarr", "Uparrow",
+    "Updownarrow", "Upsilon", "Vdash", "Vert", "Vvdash", "Xi", "Zeta", "_", "acute", "aleph",
+    "alpha", "amalg", "angle", "approx", "approxeq", "arccos", "arcsin", "arctan", "arg", "ast",
+    "asymp", "atop", "backepsilon", "backprime", "backsim", "backsimeq", "backslash", "bar",
+    "barwedge", "bcancel", "because", "begin", "beta", "beth", "between", "bf", "big", "bigcap",
+    "bigcirc", "bigcup", "bigg", "biggl", "biggm", "biggr", "bigl", "bigm", "bigodot", "bigoplus",
+    "bigotimes", "bigr", "bigsqcup", "bigstar", "bigtriangledown", "bigtriangleup", "biguplus",
+    "bigvee", "bigwedge", "binom", "blacklozenge", "blacksquare", "blacktriangle",
+    "blacktriangledown", "blacktriangleleft", "blacktriangleright", "bm", "bmod", "bold",
+    "boldsymbol", "bot", "bowtie", "boxdot", "boxed", "boxminus", "boxplus", "boxtimes", "brace",
+    "brack", "breve", "bullet", "bumpeq", "cal", "cancel", "cap", "cdot", "cdotp", "cdots",
+    "centerdot", "cfrac", "check", "checkmark", "chi", "choose", "circ", "circeq",
+    "circlearrowleft", "circlearrowright", "circledR", "circledS", "circledast", "circledcirc",
+    "circleddash", "clubsuit", "colon", "color", "colorbox", "complement", "cong", "coprod",
+    "copyright", "cos", "cosh", "cot", "coth", "csc", "cup", "curlyeqprec", "curlyeqsucc",
+    "curlyvee", "curlywedge", "curvearrowleft", "curvearrowright", "dag", "dagger", "daleth",
+    "dashleftarrow", "dashrightarrow", "dashv", "dbinom", "ddag", "ddagger", "ddot", "ddots", "def",
+    "deg", "degree", "delta", "det", "dfrac", "diagdown", "diagup", "diamond", "diamondsuit",
+    "digamma", "dim", "displaystyle", "div", "divideontimes", "dot", "doteq", "doteqdot", "dotplus",
+    "dots", "dotsb", "dotsc", "dotsi", "dotsm", "dotso", "doublebarwedge", "downarrow",
+    "downdownarrows", "downharpoonleft", "downharpoonright", "edef", "ell", "emph", "empty",
+    "emptyset", "end", "enspace", "epsilon", "eqcirc", "eqsim", "eqslantgtr", "eqslantless",
+    "equiv", "eta", "eth", "exists", "exp", "fallingdotseq", "fbox", "fcolorbox", "flat", "forall",
+    "frac", "frak", "frown", "gamma", "gcd", "gdef", "ge", "genfrac", "geq", "geqq", "geqslant",
+    "gets", "gg", "ggg", "gimel", "gnapprox", "gneq", "gneqq", "gnsim", "grave", "gt", "gtrapprox",
+    "gtrdot", "gtreqless", "gtreqqless", "gtrless", "gtrsim", "hat", "hbar", "hbox", "heartsuit",
+    "hom", "hookleftarrow", "hookrightarrow", "hphantom", "href", "hskip", "hslash", "hspace",
+    "huge", "iff", "iiint", "iint", "imath", "impliedby", "implies", "in", "inf", "infty", "injlim",
+    "int", "intercal", "iota", "isin", "it", "jmath", "kappa", "ker", "kern", "lVert", "lambda",
+    "land", "lang", "langle", "large", "lbrace", "lbrack", "lceil", "ldotp", "ldots", "le",
+    "leadsto", "left", "leftarrow", "leftarrowtail", "leftharpoondown", "leftharpoonup",
+    "leftleftarrows", "leftrightarrow", "leftrightarrows", "leftrightharpoons",
+    "leftrightsquigarrow", "leftthreetimes", "leq", "leqq", "leqslant", "lessapprox", "lessdot",
+    "lesseqgtr", "lesseqqgtr", "lessgtr", "lesssim", "let", "lfloor", "lg", "lgroup", "lhd", "lim",
+    "liminf", "limits", "limsup", "ll", "llcorner", "lll", "lmoustache", "ln", "lnapprox", "lneq",
+    "lneqq", "lnot", "lnsim", "log", "longleftarrow", "longleftrightarrow", "longmapsto",
+    "longrightarrow", "looparrowleft", "looparrowright", "lor", "lozenge", "lparen", "lrcorner",
+    "lt", "ltimes", "lvert", "mapsto", "mathbb", "mathbf", "mathbin", "mathcal", "mathclose",
+    "mathfrak", "mathit", "mathnormal", "mathop", "mathopen", "mathord", "mathpunct", "mathrel",
+    "mathring", "mathrm", "mathsf", "mathtt", "max", "mbox", "measuredangle", "mho", "mid",
+    "middle", "min", "mkern", "mod", "models", "mp", "mskip", "mu", "multimap", "nabla", "natural",
+    "ncong", "ne", "nearrow", "neg", "negthinspace", "neq", "newcommand", "nexists", "ngeq",
+    "ngeqq", "ngeqslant", "ngtr", "ni", "nleftarrow", "nleftrightarrow", "nleq", "nleqq",
+    "nleqslant", "nless", "nmid", "nolimits", "nonumber", "normalsize", "not", "notag", "notin",
+    "nparallel", "nprec", "npreceq", "nrightarrow", "nshortmid", "nshortparallel", "nsim",
+    "nsubseteq", "nsucc", "nsucceq", "nsupseteq", "ntriangleleft", "ntrianglelefteq",
+    "ntriangleright", "ntrianglerighteq", "nu", "nvDash", "nvdash", "nwarrow", "odot", "oint",
+    "omega", "omicron", "ominus", "operatorname", "operatorname*", "oplus", "oslash", "otimes",
+    "over", "overbrace", "overleftarrow", "overleftrightarrow", "overline", "overrightarrow",
+    "overset", "parallel", "partial", "perp", "phantom", "phi", "pi", "pitchfork", "pm", "pmb",
+    "pmod", "pod", "prec", "precapprox", "preccurlyeq", "preceq", "precnapprox", "precneqq",
+    "precnsim", "precsim", "prime", "prod", "projlim", "propto", "providecommand", "psi", "qquad",
+    "quad", "rVert", "raisebox", "rang", "rangle", "rbrace", "rbrack", "rceil", "renewcommand",
+    "rfloor", "rgroup", "rhd", "rho", "right", "rightarrow", "rightarrowtail", "rightharpoondown",
+    "rightharpoonup", "rightleftarrows", "rightleftharpoons", "rightrightarrows", "rightsquigarrow",
+    "rightthreetimes", "risingdotseq", "rm", "rmoustache", "rparen", "rtimes", "rule", "rvert",
+    "scriptscriptstyle", "scriptsize", "scriptstyle", "searrow", "sec", "setminus", "sf", "sharp",
+    "shortmid", "shortparallel", "sigma", "sim", "simeq", "sin", "sinh", "small", "smallint",
+    "smallsetminus", "smash", "smile", "sout", "spadesuit", "sphericalangle", "sqcap", "sqcup",
+    "sqrt", "sqsubset", "sqsubseteq", "sqsupset", "sqsupseteq", "square", "stackrel", "star",
+    "subset", "subseteq", "subseteqq", "subsetneq", "subsetneqq", "substack", "succ", "succapprox",
+    "succcurlyeq", "succeq", "succnapprox", "succneqq", "succnsim", "succsim", "sum", "sup",
+    "supset", "supseteq", "supseteqq", "supsetneq", "supsetneqq", "surd", "swarrow", "tag", "tag*",
+    "tan", "tanh", "tau", "tbinom", "text", "textbf", "textcolor", "textit", "textmd", "textnormal",
+    "textrm", "textsf", "textstyle", "texttt", "textup", "tfrac", "therefore", "theta",
+    "thickapprox", "thicksim", "thickspace", "thinspace", "tilde", "times", "tiny", "to", "top",
+    "triangle", "triangledown", "triangleleft", "trianglelefteq", "triangleq", "triangleright",
+    "trianglerighteq", "tt", "twoheadleftarrow", "twoheadrightarrow", "ulcorner", "underbrace",
+    "underleftarrow", "underleftrightarrow", "underline", "underrightarrow", "underset", "unlhd",
+    "unrhd", "uparrow", "updownarrow", "upharpoonleft", "upharpoonright", "uplus", "upsilon",
+    "upuparrows", "urcorner", "url", "utilde", "vDash", "varDelta", "varGamma", "varLambda",
+    "varOmega", "varPhi", "varPi", "varPsi", "varSigma", "varTheta", "varUpsilon", "varXi",
+    "varepsilon", "varinjlim", "varkappa", "varliminf", "varlimsup", "varnothing", "varphi",
+    "varpi", "varprojlim", "varpropto", "varrho", "varsigma", "varsubsetneq", "varsubsetneqq",
+    "varsupsetneq", "varsupsetneqq", "vartheta", "vartriangle", "vartriangleleft",
+    "vartriangleright", "vcentcolon", "vdash", "vdots", "vec", "vee", "veebar", "vert", "vphantom",
+    "wedge", "widecheck", "widehat", "widetilde", "wp", "wr", "xcancel", "xdef", "xi", "xleftarrow",
+    "xleftrightarrow", "xrightarrow", "zeta", "{", "|", "}",
+];
+
+/// KaTeX が対応している環境
+#[rustfmt::skip]
+const SUPPORTED_ENVIRONMENTS: &[&str] = &[
+    "Bmatrix", "Bmatrix*", "CD", "Vmatrix", "Vmatrix*", "align", "align*", "alignat", "alignat*",
+    "aligned", "alignedat", "array", "bmatrix", "bmatrix*", "cases", "darray", "dcases", "drcases",
+    "equation", "equation*", "gather", "gather*", "gathered", "matrix", "matrix*", "pmatrix",
+    "pmatrix*", "rcases", "smallmatrix", "split", "subarray", "vmatrix", "vmatrix*",
+];
+
+pub(super) fn is_supported_command(name: &str) -> bool {
+    SUPPORTED_COMMANDS.binary_search(&name).is_ok()
+}
+
+pub(super) fn is_supported_environment(name: &str) -> bool {
+    SUPPORTED_ENVIRONMENTS.binary_search(&name).is_ok()
+}
+
+/// KaTeX が対応していない制御綴や環境
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Unsupported {
+    /// `\foo` や `\begin{foo}` の形
+    pub name: String,
+    /// 数式の中身の先頭からの文字数
+    pub offset: usize,
+}
+
+/// 数式の中身から KaTeX が対応していない制御綴を探す
+pub(super) fn check(content: &str) -> Vec<Unsupported> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut unsupported = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i] != '\\' {
+            i += 1;
+            continue;
+        }
+
+        let offset = i;
+        i += 1;
+        let mut name: String = chars[i..]
+            .iter()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .collect();
+        if name.is_empty() {
+            // `\\` や `\,` などの1文字の制御綴
+            if let Some(&c) = chars.get(i) {
+                i += 1;
+                if c != '\\' && !is_supported_command(&c.to_string()) {
+                    unsupported.push(Unsupported {
+                        name: format!("\\{}", c),
+                        offset,
+                    });
+                }
+            }
+            continue;
+        }
+        i += name.chars().count();
+        if chars.get(i) == Some(&'*') && is_supported_command(&format!("{}*", name)) {
+            name.push('*');
+            i += 1;
+        }
+
+        if name == "begin" || name == "end" {
+            let env = read_env_name(&chars, &mut i);
+            if name == "begin" && !is_supported_environment(&env) {
+                unsupported.push(Unsupported {
+                    name: format!("\\begin{{{}}}", env),
+                    offset,
+                });
+            }
+            continue;
+        }
+
+        if !is_supported_command(&name) {
+            unsupported.push(Unsupported {
+                name: format!("\\{}", name),
+                offset,
+            });
+        }
+    }
+
+    unsupported
+}
+
+fn read_env_name(chars: &[char], i: &mut usize) -> String {
+    while chars.get(*i).is_some_and(|c| c.is_whitespace()) {
+        *i += 1;
+    }
+    if chars.get(*i) != Some(&'{') {
+        return String::new();
+    }
+
+    let name: String = chars[*i + 1..].iter().take_while(|&&c| c != '}').collect();
+    *i += name.chars().count() + 2;
+    name
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn 表が整列されている() {
+        assert!(SUPPORTED_COMMANDS.windows(2).all(|w| w[0] < w[1]));
+        assert!(SUPPORTED_ENVIRONMENTS.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn check() {
+        assert_eq!(
+            super::check(r"\frac{a}{b} \\ \operatorname*{max}_x"),
+            vec![]
+        );
+        assert_eq!(
+            super::check(r"\mathscr{L} + \R \begin{multline} x \end{multline}"),
+            vec![
+                Unsupported {
+                    name: r"\mathscr".to_string(),
+                    offset: 0,
+                },
+                Unsupported {
+                    name: r"\R".to_string(),
+                    offset: 14,
+                },
+                Unsupported {
+                    name: r"\begin{multline}".to_string(),
+                    offset: 17,
+                },
+            ]
+        );
+    }
+}
@@ -1,10 +1,56 @@
+mod asset;
+mod bib;
+mod citation;
+mod diagnostic;
+mod equation;
+mod expand;
+mod figure;
+mod heading;
+mod katex;
 mod key;
+mod library;
+mod list;
+mod macros;
+mod math_ast;
+mod math_balance;
+mod math_canon;
 mod math_expr;
+mod math_symbol;
+mod math_text;
+mod mathml;
+mod metadata;
 mod node;
+mod notation;
+mod options;
 mod outside;
 mod parser;
+mod plain_text;
+mod preamble;
+mod reference;
 mod result_map;
+mod table;
 mod tex_char;
 mod tex_chars;
+mod theorem_graph;
+mod workspace;
 
-pub use outside::parse_paragraphs_to_json;
+pub use asset::{AssetResolver, DirectoryResolver};
+pub use bib::{BibError, Bibliography};
+pub use citation::CitationStyle;
+pub use library::{LibraryError, MacroLibrary};
+pub use math_ast::{parse_math, MathNode};
+pub use math_text::math_to_unicode;
+pub use options::ParseOptions;
+pub use outside::schema::{
+    BibliographyItem, DiagnosticEntry, DiagnosticSeverity, DocumentMetadata, EntryKey,
+    EnvironmentDefinition, MacroDefinition, MetadataText, NotationEntry, OutlineItem, ParseResult,
+    ParseResultError, ParseResultOk, TheoremDefinition, WorkspaceDocument, WorkspaceLabel,
+    WorkspaceResult,
+};
+pub use outside::{
+    parse_paragraphs_to_json, parse_paragraphs_to_json_with, parse_paragraphs_to_plain_text,
+    parse_paragraphs_to_plain_text_with, parse_theorem_graph, parse_workspace_to_json, ParseError,
+};
+pub use preamble::{Package, Preamble};
+pub use theorem_graph::{GraphEdge, GraphNode, TheoremGraph};
+pub use workspace::Workspace;
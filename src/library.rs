@@ -0,0 +1,215 @@
+use crate::macros::{Definition, EnvDef, MacroDef, Macros, TheoremDef};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// 文書より先に読み込んでおく命令や環境の定義
+///
+/// `ParseOptions::macro_library` に渡すと, その後のすべての解析でこの定義が使われる.
+/// 文書の中で同じ名前が定義されていれば文書のほうが優先される.
+#[derive(Debug, Clone, Default)]
+pub struct MacroLibrary {
+    macros: Macros,
+}
+
+impl MacroLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `\newcommand`, `\DeclareMathOperator`, `\newtheorem` などを並べた `.sty` や `.tex` から読む
+    ///
+    /// 定義以外の部分は読み飛ばす.
+    pub fn from_tex(source: &str) -> Self {
+        let mut library = Self::new();
+        library.add_tex(source);
+        library
+    }
+
+    /// JSON から読む
+    ///
+    /// `macros` は KaTeX の `macros` と同じ名前から本体への対応で,
+    /// 本体の代わりに `{"args": 1, "default": "x", "body": "..."}` も書ける.
+    /// 引数の個数を省いたときは本体の `#n` から決める.
+    pub fn from_json(source: &str) -> Result<Self, LibraryError> {
+        let mut library = Self::new();
+        library.add_json(source)?;
+        Ok(library)
+    }
+
+    /// ファイルから読む. 拡張子が `.json` であれば JSON, それ以外は TeX として読む
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LibraryError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+        if path.extension().is_some_and(|x| x == "json") {
+            Self::from_json(&source)
+        } else {
+            Ok(Self::from_tex(&source))
+        }
+    }
+
+    /// TeX の定義を加える. 同じ名前は後から加えたものが優先される
+    pub fn add_tex(&mut self, source: &str) {
+        self.macros.scan(source);
+    }
+
+    /// JSON の定義を加える. 同じ名前は後から加えたものが優先される
+    pub fn add_json(&mut self, source: &str) -> Result<(), LibraryError> {
+        let library: LibraryJson = serde_json::from_str(source)?;
+
+        for (name, value) in library.macros {
+            let name = if name.starts_with('\\') {
+                name
+            } else {
+                format!("\\{}", name)
+            };
+            let (args, default, body) = match value {
+                MacroJson::Body(body) => (None, None, body),
+                MacroJson::Definition {
+                    args,
+                    default,
+                    body,
+                } => (args, default, body),
+            };
+            let args = args.unwrap_or_else(|| max_parameter(&body));
+            self.macros
+                .define(Definition::Macro(MacroDef::new(name, args, default, body)));
+        }
+        for x in library.environments {
+            self.macros.define(Definition::Environment(EnvDef::new(
+                x.name, x.args, x.default, x.begin, x.end,
+            )));
+        }
+        for x in library.theorems {
+            self.macros.define(Definition::Theorem(TheoremDef::new(
+                x.name, x.title, x.numbered, x.counter, x.within,
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.macros.is_empty()
+    }
+
+    pub(crate) fn macros(&self) -> &Macros {
+        &self.macros
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum LibraryError {
+    #[error("Failed to read the library: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid library JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Deserialize)]
+struct LibraryJson {
+    #[serde(default)]
+    macros: BTreeMap<String, MacroJson>,
+    #[serde(default)]
+    environments: Vec<EnvironmentJson>,
+    #[serde(default)]
+    theorems: Vec<TheoremJson>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MacroJson {
+    Body(String),
+    Definition {
+        args: Option<usize>,
+        default: Option<String>,
+        body: String,
+    },
+}
+
+#[derive(Deserialize)]
+struct EnvironmentJson {
+    name: String,
+    #[serde(default)]
+    args: usize,
+    default: Option<String>,
+    #[serde(default)]
+    begin: String,
+    #[serde(default)]
+    end: String,
+}
+
+#[derive(Deserialize)]
+struct TheoremJson {
+    name: String,
+    title: String,
+    #[serde(default = "default_numbered")]
+    numbered: bool,
+    counter: Option<String>,
+    within: Option<String>,
+}
+
+fn default_numbered() -> bool {
+    true
+}
+
+/// 本体に現れる `#n` の最大の n
+fn max_parameter(body: &str) -> usize {
+    let chars: Vec<char> = body.chars().collect();
+    chars
+        .windows(2)
+        .filter(|w| w[0] == '#')
+        .filter_map(|w| w[1].to_digit(10))
+        .max()
+        .unwrap_or(0) as usize
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tex_から読む() {
+        let library = MacroLibrary::from_tex(
+            r"\ProvidesPackage{notation}
+            \newcommand{\R}{\mathbb{R}}
+            \DeclareMathOperator{\Hom}{Hom}
+            \newtheorem{thm}{Theorem}[section]",
+        );
+        let macros = library.macros();
+        assert_eq!(macros.get(r"\R").unwrap().body(), r"\mathbb{R}");
+        assert_eq!(macros.get(r"\Hom").unwrap().body(), r"\operatorname{Hom}");
+        assert_eq!(macros.theorem("thm").unwrap().within(), Some("section"));
+    }
+
+    #[test]
+    fn json_から読む() {
+        let library = MacroLibrary::from_json(
+            r##"{
+                "macros": {
+                    "\\R": "\\mathbb{R}",
+                    "norm": "\\left\\| #1 \\right\\|",
+                    "\\vec": {"args": 1, "default": "x", "body": "\\mathbf{#1}"}
+                },
+                "environments": [{"name": "proofsketch", "begin": "\\textit{Sketch.}"}],
+                "theorems": [{"name": "lem", "title": "Lemma", "counter": "thm"}]
+            }"##,
+        )
+        .unwrap();
+        let macros = library.macros();
+        assert_eq!(macros.get(r"\R").unwrap().args(), 0);
+        assert_eq!(macros.get(r"\norm").unwrap().args(), 1);
+        assert_eq!(macros.get(r"\vec").unwrap().default(), Some("x"));
+        assert!(macros.environment("proofsketch").is_some());
+        let lem = macros.theorem("lem").unwrap();
+        assert!(lem.numbered());
+        assert_eq!(lem.counter(), Some("thm"));
+
+        assert!(matches!(
+            MacroLibrary::from_json(r#"{"macros": []}"#),
+            Err(LibraryError::Json(_))
+        ));
+    }
+}
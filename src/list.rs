@@ -0,0 +1,250 @@
+use crate::key::Key;
+use crate::tex_char::TexChar;
+use crate::tex_chars::TexChars;
+use std::hash::{Hash, Hasher};
+
+pub(super) fn is_list_env(name: &str) -> bool {
+    matches!(name, "itemize" | "enumerate" | "description")
+}
+
+/// 箇条書き
+#[derive(Debug, Hash)]
+pub(super) struct ListInfo {
+    env: String,
+    items: Vec<ItemInfo>,
+}
+
+impl ListInfo {
+    pub(crate) fn new(env: String, items: Vec<ItemInfo>) -> Self {
+        Self { env, items }
+    }
+
+    pub(crate) fn env(&self) -> &str {
+        &self.env
+    }
+
+    pub(crate) fn items(&self) -> &[ItemInfo] {
+        &self.items
+    }
+
+    /// 全項目の中身の Key
+    pub(crate) fn content_keys(&self) -> impl Iterator<Item = &Key> {
+        self.items.iter().flat_map(|x| x.content.iter())
+    }
+
+    pub(crate) fn retain_keys(&mut self, f: &mut impl FnMut(&Key) -> bool) {
+        for item in &mut self.items {
+            item.content.retain(|k| f(k));
+        }
+    }
+}
+
+/// 箇条書きの一項目
+#[derive(Debug)]
+pub(super) struct ItemInfo {
+    /// 表示される記号. `\item[..]` があればその中身
+    marker: String,
+    /// `\ref` で参照されるときの番号. `enumerate` の番号付きの項目にだけある
+    number: Option<String>,
+    content: Vec<Key>,
+}
+
+// 中身の Key は出現位置で変わるので, 項目の区切りが分かるよう個数だけを含める
+impl Hash for ItemInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.marker.hash(state);
+        self.number.hash(state);
+        self.content.len().hash(state);
+    }
+}
+
+impl ItemInfo {
+    pub(crate) fn new(marker: String, number: Option<String>, content: Vec<Key>) -> Self {
+        Self {
+            marker,
+            number,
+            content,
+        }
+    }
+
+    pub(crate) fn marker(&self) -> &str {
+        &self.marker
+    }
+
+    pub(crate) fn number(&self) -> Option<&str> {
+        self.number.as_deref()
+    }
+
+    pub(crate) fn content(&self) -> &[Key] {
+        &self.content
+    }
+}
+
+/// 環境の中身を `\item` ごとに分け, `\item[..]` の中身と項目の中身を返す
+///
+/// 入れ子の環境や波括弧の中の `\item` では分けない. 最初の `\item` より前は捨てる.
+pub(super) fn split_items(mut body: TexChars) -> Vec<(Option<String>, String)> {
+    let mut items: Vec<(Option<String>, String)> = Vec::new();
+
+    loop {
+        if body.peek_command_name().as_deref() == Some("item") {
+            body.consume_command_name();
+            let label = body.read_optional().map(|x| x.into_content_string());
+            items.push((label, String::new()));
+            continue;
+        }
+
+        let text = if let Some(name) = body.peek_begin_env() {
+            body.consume_begin_env();
+            let inner = body.read_env_body(&name).into_raw_string();
+            format!("\\begin{{{}}}{}\\end{{{}}}", name, inner, name)
+        } else if body.next_is(TexChar::LBrace) {
+            format!("{{{}}}", body.read_group().unwrap().into_raw_string())
+        } else if let Some(name) = body.consume_command_name() {
+            // `\itemsep` などを `\item` と読まないよう, 制御語は一度に読む
+            format!("\\{}", name)
+        } else if body.next_is(TexChar::Backslash) {
+            // `\\` や `\{` を一つの字句として読む
+            let mut s = body.next().unwrap().to_string();
+            s.extend(body.next().map(|x| x.to_string()));
+            s
+        } else {
+            match body.next() {
+                Some(c) => c.to_string(),
+                None => break,
+            }
+        };
+
+        if let Some((_, content)) = items.last_mut() {
+            content.push_str(&text);
+        }
+    }
+
+    items
+}
+
+/// `enumerate` の深さ (0 始まり) ごとの番号の書式
+pub(super) fn enumerate_value(depth: usize, n: usize) -> String {
+    match depth {
+        0 => n.to_string(),
+        1 => alph(n, 'a'),
+        2 => roman(n),
+        _ => alph(n, 'A'),
+    }
+}
+
+/// `enumerate` の項目の記号
+pub(super) fn enumerate_marker(depth: usize, value: &str) -> String {
+    match depth {
+        1 => format!("({})", value),
+        _ => format!("{}.", value),
+    }
+}
+
+/// `enumerate` の項目を `\ref` したときの番号
+///
+/// `values` は外側から順に各深さの現在の番号. LaTeX の `\p@enumii` などと同じく,
+/// 2段目は `1a`, 3段目は `1(a)i`, 4段目は `1(a)iA` となる.
+pub(super) fn enumerate_number(values: &[String]) -> String {
+    match values {
+        [] => String::new(),
+        [a] => a.clone(),
+        [a, b] => format!("{}{}", a, b),
+        [a, b, rest @ ..] => format!("{}({}){}", a, b, rest.concat()),
+    }
+}
+
+/// `itemize` の深さごとの記号
+pub(super) fn itemize_marker(depth: usize) -> String {
+    ["•", "–", "∗", "·"][depth.min(3)].to_string()
+}
+
+fn alph(n: usize, base: char) -> String {
+    match n {
+        1..=26 => char::from_u32(base as u32 + n as u32 - 1)
+            .unwrap()
+            .to_string(),
+        _ => n.to_string(),
+    }
+}
+
+fn roman(mut n: usize) -> String {
+    const TABLE: [(usize, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+
+    let mut s = String::new();
+    for (value, digits) in TABLE {
+        while n >= value {
+            s.push_str(digits);
+            n -= value;
+        }
+    }
+    s
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn split(input: &str) -> Vec<(Option<String>, String)> {
+        split_items(TexChars::from_str(input).unwrap())
+            .into_iter()
+            .map(|(label, content)| (label, content.trim().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn 項目に分ける() {
+        assert_eq!(
+            split(r"\item a \item[(i)] b {\item} \itemsep"),
+            vec![
+                (None, "a".to_string()),
+                (Some("(i)".to_string()), r"b {\item} \itemsep".to_string())
+            ]
+        );
+        assert_eq!(
+            split(r"\item a \begin{enumerate} \item b \end{enumerate} \item c"),
+            vec![
+                (
+                    None,
+                    r"a \begin{enumerate} \item b \end{enumerate}".to_string()
+                ),
+                (None, "c".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn enumerate_の番号() {
+        assert_eq!(enumerate_value(0, 3), "3");
+        assert_eq!(enumerate_value(1, 2), "b");
+        assert_eq!(enumerate_value(2, 14), "xiv");
+        assert_eq!(enumerate_value(3, 1), "A");
+        assert_eq!(enumerate_marker(1, "b"), "(b)");
+
+        let values: Vec<String> = ["2", "b", "iv", "A"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(enumerate_number(&values[..1]), "2");
+        assert_eq!(enumerate_number(&values[..2]), "2b");
+        assert_eq!(enumerate_number(&values[..3]), "2(b)iv");
+        assert_eq!(enumerate_number(&values), "2(b)ivA");
+    }
+}
@@ -0,0 +1,623 @@
+use crate::key::Key;
+use crate::node::Node;
+use crate::notation;
+use crate::result_map::ResultMap;
+use crate::tex_char::TexChar;
+use crate::tex_chars::TexChars;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+/// `\newcommand` などによる命令の定義
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub(super) struct MacroDef {
+    /// `\R` のように `\` を含む名前
+    name: String,
+    args: usize,
+    /// 省略可能な第1引数の既定値
+    default: Option<String>,
+    body: String,
+    /// 既に定義されていれば上書きしない (`\providecommand`)
+    provide: bool,
+}
+
+impl MacroDef {
+    pub(crate) fn new(name: String, args: usize, default: Option<String>, body: String) -> Self {
+        Self {
+            name,
+            args,
+            default,
+            body,
+            provide: false,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn args(&self) -> usize {
+        self.args
+    }
+
+    pub(crate) fn default(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+
+    pub(crate) fn body(&self) -> &str {
+        &self.body
+    }
+}
+
+/// `\newenvironment` による環境の定義
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub(super) struct EnvDef {
+    name: String,
+    args: usize,
+    default: Option<String>,
+    begin: String,
+    end: String,
+}
+
+impl EnvDef {
+    pub(crate) fn new(
+        name: String,
+        args: usize,
+        default: Option<String>,
+        begin: String,
+        end: String,
+    ) -> Self {
+        Self {
+            name,
+            args,
+            default,
+            begin,
+            end,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn args(&self) -> usize {
+        self.args
+    }
+
+    pub(crate) fn default(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+
+    pub(crate) fn begin(&self) -> &str {
+        &self.begin
+    }
+
+    pub(crate) fn end(&self) -> &str {
+        &self.end
+    }
+}
+
+/// `\newtheorem` による定理環境の定義
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub(super) struct TheoremDef {
+    name: String,
+    title: String,
+    numbered: bool,
+    /// `\newtheorem{lem}[thm]{Lemma}` のように番号を共有する環境
+    counter: Option<String>,
+    /// `\newtheorem{thm}{Theorem}[section]` のように番号を振り直す単位
+    within: Option<String>,
+}
+
+impl TheoremDef {
+    pub(crate) fn new(
+        name: String,
+        title: String,
+        numbered: bool,
+        counter: Option<String>,
+        within: Option<String>,
+    ) -> Self {
+        Self {
+            name,
+            title,
+            numbered,
+            counter,
+            within,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub(crate) fn numbered(&self) -> bool {
+        self.numbered
+    }
+
+    pub(crate) fn counter(&self) -> Option<&str> {
+        self.counter.as_deref()
+    }
+
+    pub(crate) fn within(&self) -> Option<&str> {
+        self.within.as_deref()
+    }
+}
+
+/// 文書で定義された環境の出現
+#[derive(Debug)]
+pub(super) struct EnvironmentInfo {
+    name: String,
+    args: Vec<String>,
+    content: Vec<Key>,
+    /// 定理環境の番号
+    number: Option<String>,
+}
+
+// 中身の Key は出現位置で変わるので含めない. 中身は ResultMap で子の値から求める
+impl Hash for EnvironmentInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.args.hash(state);
+        self.number.hash(state);
+    }
+}
+
+impl EnvironmentInfo {
+    pub(crate) fn new(name: String, args: Vec<String>, content: Vec<Key>) -> Self {
+        Self {
+            name,
+            args,
+            content,
+            number: None,
+        }
+    }
+
+    pub(crate) fn set_number(&mut self, number: Option<String>) {
+        self.number = number;
+    }
+
+    pub(crate) fn number(&self) -> Option<&str> {
+        self.number.as_deref()
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    pub(crate) fn content(&self) -> &[Key] {
+        &self.content
+    }
+
+    pub(crate) fn retain_keys(&mut self, f: &mut impl FnMut(&Key) -> bool) {
+        self.content.retain(|k| f(k));
+    }
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub(super) enum Definition {
+    Macro(MacroDef),
+    Environment(EnvDef),
+    Theorem(TheoremDef),
+}
+
+impl Definition {
+    /// 命令は `\R` のように `\` を含み, 環境は `claim` のように含まない
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            Definition::Macro(x) => &x.name,
+            Definition::Environment(x) => &x.name,
+            Definition::Theorem(x) => &x.name,
+        }
+    }
+
+    /// 定義の中で使われている制御語
+    fn used_names(&self) -> Vec<String> {
+        let source = match self {
+            Definition::Macro(x) => x.body.clone(),
+            Definition::Environment(x) => format!("{}{}", x.begin, x.end),
+            Definition::Theorem(_) => String::new(),
+        };
+        notation::control_words(&source)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+}
+
+pub(super) fn is_definition_command(name: &str) -> bool {
+    matches!(
+        name,
+        "newcommand"
+            | "renewcommand"
+            | "providecommand"
+            | "DeclareRobustCommand"
+            | "def"
+            | "gdef"
+            | "DeclareMathOperator"
+            | "newenvironment"
+            | "renewenvironment"
+            | "newtheorem"
+    )
+}
+
+/// 命令や環境の定義を読む
+///
+/// 読めなければ何も消費せずに None を返す. `\def` は引数が `#1#2` の形のものだけを読む.
+pub(super) fn read_definition(cs: &mut TexChars) -> Option<Definition> {
+    let before = cs.clone();
+    let def = read_definition_inner(cs);
+    if def.is_none() {
+        *cs = before;
+    }
+    def
+}
+
+fn read_definition_inner(cs: &mut TexChars) -> Option<Definition> {
+    let command = cs.consume_command_name()?;
+    let star = cs.consume_star();
+    cs.skip_whitespaces();
+
+    match command.as_str() {
+        "def" | "gdef" => read_def(cs).map(Definition::Macro),
+        "DeclareMathOperator" => {
+            let name = read_name(cs)?;
+            let body = cs.read_group()?.into_raw_string();
+            let star = if star { "*" } else { "" };
+            Some(Definition::Macro(MacroDef {
+                name,
+                args: 0,
+                default: None,
+                body: format!("\\operatorname{}{{{}}}", star, body),
+                provide: false,
+            }))
+        }
+        "newtheorem" => {
+            let name = cs.read_group()?.into_raw_string().trim().to_string();
+            let counter = cs
+                .read_optional()
+                .map(|x| x.into_raw_string().trim().to_string());
+            let title = cs.read_group()?.into_content_string();
+            let within = match counter {
+                Some(_) => None,
+                None => cs
+                    .read_optional()
+                    .map(|x| x.into_raw_string().trim().to_string()),
+            };
+            Some(Definition::Theorem(TheoremDef {
+                name,
+                title,
+                numbered: !star,
+                counter,
+                within,
+            }))
+        }
+        "newenvironment" | "renewenvironment" => {
+            let name = cs.read_group()?.into_raw_string().trim().to_string();
+            let (args, default) = read_arity(cs)?;
+            let begin = cs.read_group()?.into_raw_string();
+            let end = cs.read_group()?.into_raw_string();
+            Some(Definition::Environment(EnvDef {
+                name,
+                args,
+                default,
+                begin,
+                end,
+            }))
+        }
+        _ => {
+            let name = read_name(cs)?;
+            let (args, default) = read_arity(cs)?;
+            let body = cs.read_group()?.into_raw_string();
+            Some(Definition::Macro(MacroDef {
+                name,
+                args,
+                default,
+                body,
+                provide: command == "providecommand",
+            }))
+        }
+    }
+}
+
+/// `{\foo}` または `\foo` を読む
+fn read_name(cs: &mut TexChars) -> Option<String> {
+    let name = match cs.read_group() {
+        Some(group) => group.into_raw_string().trim().to_string(),
+        None => format!("\\{}", cs.consume_command_name()?),
+    };
+    name.starts_with('\\').then_some(name)
+}
+
+/// `[2][x]` のような引数の個数と省略可能な第1引数の既定値を読む
+fn read_arity(cs: &mut TexChars) -> Option<(usize, Option<String>)> {
+    let args = match cs.read_optional() {
+        Some(x) => x.into_raw_string().trim().parse().ok()?,
+        None => 0,
+    };
+    let default = cs.read_optional().map(|x| x.into_raw_string());
+    Some((args, default))
+}
+
+fn read_def(cs: &mut TexChars) -> Option<MacroDef> {
+    let name = format!("\\{}", cs.consume_command_name()?);
+    let mut params = String::new();
+    while !cs.next_is(TexChar::LBrace) {
+        params.push_str(&cs.next()?.to_string());
+    }
+    let args = params.trim().len() / 2;
+    let expected: String = (1..=args).map(|i| format!("#{}", i)).collect();
+    if params.trim() != expected {
+        return None;
+    }
+    let body = cs.read_group()?.into_raw_string();
+
+    Some(MacroDef {
+        name,
+        args,
+        default: None,
+        body,
+        provide: false,
+    })
+}
+
+/// 文書中で定義された命令と環境
+#[derive(Debug, Clone, Default)]
+pub(super) struct Macros {
+    defs: BTreeMap<String, MacroDef>,
+    envs: BTreeMap<String, EnvDef>,
+    theorems: BTreeMap<String, TheoremDef>,
+}
+
+impl Macros {
+    pub(crate) fn define(&mut self, def: Definition) {
+        match def {
+            Definition::Macro(def) => {
+                if def.provide && self.defs.contains_key(&def.name) {
+                    return;
+                }
+                self.defs.insert(def.name.clone(), def);
+            }
+            Definition::Environment(def) => {
+                self.envs.insert(def.name.clone(), def);
+            }
+            Definition::Theorem(def) => {
+                self.theorems.insert(def.name.clone(), def);
+            }
+        }
+    }
+
+    /// 定義命令のソースから読む
+    pub(crate) fn define_source(&mut self, source: &str) {
+        let mut cs = TexChars::from_str(source).unwrap();
+        if let Some(def) = read_definition(&mut cs) {
+            self.define(def);
+        }
+    }
+
+    /// 文書全体から定義を探して加える
+    ///
+    /// 環境は段落を読む前に知っておく必要があるので, 先に集めておく.
+    pub(crate) fn scan(&mut self, input: &str) {
+        let mut cs = TexChars::from_str(input).unwrap();
+        loop {
+            match cs.peek_command_name() {
+                Some(name) if is_definition_command(&name) => {
+                    if let Some(def) = read_definition(&mut cs) {
+                        self.define(def);
+                        continue;
+                    }
+                    cs.consume_command_name();
+                }
+                Some(_) => {
+                    cs.consume_command_name();
+                }
+                None => {
+                    if cs.next().is_none() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn environment(&self, name: &str) -> Option<&EnvDef> {
+        self.envs.get(name)
+    }
+
+    pub(crate) fn environments(&self) -> impl Iterator<Item = &EnvDef> {
+        self.envs.values()
+    }
+
+    pub(crate) fn theorem(&self, name: &str) -> Option<&TheoremDef> {
+        self.theorems.get(name)
+    }
+
+    pub(crate) fn theorems(&self) -> impl Iterator<Item = &TheoremDef> {
+        self.theorems.values()
+    }
+
+    /// 段落と同じく中身を読む環境か
+    pub(crate) fn is_block_env(&self, name: &str) -> bool {
+        self.envs.contains_key(name) || self.theorems.contains_key(name)
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&MacroDef> {
+        self.defs.get(name)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.defs.is_empty() && self.envs.is_empty() && self.theorems.is_empty()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &MacroDef> {
+        self.defs.values()
+    }
+}
+
+/// 命令や環境の定義を本文から取り除き, 集める
+///
+/// 後の定義が前の定義を上書きする.
+pub(super) fn collect(rmap: &mut ResultMap, macros: &mut Macros) {
+    for (_, node) in rmap.detach(|node| matches!(node, Node::Definition(_))) {
+        if let Node::Definition(def) = node {
+            macros.define(def);
+        }
+    }
+}
+
+/// Key ごとの, 使っている命令や環境の定義 (名前順)
+pub(super) type Dependencies = HashMap<Key, Vec<Definition>>;
+
+/// 数式, 命令, 環境が使っている定義を集める
+///
+/// 定義の中で使われている命令もたどる. 定義を変えたときに Key が変わるよう, ハッシュに含めるために使う.
+pub(super) fn dependencies(rmap: &ResultMap, macros: &Macros) -> Dependencies {
+    let mut dependencies = Dependencies::new();
+
+    for (key, node) in rmap.iter() {
+        let names = match node {
+            Node::MathExpr(me) => notation::control_words(me.content_str())
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect(),
+            Node::InlineCommand(Some(s)) => vec![s.trim().to_string()],
+            Node::Environment(info) => vec![info.name().to_string()],
+            _ => continue,
+        };
+
+        let mut found: BTreeMap<String, Definition> = BTreeMap::new();
+        let mut pending = names;
+        while let Some(name) = pending.pop() {
+            if found.contains_key(&name) {
+                continue;
+            }
+            let def = if let Some(x) = macros.get(&name) {
+                Definition::Macro(x.clone())
+            } else if let Some(x) = macros.environment(&name) {
+                Definition::Environment(x.clone())
+            } else if let Some(x) = macros.theorem(&name) {
+                Definition::Theorem(x.clone())
+            } else {
+                continue;
+            };
+            pending.extend(def.used_names());
+            found.insert(name, def);
+        }
+
+        if !found.is_empty() {
+            dependencies.insert(key.clone(), found.into_values().collect());
+        }
+    }
+
+    dependencies
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(input: &str) -> Option<(String, usize, Option<String>, String)> {
+        let mut cs = TexChars::from_str(input).unwrap();
+        match read_definition(&mut cs)? {
+            Definition::Macro(x) => Some((x.name, x.args, x.default, x.body)),
+            Definition::Environment(x) => Some((x.name, x.args, x.default, x.begin + &x.end)),
+            Definition::Theorem(x) => Some((x.name, 0, x.counter.or(x.within), x.title)),
+        }
+    }
+
+    #[test]
+    fn newcommand() {
+        assert_eq!(
+            read(r"\newcommand{\R}{\mathbb{R}}"),
+            Some((r"\R".into(), 0, None, r"\mathbb{R}".into()))
+        );
+        assert_eq!(
+            read(r"\renewcommand*\pair[2][x]{(#1,#2)}"),
+            Some((r"\pair".into(), 2, Some("x".into()), "(#1,#2)".into()))
+        );
+        assert_eq!(read(r"\newcommand{R}{x}"), None);
+    }
+
+    #[test]
+    fn def() {
+        assert_eq!(
+            read(r"\def\swap#1#2{#2#1}"),
+            Some((r"\swap".into(), 2, None, "#2#1".into()))
+        );
+        // 区切り付きの引数は扱わない
+        let mut cs = TexChars::from_str(r"\def\foo#1.{#1}").unwrap();
+        assert!(read_definition(&mut cs).is_none());
+        assert_eq!(cs.into_content_string(), r"\def\foo#1.{#1}");
+    }
+
+    #[test]
+    fn providecommand_は上書きしない() {
+        let mut macros = Macros::default();
+        macros.define_source(r"\newcommand{\R}{\mathbb{R}}");
+        macros.define_source(r"\providecommand{\R}{R}");
+        macros.define_source(r"\providecommand{\C}{\mathbb{C}}");
+        let bodies: Vec<_> = macros.iter().map(|x| x.body()).collect();
+        assert_eq!(bodies, vec![r"\mathbb{C}", r"\mathbb{R}"]);
+    }
+
+    #[test]
+    fn declare_math_operator() {
+        assert_eq!(
+            read(r"\DeclareMathOperator{\Hom}{Hom}"),
+            Some((r"\Hom".into(), 0, None, r"\operatorname{Hom}".into()))
+        );
+        assert_eq!(
+            read(r"\DeclareMathOperator*{\argmax}{arg\,max}"),
+            Some((
+                r"\argmax".into(),
+                0,
+                None,
+                r"\operatorname*{arg\,max}".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn newenvironment() {
+        assert_eq!(
+            read(r"\newenvironment{claim}[1][]{\textbf{Claim #1.}}{\qed}"),
+            Some((
+                "claim".into(),
+                1,
+                Some("".into()),
+                r"\textbf{Claim #1.}\qed".into()
+            ))
+        );
+        let mut macros = Macros::default();
+        macros.scan(r"本文 \newenvironment{a}{x}{y} $\renewenvironment{b}{}{}$");
+        let names: Vec<_> = macros.environments().map(|x| x.name()).collect();
+        assert_eq!(names, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn newtheorem() {
+        assert_eq!(
+            read(r"\newtheorem{thm}{Theorem}[section]"),
+            Some(("thm".into(), 0, Some("section".into()), "Theorem".into()))
+        );
+        assert_eq!(
+            read(r"\newtheorem{lem}[thm]{補題}"),
+            Some(("lem".into(), 0, Some("thm".into()), "補題".into()))
+        );
+        let mut cs = TexChars::from_str(r"\newtheorem*{rem}{Remark}").unwrap();
+        let Some(Definition::Theorem(def)) = read_definition(&mut cs) else {
+            panic!()
+        };
+        assert!(!def.numbered());
+    }
+}
@@ -0,0 +1,654 @@
+use serde::Serialize;
+
+/// 数式の構文木のノード
+///
+/// 制御綴の名前 `name` は先頭の `\` を含まない.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MathNode {
+    Symbol {
+        value: String,
+    },
+    Number {
+        value: String,
+    },
+    ControlSequence {
+        name: String,
+    },
+    Group {
+        body: Vec<MathNode>,
+    },
+    Scripts {
+        base: Option<Box<MathNode>>,
+        sub: Option<Box<MathNode>>,
+        sup: Option<Box<MathNode>>,
+    },
+    /// `\frac{a}{b}` や `\sqrt[n]{x}` のように引数を取る命令
+    Command {
+        name: String,
+        optional: Option<Vec<MathNode>>,
+        args: Vec<Vec<MathNode>>,
+    },
+    /// `\text{...}` などの中身は数式ではないので文字列のまま持つ
+    Text {
+        name: String,
+        value: String,
+    },
+    Delimited {
+        left: String,
+        right: String,
+        body: Vec<MathNode>,
+    },
+    Environment {
+        name: String,
+        args: Vec<String>,
+        rows: Vec<Vec<Vec<MathNode>>>,
+    },
+    /// `&` と `\\` で区切られた行とセル
+    Align {
+        rows: Vec<Vec<Vec<MathNode>>>,
+    },
+}
+
+/// 引数を取る命令の (省略可能引数の有無, 必須引数の個数)
+pub(super) fn command_arity(name: &str) -> Option<(bool, usize)> {
+    let arity = match name {
+        "frac" | "dfrac" | "tfrac" | "cfrac" | "binom" | "dbinom" | "tbinom" | "stackrel"
+        | "overset" | "underset" | "textcolor" => (false, 2),
+        "sqrt" | "xrightarrow" | "xleftarrow" => (true, 1),
+        "mathbf" | "mathrm" | "mathit" | "mathsf" | "mathtt" | "mathbb" | "mathcal" | "mathscr"
+        | "mathfrak" | "boldsymbol" | "bm" | "pmb" | "operatorname" | "operatorname*" | "hat"
+        | "widehat" | "check" | "breve" | "acute" | "grave" | "bar" | "overline" | "underline"
+        | "tilde" | "widetilde" | "vec" | "overrightarrow" | "overleftarrow" | "dot" | "ddot"
+        | "dddot" | "mathring" | "overbrace" | "underbrace" | "pmod" | "cancel" | "boxed"
+        | "phantom" => (false, 1),
+        _ => return None,
+    };
+
+    Some(arity)
+}
+
+pub(super) fn is_text_command(name: &str) -> bool {
+    matches!(
+        name,
+        "text"
+            | "textrm"
+            | "textbf"
+            | "textit"
+            | "textsf"
+            | "texttt"
+            | "textnormal"
+            | "mbox"
+            | "hbox"
+            | "intertext"
+    )
+}
+
+fn has_spec_arg(env: &str) -> bool {
+    matches!(
+        env,
+        "array" | "alignat" | "alignat*" | "alignedat" | "subarray"
+    )
+}
+
+/// 数式を構文木に変換する
+///
+/// 括弧の対応が取れていない場合も, 読めるところまで読んで木を返す.
+pub fn parse_math(content: &str) -> Vec<MathNode> {
+    let mut parser = MathParser::new(content);
+    let rows = parser.parse_rows(&Stop::Eof);
+    into_body(rows)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    ControlSequence(String),
+    Char(char),
+    BeginGroup,
+    EndGroup,
+    Sup,
+    Sub,
+    CellSep,
+    RowSep,
+}
+
+enum Stop {
+    Eof,
+    EndGroup,
+    Right,
+    End(String),
+}
+
+struct MathParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl MathParser {
+    fn new(content: &str) -> Self {
+        Self {
+            chars: content.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn skip_whitespaces(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek_token(&mut self) -> Option<Token> {
+        let pos = self.pos;
+        let token = self.next_token();
+        self.pos = pos;
+        token
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        self.skip_whitespaces();
+
+        let c = *self.chars.get(self.pos)?;
+        self.pos += 1;
+
+        let token = match c {
+            '{' => Token::BeginGroup,
+            '}' => Token::EndGroup,
+            '^' => Token::Sup,
+            '_' => Token::Sub,
+            '&' => Token::CellSep,
+            '\\' => {
+                let name: String = self.chars[self.pos..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphabetic())
+                    .collect();
+                if !name.is_empty() {
+                    self.pos += name.len();
+                    if self.chars.get(self.pos) == Some(&'*') && name == "operatorname" {
+                        self.pos += 1;
+                        Token::ControlSequence(format!("{}*", name))
+                    } else {
+                        Token::ControlSequence(name)
+                    }
+                } else {
+                    match self.chars.get(self.pos) {
+                        Some('\\') => {
+                            self.pos += 1;
+                            Token::RowSep
+                        }
+                        Some(c) => {
+                            self.pos += 1;
+                            Token::ControlSequence(c.to_string())
+                        }
+                        None => Token::ControlSequence(String::new()),
+                    }
+                }
+            }
+            c => Token::Char(c),
+        };
+
+        Some(token)
+    }
+
+    /// `{...}` の中身を文字列のまま読む
+    fn read_raw_group(&mut self) -> String {
+        self.skip_whitespaces();
+        if self.chars.get(self.pos) != Some(&'{') {
+            return String::new();
+        }
+        self.pos += 1;
+
+        let mut depth = 0;
+        let mut raw = String::new();
+        while let Some(&c) = self.chars.get(self.pos) {
+            self.pos += 1;
+            match c {
+                '\\' => {
+                    raw.push(c);
+                    if let Some(&c) = self.chars.get(self.pos) {
+                        self.pos += 1;
+                        raw.push(c);
+                    }
+                    continue;
+                }
+                '{' => depth += 1,
+                '}' if depth == 0 => break,
+                '}' => depth -= 1,
+                _ => {}
+            }
+            raw.push(c);
+        }
+
+        raw
+    }
+
+    fn is_stop(&mut self, stop: &Stop) -> bool {
+        match (stop, self.peek_token()) {
+            (_, None) => true,
+            (Stop::EndGroup, Some(Token::EndGroup)) => true,
+            (Stop::Right, Some(Token::ControlSequence(name))) => name == "right",
+            (Stop::End(env), Some(Token::ControlSequence(name))) if name == "end" => {
+                let pos = self.pos;
+                self.next_token();
+                let found = self.read_raw_group();
+                self.pos = pos;
+                &found == env
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_rows(&mut self, stop: &Stop) -> Vec<Vec<Vec<MathNode>>> {
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        let mut cell = Vec::new();
+
+        while !self.is_stop(stop) {
+            match self.peek_token() {
+                Some(Token::CellSep) => {
+                    self.next_token();
+                    row.push(std::mem::take(&mut cell));
+                }
+                Some(Token::RowSep) => {
+                    self.next_token();
+                    // 行間の調整 `\\[2pt]` は読み飛ばす
+                    self.skip_optional_raw();
+                    row.push(std::mem::take(&mut cell));
+                    rows.push(std::mem::take(&mut row));
+                }
+                Some(Token::EndGroup) => {
+                    // 対応する `{` のない `}` は読み飛ばす
+                    self.next_token();
+                }
+                _ => {
+                    if let Some(node) = self.parse_scripted() {
+                        cell.push(node);
+                    }
+                }
+            }
+        }
+
+        if !cell.is_empty() || !row.is_empty() || rows.is_empty() {
+            row.push(cell);
+            rows.push(row);
+        }
+
+        rows
+    }
+
+    fn skip_optional_raw(&mut self) {
+        self.skip_whitespaces();
+        if self.chars.get(self.pos) == Some(&'[') {
+            while let Some(&c) = self.chars.get(self.pos) {
+                self.pos += 1;
+                if c == ']' {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn read_optional_math(&mut self) -> Option<Vec<MathNode>> {
+        self.skip_whitespaces();
+        if self.chars.get(self.pos) != Some(&'[') {
+            return None;
+        }
+        self.pos += 1;
+
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(&c) = self.chars.get(self.pos) {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                ']' if depth == 0 => break,
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        let inner: String = self.chars[start..self.pos].iter().collect();
+        self.pos = (self.pos + 1).min(self.chars.len());
+
+        Some(parse_math(&inner))
+    }
+
+    /// 添字を含めて1つの要素を読む
+    fn parse_scripted(&mut self) -> Option<MathNode> {
+        let base = match self.peek_token()? {
+            Token::Sup | Token::Sub => None,
+            _ => Some(self.parse_atom()?),
+        };
+
+        let mut sub = None;
+        let mut sup = None;
+        loop {
+            match self.peek_token() {
+                Some(Token::Sub) if sub.is_none() => {
+                    self.next_token();
+                    sub = self.parse_script_arg().map(Box::new);
+                }
+                Some(Token::Sup) if sup.is_none() => {
+                    self.next_token();
+                    sup = self.parse_script_arg().map(Box::new);
+                }
+                _ => break,
+            }
+        }
+
+        if sub.is_none() && sup.is_none() {
+            return base;
+        }
+
+        Some(MathNode::Scripts {
+            base: base.map(Box::new),
+            sub,
+            sup,
+        })
+    }
+
+    fn parse_script_arg(&mut self) -> Option<MathNode> {
+        match self.peek_token()? {
+            // `x^23` の添字は `2` のみ
+            Token::Char(c) if c.is_ascii_digit() => {
+                self.next_token();
+                Some(MathNode::Number {
+                    value: c.to_string(),
+                })
+            }
+            Token::Sup | Token::Sub | Token::CellSep | Token::RowSep | Token::EndGroup => None,
+            _ => self.parse_atom(),
+        }
+    }
+
+    /// 命令の引数 (1トークンまたは `{...}`) を読む
+    fn parse_arg(&mut self) -> Vec<MathNode> {
+        match self.peek_token() {
+            Some(Token::BeginGroup) => {
+                self.next_token();
+                let rows = self.parse_rows(&Stop::EndGroup);
+                self.next_token();
+                into_body(rows)
+            }
+            Some(Token::Char(c)) => {
+                self.next_token();
+                vec![char_node(c)]
+            }
+            Some(Token::ControlSequence(_)) => self.parse_atom().into_iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn parse_atom(&mut self) -> Option<MathNode> {
+        let node = match self.next_token()? {
+            Token::BeginGroup => {
+                let rows = self.parse_rows(&Stop::EndGroup);
+                self.next_token();
+                MathNode::Group {
+                    body: into_body(rows),
+                }
+            }
+            Token::Char(c) if c.is_ascii_digit() => {
+                let mut value = c.to_string();
+                while let Some(&c) = self.chars.get(self.pos) {
+                    let next_is_digit = self
+                        .chars
+                        .get(self.pos + 1)
+                        .is_some_and(|c| c.is_ascii_digit());
+                    if c.is_ascii_digit() || (c == '.' && next_is_digit) {
+                        value.push(c);
+                        self.pos += 1;
+                    } else {
+                        break;
+                    }
+                }
+                MathNode::Number { value }
+            }
+            Token::Char(c) => char_node(c),
+            Token::ControlSequence(name) => self.parse_control_sequence(name),
+            // 単独の区切り記号などは記号として扱う
+            Token::Sup => symbol("^"),
+            Token::Sub => symbol("_"),
+            Token::CellSep => symbol("&"),
+            Token::RowSep | Token::EndGroup => return None,
+        };
+
+        Some(node)
+    }
+
+    fn parse_control_sequence(&mut self, name: String) -> MathNode {
+        if name == "left" {
+            let left = self.read_delimiter();
+            let rows = self.parse_rows(&Stop::Right);
+            let right = if self.peek_token() == Some(Token::ControlSequence("right".to_string())) {
+                self.next_token();
+                self.read_delimiter()
+            } else {
+                String::new()
+            };
+
+            return MathNode::Delimited {
+                left,
+                right,
+                body: into_body(rows),
+            };
+        }
+
+        if name == "begin" {
+            let env = self.read_raw_group();
+            let args = if has_spec_arg(&env) {
+                vec![self.read_raw_group()]
+            } else {
+                Vec::new()
+            };
+            let rows = self.parse_rows(&Stop::End(env.clone()));
+            if self.is_stop(&Stop::End(env.clone())) && self.peek_token().is_some() {
+                self.next_token();
+                self.read_raw_group();
+            }
+
+            return MathNode::Environment {
+                name: env,
+                args,
+                rows,
+            };
+        }
+
+        if is_text_command(&name) {
+            let value = self.read_raw_group();
+            return MathNode::Text { name, value };
+        }
+
+        if let Some((has_optional, n)) = command_arity(&name) {
+            let optional = if has_optional {
+                self.read_optional_math()
+            } else {
+                None
+            };
+            let args = (0..n).map(|_| self.parse_arg()).collect();
+
+            return MathNode::Command {
+                name,
+                optional,
+                args,
+            };
+        }
+
+        MathNode::ControlSequence { name }
+    }
+
+    fn read_delimiter(&mut self) -> String {
+        match self.next_token() {
+            Some(Token::Char(c)) => c.to_string(),
+            Some(Token::ControlSequence(name)) => format!("\\{}", name),
+            _ => String::new(),
+        }
+    }
+}
+
+fn symbol(value: &str) -> MathNode {
+    MathNode::Symbol {
+        value: value.to_string(),
+    }
+}
+
+fn char_node(c: char) -> MathNode {
+    if c.is_ascii_digit() {
+        MathNode::Number {
+            value: c.to_string(),
+        }
+    } else {
+        MathNode::Symbol {
+            value: c.to_string(),
+        }
+    }
+}
+
+/// 区切りのない場合は中身をそのまま, ある場合は Align にまとめる
+fn into_body(mut rows: Vec<Vec<Vec<MathNode>>>) -> Vec<MathNode> {
+    if rows.len() == 1 && rows[0].len() == 1 {
+        rows.pop().unwrap().pop().unwrap()
+    } else {
+        vec![MathNode::Align { rows }]
+    }
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use MathNode::*;
+
+    fn sym(s: &str) -> MathNode {
+        symbol(s)
+    }
+
+    fn num(s: &str) -> MathNode {
+        Number {
+            value: s.to_string(),
+        }
+    }
+
+    fn cs(s: &str) -> MathNode {
+        ControlSequence {
+            name: s.to_string(),
+        }
+    }
+
+    #[test]
+    fn 記号と数() {
+        assert_eq!(
+            parse_math(r"x + 12.5 \alpha"),
+            vec![sym("x"), sym("+"), num("12.5"), cs("alpha")]
+        );
+    }
+
+    #[test]
+    fn 添字() {
+        assert_eq!(
+            parse_math(r"\alpha_{i}^23"),
+            vec![
+                Scripts {
+                    base: Some(Box::new(cs("alpha"))),
+                    sub: Some(Box::new(Group {
+                        body: vec![sym("i")]
+                    })),
+                    sup: Some(Box::new(num("2"))),
+                },
+                num("3"),
+            ]
+        );
+        assert_eq!(
+            parse_math("^2"),
+            vec![Scripts {
+                base: None,
+                sub: None,
+                sup: Some(Box::new(num("2"))),
+            }]
+        );
+    }
+
+    #[test]
+    fn 引数を取る命令() {
+        assert_eq!(
+            parse_math(r"\frac12 \sqrt[3]{x}"),
+            vec![
+                Command {
+                    name: "frac".to_string(),
+                    optional: None,
+                    args: vec![vec![num("1")], vec![num("2")]],
+                },
+                Command {
+                    name: "sqrt".to_string(),
+                    optional: Some(vec![num("3")]),
+                    args: vec![vec![sym("x")]],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn テキスト() {
+        assert_eq!(
+            parse_math(r"\text{ if $x$ } y"),
+            vec![
+                Text {
+                    name: "text".to_string(),
+                    value: " if $x$ ".to_string(),
+                },
+                sym("y")
+            ]
+        );
+    }
+
+    #[test]
+    fn left_right() {
+        assert_eq!(
+            parse_math(r"\left\{ a \right."),
+            vec![Delimited {
+                left: r"\{".to_string(),
+                right: ".".to_string(),
+                body: vec![sym("a")],
+            }]
+        );
+    }
+
+    #[test]
+    fn 環境と整列() {
+        assert_eq!(
+            parse_math(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}"),
+            vec![Environment {
+                name: "pmatrix".to_string(),
+                args: vec![],
+                rows: vec![
+                    vec![vec![sym("a")], vec![sym("b")]],
+                    vec![vec![sym("c")], vec![sym("d")]],
+                ],
+            }]
+        );
+        assert_eq!(
+            parse_math(r"x &= 1 \\[2pt] &= 2"),
+            vec![Align {
+                rows: vec![
+                    vec![vec![sym("x")], vec![sym("="), num("1")]],
+                    vec![vec![], vec![sym("="), num("2")]],
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn 閉じていない括弧() {
+        assert_eq!(
+            parse_math(r"{a} } {b"),
+            vec![
+                Group {
+                    body: vec![sym("a")]
+                },
+                Group {
+                    body: vec![sym("b")]
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use crate::math_ast;
+
+/// 数式の中身の構造上の誤り
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct MathError {
+    reason: MathErrorReason,
+    /// 数式の中身の先頭からの文字数
+    offset: usize,
+}
+
+impl MathError {
+    pub(crate) fn new(reason: MathErrorReason, offset: usize) -> Self {
+        Self { reason, offset }
+    }
+
+    pub(crate) fn reason(&self) -> &MathErrorReason {
+        &self.reason
+    }
+
+    pub(crate) fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum MathErrorReason {
+    /// 閉じる区切り (`$` や `\]` など) がない
+    UnclosedMath,
+    UnclosedBrace,
+    UnmatchedCloseBrace,
+    UnclosedLeft,
+    UnmatchedRight,
+    /// `\left{` のように区切りとして使えない文字
+    InvalidDelimiter,
+    UnclosedEnvironment(String),
+    UnmatchedEnd(String),
+    MismatchedEnvironment {
+        begin: String,
+        end: String,
+    },
+    /// 数式の中に現れた数式の開始 (`\(` や `$` など)
+    NestedDelimiter(String),
+}
+
+impl MathErrorReason {
+    pub(crate) fn code(&self) -> &'static str {
+        use MathErrorReason::*;
+
+        match self {
+            UnclosedMath => "unclosed_math",
+            UnclosedBrace => "unclosed_brace",
+            UnmatchedCloseBrace => "unmatched_close_brace",
+            UnclosedLeft => "unclosed_left",
+            UnmatchedRight => "unmatched_right",
+            InvalidDelimiter => "invalid_delimiter",
+            UnclosedEnvironment(_) => "unclosed_environment",
+            UnmatchedEnd(_) => "unmatched_end",
+            MismatchedEnvironment { .. } => "mismatched_environment",
+            NestedDelimiter(_) => "nested_delimiter",
+        }
+    }
+}
+
+enum Opener {
+    Brace(usize),
+    Left(usize),
+    Begin(usize, String),
+}
+
+impl Opener {
+    fn into_unclosed(self) -> MathError {
+        match self {
+            Opener::Brace(offset) => MathError::new(MathErrorReason::UnclosedBrace, offset),
+            Opener::Left(offset) => MathError::new(MathErrorReason::UnclosedLeft, offset),
+            Opener::Begin(offset, env) => {
+                MathError::new(MathErrorReason::UnclosedEnvironment(env), offset)
+            }
+        }
+    }
+}
+
+/// 波括弧, `\left`/`\right`, `\begin`/`\end` の対応と, 入れ子になった数式の区切りを調べる
+///
+/// `\text{..}` などの中身は数式ではないので調べない.
+/// 閉じる側が直近の開く側と対応しない場合, 対応する開く側がより外にあればその間を閉じ忘れとし,
+/// なければ閉じる側を余分なものとする.
+pub(super) fn validate(content: &str) -> Vec<MathError> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut stack: Vec<Opener> = Vec::new();
+    let mut errors = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let offset = i;
+        match chars[i] {
+            '$' => {
+                let double = chars.get(i + 1) == Some(&'$');
+                let delimiter = if double { "$$" } else { "$" };
+                errors.push(MathError::new(
+                    MathErrorReason::NestedDelimiter(delimiter.to_string()),
+                    offset,
+                ));
+                i += delimiter.len();
+            }
+            '{' => {
+                stack.push(Opener::Brace(offset));
+                i += 1;
+            }
+            '}' => {
+                i += 1;
+                if !close(&mut stack, &mut errors, |x| matches!(x, Opener::Brace(_))) {
+                    errors.push(MathError::new(MathErrorReason::UnmatchedCloseBrace, offset));
+                }
+            }
+            '\\' => {
+                i += 1;
+                let name: String = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphabetic())
+                    .collect();
+                if name.is_empty() {
+                    if let Some(c @ ('(' | '[')) = chars.get(i) {
+                        errors.push(MathError::new(
+                            MathErrorReason::NestedDelimiter(format!("\\{}", c)),
+                            offset,
+                        ));
+                    }
+                    // `\{` や `\\` は括弧として数えない
+                    i += 1;
+                    continue;
+                }
+                i += name.len();
+
+                if math_ast::is_text_command(&name) {
+                    skip_group(&chars, &mut i);
+                    continue;
+                }
+
+                match name.as_str() {
+                    "left" => {
+                        stack.push(Opener::Left(offset));
+                        skip_delimiter(&chars, &mut i, &mut errors);
+                    }
+                    "right" => {
+                        if !close(&mut stack, &mut errors, |x| matches!(x, Opener::Left(_))) {
+                            errors.push(MathError::new(MathErrorReason::UnmatchedRight, offset));
+                        }
+                        skip_delimiter(&chars, &mut i, &mut errors);
+                    }
+                    "begin" => {
+                        let env = read_env_name(&chars, &mut i);
+                        stack.push(Opener::Begin(offset, env));
+                    }
+                    "end" => {
+                        let env = read_env_name(&chars, &mut i);
+                        close_env(&mut stack, &mut errors, env, offset);
+                    }
+                    _ => {}
+                }
+            }
+            _ => i += 1,
+        }
+    }
+
+    errors.extend(stack.into_iter().rev().map(Opener::into_unclosed));
+    errors.sort_by_key(|x| x.offset);
+
+    errors
+}
+
+/// 条件に合う開く側まで閉じる. 見つからなければ何もせず false を返す.
+fn close(
+    stack: &mut Vec<Opener>,
+    errors: &mut Vec<MathError>,
+    pred: impl Fn(&Opener) -> bool,
+) -> bool {
+    let Some(pos) = stack.iter().rposition(pred) else {
+        return false;
+    };
+
+    for opener in stack.drain(pos + 1..).rev() {
+        errors.push(opener.into_unclosed());
+    }
+    stack.pop();
+
+    true
+}
+
+fn close_env(stack: &mut Vec<Opener>, errors: &mut Vec<MathError>, env: String, offset: usize) {
+    if close(
+        stack,
+        errors,
+        |x| matches!(x, Opener::Begin(_, name) if name == &env),
+    ) {
+        return;
+    }
+
+    // 名前の違う `\begin` が直近にあれば取り違えとみなす
+    if let Some(Opener::Begin(_, begin)) = stack.last() {
+        let begin = begin.clone();
+        stack.pop();
+        errors.push(MathError::new(
+            MathErrorReason::MismatchedEnvironment { begin, end: env },
+            offset,
+        ));
+        return;
+    }
+
+    errors.push(MathError::new(MathErrorReason::UnmatchedEnd(env), offset));
+}
+
+/// `\left` や `\right` の後の区切りを読み飛ばす
+fn skip_delimiter(chars: &[char], i: &mut usize, errors: &mut Vec<MathError>) {
+    while chars.get(*i).is_some_and(|c| c.is_whitespace()) {
+        *i += 1;
+    }
+
+    match chars.get(*i) {
+        Some('\\') => {
+            *i += 1;
+            let len = chars[*i..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphabetic())
+                .count();
+            *i += len.max(1);
+        }
+        // 波括弧は区切りにならず, 群として数える
+        Some('{') | Some('}') => {
+            errors.push(MathError::new(MathErrorReason::InvalidDelimiter, *i));
+        }
+        Some(_) => *i += 1,
+        None => {}
+    }
+}
+
+/// `{...}` を中身ごと読み飛ばす
+fn skip_group(chars: &[char], i: &mut usize) {
+    while chars.get(*i).is_some_and(|c| c.is_whitespace()) {
+        *i += 1;
+    }
+    if chars.get(*i) != Some(&'{') {
+        return;
+    }
+
+    let mut depth = 0;
+    while let Some(&c) = chars.get(*i) {
+        *i += 1;
+        match c {
+            '\\' => *i += 1,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn read_env_name(chars: &[char], i: &mut usize) -> String {
+    while chars.get(*i).is_some_and(|c| c.is_whitespace()) {
+        *i += 1;
+    }
+    if chars.get(*i) != Some(&'{') {
+        return String::new();
+    }
+
+    let name: String = chars[*i + 1..].iter().take_while(|&&c| c != '}').collect();
+    *i = (*i + name.chars().count() + 2).min(chars.len());
+    name
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use MathErrorReason::*;
+
+    fn reasons(content: &str) -> Vec<(MathErrorReason, usize)> {
+        validate(content)
+            .into_iter()
+            .map(|x| (x.reason, x.offset))
+            .collect()
+    }
+
+    #[test]
+    fn 対応している() {
+        assert_eq!(
+            reasons(r"\left\{ \frac{a}{b} \right\} \begin{matrix} \{ \\ \end{matrix}"),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn 波括弧() {
+        assert_eq!(reasons(r"{a}}"), vec![(UnmatchedCloseBrace, 3)]);
+        assert_eq!(reasons(r"\frac{a{b}"), vec![(UnclosedBrace, 5)]);
+    }
+
+    #[test]
+    fn left_right() {
+        assert_eq!(
+            reasons(r"Z \cong \left{A \oplus B\right. ."),
+            vec![(InvalidDelimiter, 13), (UnclosedBrace, 13)]
+        );
+        assert_eq!(reasons(r"\left( a"), vec![(UnclosedLeft, 0)]);
+        assert_eq!(reasons(r"a \right)"), vec![(UnmatchedRight, 2)]);
+    }
+
+    #[test]
+    fn 入れ子の区切り() {
+        assert_eq!(
+            reasons(r"a \[ b \] + \( c"),
+            vec![
+                (NestedDelimiter(r"\[".to_string()), 2),
+                (NestedDelimiter(r"\(".to_string()), 12),
+            ]
+        );
+        assert_eq!(
+            reasons(r"a $ b $$ c"),
+            vec![
+                (NestedDelimiter("$".to_string()), 2),
+                (NestedDelimiter("$$".to_string()), 6),
+            ]
+        );
+        assert_eq!(reasons(r"\text{if $x$ is {odd}} \$"), vec![]);
+    }
+
+    #[test]
+    fn begin_end() {
+        assert_eq!(
+            reasons(r"\begin{pmatrix} a \end{bmatrix}"),
+            vec![(
+                MismatchedEnvironment {
+                    begin: "pmatrix".to_string(),
+                    end: "bmatrix".to_string()
+                },
+                18
+            )]
+        );
+        assert_eq!(
+            reasons(r"\begin{cases} a"),
+            vec![(UnclosedEnvironment("cases".to_string()), 0)]
+        );
+        assert_eq!(
+            reasons(r"a \end{cases}"),
+            vec![(UnmatchedEnd("cases".to_string()), 2)]
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use crate::math_ast;
+
+/// 表示の変わらない書き方の違いをそろえる
+///
+/// 意味を持たない空白を除き, `x^{2}` のような添字の一字だけの波括弧を外す.
+/// 空白は制御語の後に文字が続く場合 (`\alpha x`) と `\text{..}` や `\tag{..}` の中身にだけ残す.
+pub(super) fn canonicalize(content: &str) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let mut out = String::new();
+    // 直前に書き出したのが制御語なら, 文字との間の空白を残す必要がある
+    let mut after_word = false;
+    let mut i = 0;
+
+    macro_rules! push {
+        ($s:expr) => {{
+            let s: &str = $s;
+            if after_word && s.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                out.push(' ');
+            }
+            out.push_str(s);
+        }};
+    }
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c == '\\' {
+            let name: String = chars[i + 1..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphabetic())
+                .collect();
+            if name.is_empty() {
+                // `\,` や `\ ` などの制御記号はそのまま
+                let symbol: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+                push!(&symbol);
+                after_word = false;
+                i += 2;
+                continue;
+            }
+
+            push!(&format!("\\{}", name));
+            after_word = true;
+            i += 1 + name.len();
+
+            // `\tag{..}` の中身も文字列として表示される
+            if math_ast::is_text_command(&name) || name == "tag" {
+                if chars.get(i) == Some(&'*') {
+                    out.push('*');
+                    i += 1;
+                }
+                let start = skip_whitespaces(&chars, i);
+                if let Some(end) = group_end(&chars, start) {
+                    out.extend(&chars[start..end]);
+                    after_word = false;
+                    i = end;
+                }
+            }
+            continue;
+        }
+
+        push!(&c.to_string());
+        after_word = false;
+        i += 1;
+
+        if matches!(c, '^' | '_') {
+            let start = skip_whitespaces(&chars, i);
+            if let Some(end) = group_end(&chars, start) {
+                let inner: String = chars[start + 1..end - 1].iter().collect();
+                if let Some(token) = single_token(inner.trim()) {
+                    out.push_str(token);
+                    after_word = token.starts_with('\\');
+                    i = end;
+                }
+            }
+        }
+    }
+
+    out
+}
+
+fn skip_whitespaces(chars: &[char], mut i: usize) -> usize {
+    while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+        i += 1;
+    }
+    i
+}
+
+/// `start` が `{` であれば対応する `}` の次の位置を返す
+fn group_end(chars: &[char], start: usize) -> Option<usize> {
+    if chars.get(start) != Some(&'{') {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut i = start;
+    while let Some(&c) = chars.get(i) {
+        i += 1;
+        match c {
+            '\\' => i += 1,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// 英数字一字か, 引数をとらない制御語一つであれば返す
+fn single_token(s: &str) -> Option<&str> {
+    let mut cs = s.chars();
+    match (cs.next()?, cs.next()) {
+        (c, None) if c.is_ascii_alphanumeric() => Some(s),
+        ('\\', Some(_)) => {
+            let name = &s[1..];
+            let is_word = name.chars().all(|c| c.is_ascii_alphabetic());
+            (is_word && math_ast::command_arity(name).is_none() && !math_ast::is_text_command(name))
+                .then_some(s)
+        }
+        _ => None,
+    }
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn 添字の波括弧と空白() {
+        assert_eq!(canonicalize(r"x^2"), r"x^2");
+        assert_eq!(canonicalize(r"x^{2}"), r"x^2");
+        assert_eq!(canonicalize(r"x ^ 2"), r"x^2");
+        assert_eq!(canonicalize(r"x_{ \alpha } y"), r"x_\alpha y");
+        assert_eq!(canonicalize(r"x^{10} + a_{ij}"), r"x^{10}+a_{ij}");
+        assert_eq!(canonicalize(r"x^{\hat a}"), r"x^{\hat a}");
+    }
+
+    #[test]
+    fn 制御語の後の空白() {
+        assert_eq!(canonicalize(r"\alpha  x + \beta"), r"\alpha x+\beta");
+        assert_eq!(canonicalize(r"\frac {a} {b}"), r"\frac{a}{b}");
+        assert_eq!(canonicalize(r"a \, b \ c"), r"a\,b\ c");
+    }
+
+    #[test]
+    fn 文字列の中身は変えない() {
+        assert_eq!(
+            canonicalize(r"f(x) \quad \text{if  $x$ is odd} , x"),
+            r"f(x)\quad\text{if  $x$ is odd},x"
+        );
+        assert_eq!(canonicalize(r"a = b \tag*{(A  1)}"), r"a=b\tag*{(A  1)}");
+    }
+}
@@ -1,5 +1,13 @@
+use crate::equation::{self, Equation};
+use crate::katex::{self, Unsupported};
+use crate::macros::Macros;
+use crate::math_ast::{self, MathNode};
+use crate::math_balance::{self, MathError, MathErrorReason};
+use crate::math_canon;
+use crate::mathml;
 use crate::tex_char::TexChar;
 use crate::tex_chars::TexChars;
+use std::hash::{Hash, Hasher};
 
 #[derive(Debug, Hash)]
 pub(super) enum MathExprParseResult {
@@ -8,12 +16,29 @@ pub(super) enum MathExprParseResult {
 }
 
 impl MathExprParseResult {
-    pub(crate) fn ok(content: String, disc: MathDisc) -> Self {
-        Self::Ok(MathExprInfo { content, disc })
+    /// 構造上の誤りがなければ Ok, あれば Err とする
+    pub(crate) fn new(content: String, disc: MathDisc, errors: Vec<MathError>) -> Self {
+        let info = MathExprInfo {
+            disc,
+            content,
+            errors,
+            equations: Vec::new(),
+            ast: None,
+            mathml: None,
+            has_warning: false,
+        };
+
+        if info.errors.is_empty() {
+            Self::Ok(info)
+        } else {
+            Self::Err(info)
+        }
     }
 
-    pub(crate) fn err(content: String, disc: MathDisc) -> Self {
-        Self::Err(MathExprInfo { content, disc })
+    /// 番号付けの情報を持たせる
+    pub(crate) fn with_equations(mut self, equations: Vec<Equation>) -> Self {
+        self.info_mut().equations = equations;
+        self
     }
 
     pub(crate) fn is_ok(&self) -> bool {
@@ -34,6 +59,138 @@ impl MathExprParseResult {
         }
     }
 
+    /// `equation` などの環境であればその名前
+    pub(crate) fn env(&self) -> Option<&str> {
+        match self {
+            Self::Ok(info) => info.disc.env(),
+            Self::Err(info) => info.disc.env(),
+        }
+    }
+
+    pub(crate) fn equations(&self) -> &[Equation] {
+        match self {
+            Self::Ok(info) => &info.equations,
+            Self::Err(info) => &info.equations,
+        }
+    }
+
+    pub(crate) fn equations_mut(&mut self) -> &mut [Equation] {
+        &mut self.info_mut().equations
+    }
+
+    pub(crate) fn content_str(&self) -> &str {
+        match self {
+            Self::Ok(info) => &info.content,
+            Self::Err(info) => &info.content,
+        }
+    }
+
+    fn info_mut(&mut self) -> &mut MathExprInfo {
+        match self {
+            Self::Ok(info) => info,
+            Self::Err(info) => info,
+        }
+    }
+
+    /// 中身を置き換え, 構造上の誤りを調べ直す
+    pub(crate) fn replace_content(&mut self, content: String) {
+        let unclosed = self
+            .errors()
+            .iter()
+            .any(|x| x.reason() == &MathErrorReason::UnclosedMath);
+        let placeholder = Self::new(String::new(), MathDisc::SingleDollar, Vec::new());
+        let (Self::Ok(mut info) | Self::Err(mut info)) = std::mem::replace(self, placeholder);
+
+        info.errors = math_balance::validate(&content);
+        if unclosed {
+            info.errors.push(MathError::new(
+                MathErrorReason::UnclosedMath,
+                content.chars().count(),
+            ));
+        }
+        info.content = content;
+
+        *self = if info.errors.is_empty() {
+            Self::Ok(info)
+        } else {
+            Self::Err(info)
+        };
+    }
+
+    /// 中身の書き方と区切りをそろえる
+    ///
+    /// 誤りのある数式は offset が変わってしまうのでそのままにする.
+    pub(crate) fn canonicalize(&mut self) {
+        let Self::Ok(info) = self else {
+            return;
+        };
+        info.content = math_canon::canonicalize(&info.content);
+        info.disc = match info.disc {
+            MathDisc::SingleDollar => MathDisc::BsParen,
+            MathDisc::DoubleDollar => MathDisc::BsBracket,
+            _ => return,
+        };
+    }
+
+    /// 中身を構文木に変換して持たせる
+    pub(crate) fn build_ast(&mut self) {
+        let info = self.info_mut();
+        info.ast = Some(math_ast::parse_math(&info.content));
+    }
+
+    /// 中身を MathML に変換して持たせる
+    ///
+    /// 戻り値は変換できなかった制御綴など.
+    pub(crate) fn build_mathml(&mut self) -> Vec<String> {
+        let display = self.is_display();
+        let info = self.info_mut();
+        let ast = match &info.ast {
+            Some(ast) => ast.clone(),
+            None => math_ast::parse_math(&info.content),
+        };
+        let result = mathml::render(&ast, display);
+        info.mathml = Some(result.markup);
+        result.unsupported
+    }
+
+    /// KaTeX が対応していない制御綴を探し, あれば警告の印をつける
+    ///
+    /// 文書で定義したマクロは `macros` として KaTeX に渡すので除く.
+    /// ただし省略可能引数を持つものは渡せないので残す.
+    pub(crate) fn check_katex(&mut self, macros: &Macros) -> Vec<Unsupported> {
+        let info = self.info_mut();
+        let mut unsupported = katex::check(&info.content);
+        unsupported.retain(|x| macros.get(&x.name).is_none_or(|x| x.default().is_some()));
+        info.has_warning |= !unsupported.is_empty();
+        unsupported
+    }
+
+    pub(crate) fn has_warning(&self) -> bool {
+        match self {
+            Self::Ok(info) => info.has_warning,
+            Self::Err(info) => info.has_warning,
+        }
+    }
+
+    pub(crate) fn errors(&self) -> &[MathError] {
+        match self {
+            Self::Ok(info) => &info.errors,
+            Self::Err(info) => &info.errors,
+        }
+    }
+
+    pub(crate) fn take_errors(&mut self) -> Vec<MathError> {
+        std::mem::take(&mut self.info_mut().errors)
+    }
+
+    pub(crate) fn take_ast(&mut self) -> Option<Vec<MathNode>> {
+        self.info_mut().ast.take()
+    }
+
+    pub(crate) fn take_mathml(&mut self) -> Option<String> {
+        self.info_mut().mathml.take()
+    }
+
     pub(crate) fn content(self) -> String {
         match self {
             Self::Ok(info) => info.content,
@@ -42,10 +199,25 @@ impl MathExprParseResult {
     }
 }
 
-#[derive(Debug, Hash)]
+#[derive(Debug)]
 pub(super) struct MathExprInfo {
     disc: MathDisc,
     content: String,
+    errors: Vec<MathError>,
+    equations: Vec<Equation>,
+    ast: Option<Vec<MathNode>>,
+    mathml: Option<String>,
+    has_warning: bool,
+}
+
+// 誤りや構文木, MathML は content から決まるので, オプションによって Key が変わらないよう含めない.
+// 番号は同じ中身の数式でも異なりうるので含める
+impl Hash for MathExprInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.disc.hash(state);
+        self.content.hash(state);
+        self.equations.hash(state);
+    }
 }
 
 #[derive(Debug, Hash)]
@@ -54,6 +226,8 @@ pub(super) enum MathDisc {
     BsBracket,
     DoubleDollar,
     SingleDollar,
+    /// `\begin{equation}` などの環境
+    Environment(String),
 }
 
 impl MathDisc {
@@ -64,7 +238,14 @@ impl MathDisc {
 
     pub(crate) fn is_display(&self) -> bool {
         use MathDisc::*;
-        matches!(self, DoubleDollar | BsBracket)
+        matches!(self, DoubleDollar | BsBracket | Environment(_))
+    }
+
+    pub(crate) fn env(&self) -> Option<&str> {
+        match self {
+            MathDisc::Environment(name) => Some(name),
+            _ => None,
+        }
     }
 
     pub(crate) fn match_begin(cs: &TexChars) -> Option<Self> {
@@ -79,6 +260,10 @@ impl MathDisc {
             return Some(BsBracket);
         }
 
+        if let Some(name) = cs.peek_begin_env().filter(|x| equation::is_math_env(x)) {
+            return Some(Environment(name));
+        }
+
         if cs.next_isis(Dollar, Dollar) {
             return Some(DoubleDollar);
         }
@@ -99,6 +284,7 @@ impl MathDisc {
             BsBracket => cs.next_isis(Backslash, RBracket),
             DoubleDollar => cs.next_isis(Dollar, Dollar),
             SingleDollar => cs.next_is(Dollar),
+            Environment(name) => cs.starts_with(&format!("\\end{{{}}}", name)),
         }
     }
 
@@ -113,21 +299,127 @@ impl MathDisc {
             SingleDollar => {
                 cs.next().unwrap();
             }
+            Environment(_) => {
+                cs.consume_begin_env().unwrap();
+            }
         }
     }
 
     pub(crate) fn consume_end(&self, cs: &mut TexChars) {
-        // use MathDisc::*;
-        //
-        // match self {
-        //     BsParen | BsBracket | DoubleDollar => {
-        //         cs.next().unwrap();
-        //         cs.next().unwrap();
-        //     }
-        //     SingleDollar => {
-        //         cs.next().unwrap();
-        //     }
-        // }
-        self.consume_begin(cs);
+        use MathDisc::*;
+
+        match self {
+            Environment(name) => {
+                let len = format!("\\end{{{}}}", name).chars().count();
+                for _ in 0..len {
+                    cs.next();
+                }
+            }
+            _ => self.consume_begin(cs),
+        }
+    }
+}
+
+/// 通貨などの `$` を数式の区切りとみなさない (pandoc と同様の規則)
+///
+/// 開く `$` の直後は空白でなく, 閉じる `$` の直前は空白でなく直後は数字でない.
+/// これを満たさない `$` は文字として扱う.
+/// 数式の中の `\text{..}` などの `$` は入れ子の数式なので, 閉じる `$` を探すときは読み飛ばす.
+pub(super) fn mark_literal_dollars(cs: TexChars) -> TexChars {
+    use TexChar::*;
+
+    let mut chars: Vec<TexChar> = cs.collect();
+    let is_dollar =
+        |chars: &[TexChar], i: usize| chars[i] == Dollar && (i == 0 || chars[i - 1] != Backslash);
+    let is_space = |c: Option<&TexChar>| matches!(c, None | Some(Whitespace | Return));
+
+    let mut i = 0;
+    while i < chars.len() {
+        if !is_dollar(&chars, i) {
+            i += 1;
+            continue;
+        }
+
+        // `$$` はディスプレイ数式として閉じるまで読み飛ばす
+        if chars.get(i + 1) == Some(&Dollar) {
+            i = (i + 2..chars.len().saturating_sub(1))
+                .find(|&j| chars[j] == Dollar && chars[j + 1] == Dollar)
+                .map_or(i + 2, |j| j + 2);
+            continue;
+        }
+
+        // 閉じる `$` と, その間の文字として扱う `$`
+        let mut close = None;
+        let mut literals = Vec::new();
+        if !is_space(chars.get(i + 1)) {
+            let mut j = i + 1;
+            while j < chars.len() {
+                if let Some(end) = text_group_end(&chars, j) {
+                    j = end;
+                    continue;
+                }
+                if is_dollar(&chars, j) {
+                    if !is_space(chars.get(j - 1))
+                        && !matches!(chars.get(j + 1), Some(Char(c)) if c.is_ascii_digit())
+                    {
+                        close = Some(j);
+                        break;
+                    }
+                    literals.push(j);
+                }
+                j += 1;
+            }
+        }
+
+        match close {
+            Some(j) => {
+                for k in literals {
+                    chars[k] = Char('$');
+                }
+                i = j + 1;
+            }
+            None => {
+                chars[i] = Char('$');
+                i += 1;
+            }
+        }
+    }
+
+    chars.into_iter().collect()
+}
+
+/// `i` から `\text{..}` などが始まれば, その群の次の位置
+fn text_group_end(chars: &[TexChar], i: usize) -> Option<usize> {
+    if chars[i] != TexChar::Backslash {
+        return None;
+    }
+    let name_end = (i + 1..chars.len())
+        .find(|&j| !matches!(chars[j], TexChar::Char(c) if c.is_ascii_alphabetic()))
+        .unwrap_or(chars.len());
+    let name: String = chars[i + 1..name_end]
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+    if !math_ast::is_text_command(&name) {
+        return None;
+    }
+
+    let start = (name_end..chars.len()).find(|&j| chars[j] != TexChar::Whitespace)?;
+    if chars[start] != TexChar::LBrace {
+        return None;
+    }
+    let mut depth = 0;
+    for (j, c) in chars.iter().enumerate().skip(start) {
+        match c {
+            TexChar::LBrace if chars[j - 1] != TexChar::Backslash => depth += 1,
+            TexChar::RBrace if chars[j - 1] != TexChar::Backslash => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(j + 1);
+                }
+            }
+            _ => {}
+        }
     }
+    None
 }
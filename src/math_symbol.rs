@@ -0,0 +1,200 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum SymbolKind {
+    Identifier,
+    Operator,
+    Function,
+    Space,
+}
+
+/// 引数を取らない制御綴の変換先
+pub(super) fn symbol_of(name: &str) -> Option<(SymbolKind, &'static str)> {
+    use SymbolKind::*;
+
+    let symbol = match name {
+        // ギリシャ文字
+        "alpha" => (Identifier, "α"),
+        "beta" => (Identifier, "β"),
+        "gamma" => (Identifier, "γ"),
+        "delta" => (Identifier, "δ"),
+        "epsilon" => (Identifier, "ϵ"),
+        "varepsilon" => (Identifier, "ε"),
+        "zeta" => (Identifier, "ζ"),
+        "eta" => (Identifier, "η"),
+        "theta" => (Identifier, "θ"),
+        "vartheta" => (Identifier, "ϑ"),
+        "iota" => (Identifier, "ι"),
+        "kappa" => (Identifier, "κ"),
+        "lambda" => (Identifier, "λ"),
+        "mu" => (Identifier, "μ"),
+        "nu" => (Identifier, "ν"),
+        "xi" => (Identifier, "ξ"),
+        "pi" => (Identifier, "π"),
+        "varpi" => (Identifier, "ϖ"),
+        "rho" => (Identifier, "ρ"),
+        "varrho" => (Identifier, "ϱ"),
+        "sigma" => (Identifier, "σ"),
+        "varsigma" => (Identifier, "ς"),
+        "tau" => (Identifier, "τ"),
+        "upsilon" => (Identifier, "υ"),
+        "phi" => (Identifier, "ϕ"),
+        "varphi" => (Identifier, "φ"),
+        "chi" => (Identifier, "χ"),
+        "psi" => (Identifier, "ψ"),
+        "omega" => (Identifier, "ω"),
+        "Gamma" => (Identifier, "Γ"),
+        "Delta" => (Identifier, "Δ"),
+        "Theta" => (Identifier, "Θ"),
+        "Lambda" => (Identifier, "Λ"),
+        "Xi" => (Identifier, "Ξ"),
+        "Pi" => (Identifier, "Π"),
+        "Sigma" => (Identifier, "Σ"),
+        "Upsilon" => (Identifier, "Υ"),
+        "Phi" => (Identifier, "Φ"),
+        "Psi" => (Identifier, "Ψ"),
+        "Omega" => (Identifier, "Ω"),
+        // 記号
+        "infty" => (Identifier, "∞"),
+        "partial" => (Identifier, "∂"),
+        "nabla" => (Identifier, "∇"),
+        "emptyset" => (Identifier, "∅"),
+        "varnothing" => (Identifier, "∅"),
+        "hbar" => (Identifier, "ℏ"),
+        "ell" => (Identifier, "ℓ"),
+        "Re" => (Identifier, "ℜ"),
+        "Im" => (Identifier, "ℑ"),
+        "aleph" => (Identifier, "ℵ"),
+        "prime" => (Operator, "′"),
+        // 演算子
+        "times" => (Operator, "×"),
+        "cdot" => (Operator, "⋅"),
+        "pm" => (Operator, "±"),
+        "mp" => (Operator, "∓"),
+        "div" => (Operator, "÷"),
+        "ast" => (Operator, "∗"),
+        "star" => (Operator, "⋆"),
+        "circ" => (Operator, "∘"),
+        "bullet" => (Operator, "∙"),
+        "oplus" => (Operator, "⊕"),
+        "otimes" => (Operator, "⊗"),
+        "cup" => (Operator, "∪"),
+        "cap" => (Operator, "∩"),
+        "setminus" => (Operator, "∖"),
+        "wedge" | "land" => (Operator, "∧"),
+        "vee" | "lor" => (Operator, "∨"),
+        "neg" | "lnot" => (Operator, "¬"),
+        "forall" => (Operator, "∀"),
+        "exists" => (Operator, "∃"),
+        // 関係
+        "leq" | "le" => (Operator, "≤"),
+        "geq" | "ge" => (Operator, "≥"),
+        "neq" | "ne" => (Operator, "≠"),
+        "ll" => (Operator, "≪"),
+        "gg" => (Operator, "≫"),
+        "approx" => (Operator, "≈"),
+        "equiv" => (Operator, "≡"),
+        "sim" => (Operator, "∼"),
+        "simeq" => (Operator, "≃"),
+        "cong" => (Operator, "≅"),
+        "propto" => (Operator, "∝"),
+        "in" => (Operator, "∈"),
+        "notin" => (Operator, "∉"),
+        "ni" => (Operator, "∋"),
+        "subset" => (Operator, "⊂"),
+        "subseteq" => (Operator, "⊆"),
+        "supset" => (Operator, "⊃"),
+        "supseteq" => (Operator, "⊇"),
+        "perp" => (Operator, "⊥"),
+        "parallel" => (Operator, "∥"),
+        "mid" => (Operator, "∣"),
+        "colon" => (Operator, ":"),
+        // 矢印
+        "to" | "rightarrow" => (Operator, "→"),
+        "leftarrow" | "gets" => (Operator, "←"),
+        "leftrightarrow" => (Operator, "↔"),
+        "Rightarrow" => (Operator, "⇒"),
+        "Leftarrow" => (Operator, "⇐"),
+        "Leftrightarrow" => (Operator, "⇔"),
+        "implies" => (Operator, "⟹"),
+        "impliedby" => (Operator, "⟸"),
+        "iff" => (Operator, "⟺"),
+        "mapsto" => (Operator, "↦"),
+        "uparrow" => (Operator, "↑"),
+        "downarrow" => (Operator, "↓"),
+        // 大きな演算子
+        "sum" => (Operator, "∑"),
+        "prod" => (Operator, "∏"),
+        "coprod" => (Operator, "∐"),
+        "int" => (Operator, "∫"),
+        "iint" => (Operator, "∬"),
+        "iiint" => (Operator, "∭"),
+        "oint" => (Operator, "∮"),
+        "bigcup" => (Operator, "⋃"),
+        "bigcap" => (Operator, "⋂"),
+        "bigoplus" => (Operator, "⨁"),
+        "bigotimes" => (Operator, "⨂"),
+        // 点
+        "ldots" | "dots" | "dotsc" | "dotsb" => (Operator, "…"),
+        "cdots" => (Operator, "⋯"),
+        "vdots" => (Operator, "⋮"),
+        "ddots" => (Operator, "⋱"),
+        // 括弧
+        "{" | "lbrace" => (Operator, "{"),
+        "}" | "rbrace" => (Operator, "}"),
+        "langle" => (Operator, "⟨"),
+        "rangle" => (Operator, "⟩"),
+        "lvert" | "rvert" | "vert" => (Operator, "|"),
+        "|" | "lVert" | "rVert" | "Vert" => (Operator, "‖"),
+        "lfloor" => (Operator, "⌊"),
+        "rfloor" => (Operator, "⌋"),
+        "lceil" => (Operator, "⌈"),
+        "rceil" => (Operator, "⌉"),
+        // 関数名
+        "sin" => (Function, "sin"),
+        "cos" => (Function, "cos"),
+        "tan" => (Function, "tan"),
+        "cot" => (Function, "cot"),
+        "sec" => (Function, "sec"),
+        "csc" => (Function, "csc"),
+        "arcsin" => (Function, "arcsin"),
+        "arccos" => (Function, "arccos"),
+        "arctan" => (Function, "arctan"),
+        "sinh" => (Function, "sinh"),
+        "cosh" => (Function, "cosh"),
+        "tanh" => (Function, "tanh"),
+        "log" => (Function, "log"),
+        "ln" => (Function, "ln"),
+        "exp" => (Function, "exp"),
+        "lim" => (Function, "lim"),
+        "liminf" => (Function, "lim inf"),
+        "limsup" => (Function, "lim sup"),
+        "max" => (Function, "max"),
+        "min" => (Function, "min"),
+        "sup" => (Function, "sup"),
+        "inf" => (Function, "inf"),
+        "det" => (Function, "det"),
+        "dim" => (Function, "dim"),
+        "ker" => (Function, "ker"),
+        "deg" => (Function, "deg"),
+        "gcd" => (Function, "gcd"),
+        "arg" => (Function, "arg"),
+        "Pr" => (Function, "Pr"),
+        "bmod" | "mod" => (Function, "mod"),
+        // 空白
+        "," | "thinspace" => (Space, "0.167em"),
+        ":" | ">" | "medspace" => (Space, "0.222em"),
+        ";" | "thickspace" => (Space, "0.278em"),
+        " " => (Space, "0.333em"),
+        "quad" => (Space, "1em"),
+        "qquad" => (Space, "2em"),
+        "!" | "negthinspace" => (Space, "-0.167em"),
+        // エスケープ
+        "%" => (Identifier, "%"),
+        "$" => (Identifier, "$"),
+        "&" => (Operator, "&"),
+        "#" => (Identifier, "#"),
+        "_" => (Identifier, "_"),
+        _ => return None,
+    };
+
+    Some(symbol)
+}
@@ -0,0 +1,475 @@
+use crate::math_ast::{self, MathNode};
+use crate::math_symbol::{symbol_of, SymbolKind};
+
+/// 数式を Unicode の文字列に変換する
+///
+/// 上付き・下付き文字や書体は Unicode で表せる範囲で置き換え,
+/// 表せないものは `(a)/(b)` や `x^(n+1)` のように1行の形にする.
+pub fn math_to_unicode(content: &str) -> String {
+    render(&math_ast::parse_math(content))
+}
+
+pub(super) fn render(nodes: &[MathNode]) -> String {
+    normalize_spaces(&render_nodes(nodes))
+}
+
+fn render_nodes(nodes: &[MathNode]) -> String {
+    nodes.iter().map(render_node).collect()
+}
+
+fn render_node(node: &MathNode) -> String {
+    match node {
+        MathNode::Symbol { value } => render_symbol(value),
+        MathNode::Number { value } => value.to_owned(),
+        MathNode::ControlSequence { name } => render_control_sequence(name),
+        MathNode::Group { body } => render_nodes(body),
+        MathNode::Scripts { base, sub, sup } => {
+            let base = base.as_deref().map(render_node).unwrap_or_default();
+            // 総和などの後の空白は添字の後に置く
+            let (mut s, trailing) = match base.strip_suffix(' ') {
+                Some(base) => (base.to_string(), " "),
+                None => (base, ""),
+            };
+            if let Some(sub) = sub {
+                s.push_str(&render_script(sub, '_', to_subscript));
+            }
+            if let Some(sup) = sup {
+                s.push_str(&render_script(sup, '^', to_superscript));
+            }
+            s.push_str(trailing);
+            s
+        }
+        MathNode::Command {
+            name,
+            optional,
+            args,
+        } => render_command(name, optional.as_deref(), args),
+        MathNode::Text { value, .. } => value.to_owned(),
+        MathNode::Delimited { left, right, body } => {
+            format!(
+                "{}{}{}",
+                render_delimiter(left),
+                render_nodes(body),
+                render_delimiter(right)
+            )
+        }
+        MathNode::Environment { name, rows, .. } => {
+            let (left, right, row_sep) = match name.as_str() {
+                "pmatrix" => ("(", ")", "; "),
+                "bmatrix" => ("[", "]", "; "),
+                "Bmatrix" => ("{", "}", "; "),
+                "vmatrix" => ("|", "|", "; "),
+                "Vmatrix" => ("‖", "‖", "; "),
+                "cases" => ("{ ", "", "; "),
+                "matrix" | "smallmatrix" | "array" | "subarray" => ("", "", "; "),
+                _ => ("", "", "\n"),
+            };
+            format!("{}{}{}", left, render_rows(rows, row_sep), right)
+        }
+        MathNode::Align { rows } => render_rows(rows, "\n"),
+    }
+}
+
+fn render_rows(rows: &[Vec<Vec<MathNode>>], row_sep: &str) -> String {
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .map(|cell| render(cell))
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(row_sep)
+}
+
+fn render_symbol(value: &str) -> String {
+    match value {
+        "-" => " − ".to_string(),
+        "'" => "′".to_string(),
+        "~" => " ".to_string(),
+        "," | ";" | ":" => format!("{} ", value),
+        "+" | "=" | "<" | ">" => format!(" {} ", value),
+        _ => value.to_owned(),
+    }
+}
+
+fn render_control_sequence(name: &str) -> String {
+    match symbol_of(name) {
+        Some((SymbolKind::Space, width)) => {
+            if width.starts_with('-') {
+                String::new()
+            } else {
+                " ".to_string()
+            }
+        }
+        Some((SymbolKind::Function, s)) => format!("{} ", s),
+        Some((SymbolKind::Operator, s)) if is_large_operator(s) => format!("{} ", s),
+        Some((SymbolKind::Operator, s)) if is_spaced_operator(s) => format!(" {} ", s),
+        Some((_, s)) => s.to_owned(),
+        // 次の文字とつながらないよう, 制御語の後には空白を置く
+        None if name.chars().all(|c| c.is_ascii_alphabetic()) => format!("\\{} ", name),
+        None => format!("\\{}", name),
+    }
+}
+
+/// 前後に空白を置く二項演算子や関係
+fn is_spaced_operator(s: &str) -> bool {
+    !matches!(
+        s,
+        "{" | "}"
+            | "⟨"
+            | "⟩"
+            | "|"
+            | "‖"
+            | "⌊"
+            | "⌋"
+            | "⌈"
+            | "⌉"
+            | "…"
+            | "⋯"
+            | "⋮"
+            | "⋱"
+            | "′"
+            | "¬"
+            | "∀"
+            | "∃"
+            | ":"
+            | "&"
+    )
+}
+
+fn is_large_operator(s: &str) -> bool {
+    matches!(
+        s,
+        "∑" | "∏" | "∐" | "∫" | "∬" | "∭" | "∮" | "⋃" | "⋂" | "⨁" | "⨂"
+    )
+}
+
+fn render_delimiter(delimiter: &str) -> String {
+    match delimiter {
+        "." => String::new(),
+        d if d.starts_with('\\') => match symbol_of(&d[1..]) {
+            Some((_, s)) => s.to_owned(),
+            None => d.to_owned(),
+        },
+        d => d.to_owned(),
+    }
+}
+
+/// 添字を上付き・下付き文字で表し, 表せなければ `_x` や `^(n+1)` とする
+fn render_script(node: &MathNode, mark: char, convert: fn(char) -> Option<char>) -> String {
+    let s: String = render_node(node)
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+
+    if let Some(converted) = s.chars().map(convert).collect::<Option<String>>() {
+        return converted;
+    }
+
+    if s.chars().count() == 1 {
+        format!("{}{}", mark, s)
+    } else {
+        format!("{}({})", mark, s)
+    }
+}
+
+fn render_command(name: &str, optional: Option<&[MathNode]>, args: &[Vec<MathNode>]) -> String {
+    let arg = |i: usize| render(args.get(i).map(|x| x.as_slice()).unwrap_or_default());
+
+    if let Some(convert) = letter_style(name) {
+        let s = arg(0);
+        return match s.chars().map(convert).collect::<Option<String>>() {
+            Some(converted) => converted,
+            None => s,
+        };
+    }
+
+    if let Some(mark) = combining_accent(name) {
+        let s = arg(0);
+        if s.chars().count() == 1 || name == "overline" || name == "underline" {
+            return s.chars().flat_map(|c| [c, mark]).collect();
+        }
+        return format!("{}({})", name, s);
+    }
+
+    match name {
+        "frac" | "dfrac" | "tfrac" | "cfrac" => format!("({})/({})", arg(0), arg(1)),
+        "binom" | "dbinom" | "tbinom" => format!("({} choose {})", arg(0), arg(1)),
+        "sqrt" => {
+            let index = optional.map(render).unwrap_or_default();
+            let radical = match index.as_str() {
+                "" => "√".to_string(),
+                "3" => "∛".to_string(),
+                "4" => "∜".to_string(),
+                _ => match index
+                    .chars()
+                    .map(to_superscript)
+                    .collect::<Option<String>>()
+                {
+                    Some(index) => format!("{}√", index),
+                    None => format!("({})√", index),
+                },
+            };
+            let radicand = arg(0);
+            if radicand.chars().count() == 1 {
+                format!("{}{}", radical, radicand)
+            } else {
+                format!("{}({})", radical, radicand)
+            }
+        }
+        "stackrel" | "overset" | "underset" => arg(1),
+        "xrightarrow" => " → ".to_string(),
+        "xleftarrow" => " ← ".to_string(),
+        "operatorname" | "operatorname*" => format!("{} ", arg(0)),
+        "pmod" => format!(" (mod {})", arg(0)),
+        "phantom" => String::new(),
+        "textcolor" => arg(1),
+        _ => args.iter().map(|x| render(x)).collect(),
+    }
+}
+
+fn combining_accent(name: &str) -> Option<char> {
+    let mark = match name {
+        "hat" | "widehat" => '\u{0302}',
+        "check" => '\u{030C}',
+        "breve" => '\u{0306}',
+        "acute" => '\u{0301}',
+        "grave" => '\u{0300}',
+        "bar" => '\u{0304}',
+        "overline" => '\u{0305}',
+        "tilde" | "widetilde" => '\u{0303}',
+        "vec" | "overrightarrow" => '\u{20D7}',
+        "dot" => '\u{0307}',
+        "ddot" => '\u{0308}',
+        "mathring" => '\u{030A}',
+        "underline" => '\u{0332}',
+        _ => return None,
+    };
+
+    Some(mark)
+}
+
+fn letter_style(name: &str) -> Option<fn(char) -> Option<char>> {
+    let convert: fn(char) -> Option<char> = match name {
+        "mathbb" => to_double_struck,
+        "mathcal" | "mathscr" => to_script,
+        "mathfrak" => to_fraktur,
+        "mathbf" | "boldsymbol" | "bm" => to_bold,
+        _ => return None,
+    };
+
+    Some(convert)
+}
+
+fn offset(c: char, base: u32) -> Option<char> {
+    char::from_u32(base + c as u32 - 'A' as u32)
+}
+
+fn to_double_struck(c: char) -> Option<char> {
+    match c {
+        'C' => Some('ℂ'),
+        'H' => Some('ℍ'),
+        'N' => Some('ℕ'),
+        'P' => Some('ℙ'),
+        'Q' => Some('ℚ'),
+        'R' => Some('ℝ'),
+        'Z' => Some('ℤ'),
+        'A'..='Z' => offset(c, 0x1D538),
+        '0'..='9' => char::from_u32(0x1D7D8 + c as u32 - '0' as u32),
+        _ => None,
+    }
+}
+
+fn to_script(c: char) -> Option<char> {
+    match c {
+        'B' => Some('ℬ'),
+        'E' => Some('ℰ'),
+        'F' => Some('ℱ'),
+        'H' => Some('ℋ'),
+        'I' => Some('ℐ'),
+        'L' => Some('ℒ'),
+        'M' => Some('ℳ'),
+        'R' => Some('ℛ'),
+        'A'..='Z' => offset(c, 0x1D49C),
+        _ => None,
+    }
+}
+
+fn to_fraktur(c: char) -> Option<char> {
+    match c {
+        'C' => Some('ℭ'),
+        'H' => Some('ℌ'),
+        'I' => Some('ℑ'),
+        'R' => Some('ℜ'),
+        'Z' => Some('ℨ'),
+        'A'..='Z' => offset(c, 0x1D504),
+        _ => None,
+    }
+}
+
+fn to_bold(c: char) -> Option<char> {
+    match c {
+        'A'..='Z' => offset(c, 0x1D400),
+        'a'..='z' => char::from_u32(0x1D41A + c as u32 - 'a' as u32),
+        '0'..='9' => char::from_u32(0x1D7CE + c as u32 - '0' as u32),
+        _ => None,
+    }
+}
+
+fn to_superscript(c: char) -> Option<char> {
+    let s = match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '+' => '⁺',
+        '−' | '-' => '⁻',
+        '=' => '⁼',
+        '(' => '⁽',
+        ')' => '⁾',
+        'a' => 'ᵃ',
+        'b' => 'ᵇ',
+        'c' => 'ᶜ',
+        'd' => 'ᵈ',
+        'e' => 'ᵉ',
+        'f' => 'ᶠ',
+        'g' => 'ᵍ',
+        'h' => 'ʰ',
+        'i' => 'ⁱ',
+        'j' => 'ʲ',
+        'k' => 'ᵏ',
+        'l' => 'ˡ',
+        'm' => 'ᵐ',
+        'n' => 'ⁿ',
+        'o' => 'ᵒ',
+        'p' => 'ᵖ',
+        'r' => 'ʳ',
+        's' => 'ˢ',
+        't' => 'ᵗ',
+        'u' => 'ᵘ',
+        'v' => 'ᵛ',
+        'w' => 'ʷ',
+        'x' => 'ˣ',
+        'y' => 'ʸ',
+        'z' => 'ᶻ',
+        'T' => 'ᵀ',
+        '′' => '′',
+        _ => return None,
+    };
+
+    Some(s)
+}
+
+fn to_subscript(c: char) -> Option<char> {
+    let s = match c {
+        '0' => '₀',
+        '1' => '₁',
+        '2' => '₂',
+        '3' => '₃',
+        '4' => '₄',
+        '5' => '₅',
+        '6' => '₆',
+        '7' => '₇',
+        '8' => '₈',
+        '9' => '₉',
+        '+' => '₊',
+        '−' | '-' => '₋',
+        '=' => '₌',
+        '(' => '₍',
+        ')' => '₎',
+        'a' => 'ₐ',
+        'e' => 'ₑ',
+        'h' => 'ₕ',
+        'i' => 'ᵢ',
+        'j' => 'ⱼ',
+        'k' => 'ₖ',
+        'l' => 'ₗ',
+        'm' => 'ₘ',
+        'n' => 'ₙ',
+        'o' => 'ₒ',
+        'p' => 'ₚ',
+        'r' => 'ᵣ',
+        's' => 'ₛ',
+        't' => 'ₜ',
+        'u' => 'ᵤ',
+        'v' => 'ᵥ',
+        'x' => 'ₓ',
+        'β' => 'ᵦ',
+        'γ' => 'ᵧ',
+        'ρ' => 'ᵨ',
+        'φ' => 'ᵩ',
+        'χ' => 'ᵪ',
+        _ => return None,
+    };
+
+    Some(s)
+}
+
+/// 各行の連続する空白を1つにまとめる
+pub(super) fn normalize_spaces(s: &str) -> String {
+    s.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn 上付きと下付き() {
+        assert_eq!(math_to_unicode(r"\alpha_{i}^{2} \to \infty"), "αᵢ² → ∞");
+        assert_eq!(math_to_unicode(r"\sum_{k=1}^{n} x_k"), "∑ₖ₌₁ⁿ xₖ");
+        assert_eq!(math_to_unicode(r"e^{\pi i}"), "e^(πi)");
+        assert_eq!(math_to_unicode(r"x_\beta"), "xᵦ");
+        assert_eq!(math_to_unicode(r"x_Q"), "x_Q");
+    }
+
+    #[test]
+    fn 分数と根号() {
+        assert_eq!(math_to_unicode(r"\frac{a}{b}"), "(a)/(b)");
+        assert_eq!(
+            math_to_unicode(r"\sqrt{x+1} + \sqrt[3]{y}"),
+            "√(x + 1) + ∛y"
+        );
+    }
+
+    #[test]
+    fn 書体とアクセント() {
+        assert_eq!(
+            math_to_unicode(r"f: \mathbb{R} \to \mathcal{L}"),
+            "f: ℝ → ℒ"
+        );
+        assert_eq!(math_to_unicode(r"\hat{x} = \vec{v}"), "x̂ = v⃗");
+        assert_eq!(math_to_unicode(r"\sin x \leq 1"), "sin x ≤ 1");
+    }
+
+    #[test]
+    fn 行列と整列() {
+        assert_eq!(
+            math_to_unicode(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}"),
+            "(a b; c d)"
+        );
+        assert_eq!(math_to_unicode(r"x &= 1 \\ y &= 2"), "x = 1\ny = 2");
+    }
+    #[test]
+    fn 知らない命令() {
+        assert_eq!(math_to_unicode(r"\foo x"), r"\foo x");
+        assert_eq!(math_to_unicode(r"a \over b"), r"a\over b");
+        assert_eq!(math_to_unicode(r"A \defeq B"), r"A\defeq B");
+        assert_eq!(math_to_unicode(r"\foo"), r"\foo");
+    }
+}
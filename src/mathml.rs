@@ -0,0 +1,482 @@
+use crate::math_ast::MathNode;
+use crate::math_symbol::{symbol_of, SymbolKind};
+
+const MATHML_NS: &str = "http://www.w3.org/1998/Math/MathML";
+
+/// MathML に変換した結果
+///
+/// 変換できなかった制御綴や環境は `<merror>` として出力し, `unsupported` に名前を残す.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct MathMl {
+    pub markup: String,
+    pub unsupported: Vec<String>,
+}
+
+pub(super) fn render(nodes: &[MathNode], display: bool) -> MathMl {
+    let mut renderer = Renderer {
+        out: String::new(),
+        unsupported: Vec::new(),
+        variant: None,
+        display,
+    };
+
+    renderer.out.push_str(&format!(
+        r#"<math xmlns="{}" display="{}">"#,
+        MATHML_NS,
+        if display { "block" } else { "inline" }
+    ));
+    renderer.row(nodes);
+    renderer.out.push_str("</math>");
+
+    MathMl {
+        markup: renderer.out,
+        unsupported: renderer.unsupported,
+    }
+}
+
+struct Renderer {
+    out: String,
+    unsupported: Vec<String>,
+    variant: Option<&'static str>,
+    display: bool,
+}
+
+impl Renderer {
+    fn row(&mut self, nodes: &[MathNode]) {
+        if nodes.len() == 1 {
+            self.node(&nodes[0]);
+            return;
+        }
+
+        self.out.push_str("<mrow>");
+        for node in nodes {
+            self.node(node);
+        }
+        self.out.push_str("</mrow>");
+    }
+
+    fn node(&mut self, node: &MathNode) {
+        match node {
+            MathNode::Symbol { value } => self.symbol(value),
+            MathNode::Number { value } => self.element("mn", value),
+            MathNode::ControlSequence { name } => self.control_sequence(name),
+            MathNode::Group { body } => self.row(body),
+            MathNode::Scripts { base, sub, sup } => self.scripts(base, sub, sup),
+            MathNode::Command {
+                name,
+                optional,
+                args,
+            } => self.command(name, optional.as_deref(), args),
+            MathNode::Text { value, .. } => self.element("mtext", value),
+            MathNode::Delimited { left, right, body } => {
+                self.out.push_str("<mrow>");
+                self.fence(left);
+                self.row(body);
+                self.fence(right);
+                self.out.push_str("</mrow>");
+            }
+            MathNode::Environment { name, rows, .. } => self.environment(name, rows),
+            MathNode::Align { rows } => self.table(rows),
+        }
+    }
+
+    fn element(&mut self, tag: &str, content: &str) {
+        self.out
+            .push_str(&format!("<{tag}>{}</{tag}>", escape(content)));
+    }
+
+    fn identifier(&mut self, content: &str) {
+        match self.variant {
+            Some(variant) => self.out.push_str(&format!(
+                r#"<mi mathvariant="{}">{}</mi>"#,
+                variant,
+                escape(content)
+            )),
+            None => self.element("mi", content),
+        }
+    }
+
+    fn operator(&mut self, content: &str) {
+        self.element("mo", content);
+    }
+
+    fn space(&mut self, width: &str) {
+        self.out
+            .push_str(&format!(r#"<mspace width="{}"/>"#, width));
+    }
+
+    fn error(&mut self, name: &str) {
+        self.unsupported.push(name.to_string());
+        self.out.push_str("<merror>");
+        self.element("mtext", name);
+        self.out.push_str("</merror>");
+    }
+
+    fn symbol(&mut self, value: &str) {
+        match value {
+            "-" => self.operator("\u{2212}"),
+            "'" => self.operator("\u{2032}"),
+            "~" => self.space("0.333em"),
+            "+" | "=" | "<" | ">" | "," | ";" | ":" | "!" | "(" | ")" | "[" | "]" | "|" | "/"
+            | "*" | "." | "?" => self.operator(value),
+            _ => self.identifier(value),
+        }
+    }
+
+    fn fence(&mut self, delimiter: &str) {
+        let delimiter = match delimiter {
+            "." | "" => return,
+            d if d.starts_with('\\') => match symbol_of(&d[1..]) {
+                Some((_, s)) => s,
+                None => {
+                    self.error(d);
+                    return;
+                }
+            },
+            d => d,
+        };
+        self.out.push_str(&format!(
+            r#"<mo fence="true" stretchy="true">{}</mo>"#,
+            escape(delimiter)
+        ));
+    }
+
+    fn control_sequence(&mut self, name: &str) {
+        if let Some((kind, s)) = symbol_of(name) {
+            match kind {
+                SymbolKind::Identifier => self.identifier(s),
+                SymbolKind::Operator => self.operator(s),
+                SymbolKind::Function => self.element("mi", s),
+                SymbolKind::Space => self.space(s),
+            }
+            return;
+        }
+
+        self.error(&format!("\\{}", name));
+    }
+
+    fn scripts(
+        &mut self,
+        base: &Option<Box<MathNode>>,
+        sub: &Option<Box<MathNode>>,
+        sup: &Option<Box<MathNode>>,
+    ) {
+        // 表示数式中の総和などは添字を上下に置く
+        let is_large_op = matches!(
+            base.as_deref(),
+            Some(MathNode::ControlSequence { name }) if is_limits_operator(name)
+        ) && self.display;
+
+        let tag = match (sub.is_some(), sup.is_some(), is_large_op) {
+            (true, true, false) => "msubsup",
+            (true, false, false) => "msub",
+            (false, _, false) => "msup",
+            (true, true, true) => "munderover",
+            (true, false, true) => "munder",
+            (false, _, true) => "mover",
+        };
+
+        self.out.push_str(&format!("<{}>", tag));
+        match base {
+            Some(base) => self.node(base),
+            None => self.out.push_str("<mrow/>"),
+        }
+        for script in [sub, sup].into_iter().flatten() {
+            self.node(script);
+        }
+        self.out.push_str(&format!("</{}>", tag));
+    }
+
+    fn wrap(&mut self, open: &str, args: &[&[MathNode]], close: &str) {
+        self.out.push_str(open);
+        for arg in args {
+            self.row(arg);
+        }
+        self.out.push_str(close);
+    }
+
+    fn command(&mut self, name: &str, optional: Option<&[MathNode]>, args: &[Vec<MathNode>]) {
+        let arg = |i: usize| args.get(i).map(|x| x.as_slice()).unwrap_or_default();
+
+        if let Some(variant) = math_variant(name) {
+            let outer = self.variant.replace(variant);
+            self.row(arg(0));
+            self.variant = outer;
+            return;
+        }
+
+        if let Some((accent, over)) = accent_of(name) {
+            let tag = if over { "mover" } else { "munder" };
+            let attr = if over { "accent" } else { "accentunder" };
+            self.out.push_str(&format!(r#"<{} {}="true">"#, tag, attr));
+            self.row(arg(0));
+            self.operator(accent);
+            self.out.push_str(&format!("</{}>", tag));
+            return;
+        }
+
+        match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                self.wrap("<mfrac>", &[arg(0), arg(1)], "</mfrac>");
+            }
+            "binom" | "dbinom" | "tbinom" => {
+                self.out.push_str("<mrow>");
+                self.operator("(");
+                self.wrap(
+                    r#"<mfrac linethickness="0">"#,
+                    &[arg(0), arg(1)],
+                    "</mfrac>",
+                );
+                self.operator(")");
+                self.out.push_str("</mrow>");
+            }
+            "sqrt" => match optional {
+                Some(index) => self.wrap("<mroot>", &[arg(0), index], "</mroot>"),
+                None => self.wrap("<msqrt>", &[arg(0)], "</msqrt>"),
+            },
+            "stackrel" | "overset" => self.wrap("<mover>", &[arg(1), arg(0)], "</mover>"),
+            "underset" => self.wrap("<munder>", &[arg(1), arg(0)], "</munder>"),
+            "xrightarrow" | "xleftarrow" => {
+                let arrow = if name == "xrightarrow" {
+                    "\u{2192}"
+                } else {
+                    "\u{2190}"
+                };
+                let tag = if optional.is_some() {
+                    "munderover"
+                } else {
+                    "mover"
+                };
+                self.out.push_str(&format!("<{}>", tag));
+                self.out
+                    .push_str(&format!(r#"<mo stretchy="true">{}</mo>"#, arrow));
+                if let Some(under) = optional {
+                    self.row(under);
+                }
+                self.row(arg(0));
+                self.out.push_str(&format!("</{}>", tag));
+            }
+            "operatorname" | "operatorname*" => {
+                let text = plain_text(arg(0));
+                self.element("mi", &text);
+            }
+            "pmod" => {
+                self.out.push_str("<mrow>");
+                self.operator("(");
+                self.element("mi", "mod");
+                self.row(arg(0));
+                self.operator(")");
+                self.out.push_str("</mrow>");
+            }
+            "boxed" => self.wrap(r#"<menclose notation="box">"#, &[arg(0)], "</menclose>"),
+            "cancel" => self.wrap(
+                r#"<menclose notation="updiagonalstrike">"#,
+                &[arg(0)],
+                "</menclose>",
+            ),
+            "phantom" => self.wrap("<mphantom>", &[arg(0)], "</mphantom>"),
+            "textcolor" => {
+                let color = plain_text(arg(0));
+                self.out
+                    .push_str(&format!(r#"<mstyle mathcolor="{}">"#, escape(&color)));
+                self.row(arg(1));
+                self.out.push_str("</mstyle>");
+            }
+            _ => self.error(&format!("\\{}", name)),
+        }
+    }
+
+    fn environment(&mut self, name: &str, rows: &[Vec<Vec<MathNode>>]) {
+        let fences = match name {
+            "matrix" | "smallmatrix" | "array" | "aligned" | "alignedat" | "gathered" | "split"
+            | "align" | "align*" | "alignat" | "alignat*" | "gather" | "gather*" | "subarray" => {
+                None
+            }
+            "pmatrix" => Some(("(", ")")),
+            "bmatrix" => Some(("[", "]")),
+            "Bmatrix" => Some(("{", "}")),
+            "vmatrix" => Some(("|", "|")),
+            "Vmatrix" => Some(("\u{2016}", "\u{2016}")),
+            "cases" => Some(("{", "")),
+            _ => {
+                self.error(&format!("\\begin{{{}}}", name));
+                return;
+            }
+        };
+
+        match fences {
+            Some((left, right)) => {
+                self.out.push_str("<mrow>");
+                self.fence(left);
+                self.table(rows);
+                self.fence(right);
+                self.out.push_str("</mrow>");
+            }
+            None => self.table(rows),
+        }
+    }
+
+    fn table(&mut self, rows: &[Vec<Vec<MathNode>>]) {
+        self.out.push_str("<mtable>");
+        for row in rows {
+            self.out.push_str("<mtr>");
+            for cell in row {
+                self.out.push_str("<mtd>");
+                self.row(cell);
+                self.out.push_str("</mtd>");
+            }
+            self.out.push_str("</mtr>");
+        }
+        self.out.push_str("</mtable>");
+    }
+}
+
+fn is_limits_operator(name: &str) -> bool {
+    matches!(
+        name,
+        "sum"
+            | "prod"
+            | "coprod"
+            | "bigcup"
+            | "bigcap"
+            | "bigoplus"
+            | "bigotimes"
+            | "lim"
+            | "liminf"
+            | "limsup"
+            | "max"
+            | "min"
+            | "sup"
+            | "inf"
+            | "det"
+            | "gcd"
+            | "Pr"
+    )
+}
+
+fn math_variant(name: &str) -> Option<&'static str> {
+    let variant = match name {
+        "mathbf" | "boldsymbol" | "bm" | "pmb" => "bold",
+        "mathrm" => "normal",
+        "mathit" => "italic",
+        "mathsf" => "sans-serif",
+        "mathtt" => "monospace",
+        "mathbb" => "double-struck",
+        "mathcal" | "mathscr" => "script",
+        "mathfrak" => "fraktur",
+        _ => return None,
+    };
+
+    Some(variant)
+}
+
+/// アクセント記号と, それを上に置くかどうか
+fn accent_of(name: &str) -> Option<(&'static str, bool)> {
+    let accent = match name {
+        "hat" | "widehat" => ("^", true),
+        "check" => ("ˇ", true),
+        "breve" => ("˘", true),
+        "acute" => ("´", true),
+        "grave" => ("`", true),
+        "bar" | "overline" => ("¯", true),
+        "tilde" | "widetilde" => ("~", true),
+        "vec" | "overrightarrow" => ("→", true),
+        "overleftarrow" => ("←", true),
+        "dot" => ("˙", true),
+        "ddot" => ("¨", true),
+        "dddot" => ("⃛", true),
+        "mathring" => ("˚", true),
+        "overbrace" => ("⏞", true),
+        "underline" => ("_", false),
+        "underbrace" => ("⏟", false),
+        _ => return None,
+    };
+
+    Some(accent)
+}
+
+fn plain_text(nodes: &[MathNode]) -> String {
+    nodes
+        .iter()
+        .map(|node| match node {
+            MathNode::Symbol { value } | MathNode::Number { value } => value.to_owned(),
+            MathNode::Group { body } => plain_text(body),
+            MathNode::Text { value, .. } => value.to_owned(),
+            MathNode::ControlSequence { name } => match symbol_of(name) {
+                Some((SymbolKind::Space, _)) => " ".to_string(),
+                Some((_, s)) => s.to_string(),
+                None => String::new(),
+            },
+            _ => String::new(),
+        })
+        .collect()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_ast::parse_math;
+
+    fn body(content: &str, display: bool) -> MathMl {
+        let mut result = render(&parse_math(content), display);
+        let open = result.markup.find('>').unwrap() + 1;
+        let close = result.markup.len() - "</math>".len();
+        result.markup = result.markup[open..close].to_string();
+        result
+    }
+
+    #[test]
+    fn 記号と添字() {
+        assert_eq!(
+            body(r"x_i^2 + \alpha", false).markup,
+            "<mrow><msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup><mo>+</mo><mi>α</mi></mrow>"
+        );
+        assert_eq!(
+            body(r"\sum_{k=1}^n a < b", true).markup,
+            "<mrow><munderover><mo>∑</mo><mrow><mi>k</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi></munderover><mi>a</mi><mo>&lt;</mo><mi>b</mi></mrow>"
+        );
+    }
+
+    #[test]
+    fn 分数と根号() {
+        assert_eq!(
+            body(r"\frac{1}{\sqrt[3]{x}}", false).markup,
+            "<mfrac><mn>1</mn><mroot><mi>x</mi><mn>3</mn></mroot></mfrac>"
+        );
+    }
+
+    #[test]
+    fn 書体とアクセント() {
+        assert_eq!(
+            body(r"\mathbb{R} \hat{x}", false).markup,
+            r#"<mrow><mi mathvariant="double-struck">R</mi><mover accent="true"><mi>x</mi><mo>^</mo></mover></mrow>"#
+        );
+    }
+
+    #[test]
+    fn 行列() {
+        assert_eq!(
+            body(r"\begin{pmatrix} a & b \end{pmatrix}", true).markup,
+            r#"<mrow><mo fence="true" stretchy="true">(</mo><mtable><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr></mtable><mo fence="true" stretchy="true">)</mo></mrow>"#
+        );
+    }
+
+    #[test]
+    fn 未対応の命令() {
+        let result = body(r"\foo x", false);
+        assert_eq!(
+            result.markup,
+            r"<mrow><merror><mtext>\foo</mtext></merror><mi>x</mi></mrow>"
+        );
+        assert_eq!(result.unsupported, vec![r"\foo"]);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::key::Key;
+use crate::node::Node;
+use crate::result_map::ResultMap;
+use crate::tex_char::TexChar;
+use crate::tex_chars::TexChars;
+
+/// 本文から取り除かれ, メタデータとして集められる宣言
+#[derive(Debug, Hash)]
+pub(super) enum MetadataDecl {
+    Title(MetaText),
+    Author(Vec<MetaText>),
+    Date(MetaText),
+    Abstract(Vec<Key>),
+}
+
+impl MetadataDecl {
+    pub(crate) fn match_command(name: &str) -> bool {
+        matches!(name, "title" | "author" | "date")
+    }
+
+    pub(crate) fn retain_keys(&mut self, f: &mut impl FnMut(&Key) -> bool) {
+        match self {
+            MetadataDecl::Title(x) | MetadataDecl::Date(x) => x.retain_keys(f),
+            MetadataDecl::Author(xs) => xs.iter_mut().for_each(|x| x.retain_keys(f)),
+            MetadataDecl::Abstract(ks) => ks.retain(|k| f(k)),
+        }
+    }
+}
+
+/// `\thanks` による脚注を伴う文字列
+#[derive(Debug, Hash, Clone)]
+pub(super) struct MetaText {
+    content: Vec<Key>,
+    footnotes: Vec<Vec<Key>>,
+}
+
+impl MetaText {
+    pub(crate) fn new(content: Vec<Key>, footnotes: Vec<Vec<Key>>) -> Self {
+        Self { content, footnotes }
+    }
+
+    pub(crate) fn content(&self) -> &[Key] {
+        &self.content
+    }
+
+    pub(crate) fn footnotes(&self) -> &[Vec<Key>] {
+        &self.footnotes
+    }
+
+    fn retain_keys(&mut self, f: &mut impl FnMut(&Key) -> bool) {
+        self.content.retain(|k| f(k));
+        for footnote in &mut self.footnotes {
+            footnote.retain(|k| f(k));
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Metadata {
+    pub title: Option<MetaText>,
+    pub authors: Vec<MetaText>,
+    pub date: Option<MetaText>,
+    pub abstract_: Option<Vec<Key>>,
+    pub maketitle: bool,
+}
+
+impl Metadata {
+    pub(crate) fn retain_keys(&mut self, f: &mut impl FnMut(&Key) -> bool) {
+        self.title.iter_mut().for_each(|x| x.retain_keys(f));
+        self.authors.iter_mut().for_each(|x| x.retain_keys(f));
+        self.date.iter_mut().for_each(|x| x.retain_keys(f));
+        if let Some(ks) = &mut self.abstract_ {
+            ks.retain(|k| f(k));
+        }
+    }
+}
+
+/// メタデータの宣言を本文から取り除き, 集める
+///
+/// 同じ宣言が複数回あれば LaTeX と同様に最後のものを採用する.
+pub(super) fn collect(rmap: &mut ResultMap) -> Metadata {
+    let mut metadata = Metadata {
+        maketitle: rmap.iter().any(|(_, node)| matches!(node, Node::MakeTitle)),
+        ..Default::default()
+    };
+
+    for (_, node) in rmap.detach(|node| matches!(node, Node::Metadata(_))) {
+        if let Node::Metadata(decl) = node {
+            match decl {
+                MetadataDecl::Title(x) => metadata.title = Some(x),
+                MetadataDecl::Author(xs) => metadata.authors = xs,
+                MetadataDecl::Date(x) => metadata.date = Some(x),
+                MetadataDecl::Abstract(ks) => metadata.abstract_ = Some(ks),
+            }
+        }
+    }
+
+    metadata
+}
+
+/// トップレベルの `\and` で著者を分ける
+pub(super) fn split_authors(cs: TexChars) -> Vec<TexChars> {
+    let mut authors = Vec::new();
+    let mut author = Vec::new();
+    let mut depth = 0;
+    let mut cs = cs;
+
+    loop {
+        if depth == 0 && cs.peek_command_name().as_deref() == Some("and") {
+            cs.consume_command_name();
+            authors.push(std::mem::take(&mut author).into_iter().collect());
+            continue;
+        }
+
+        let Some(c) = cs.next() else {
+            break;
+        };
+        match c {
+            TexChar::LBrace => depth += 1,
+            TexChar::RBrace => depth -= 1,
+            TexChar::Backslash => {
+                // `\\` や `\{` を1文字として扱う
+                author.push(c);
+                if let Some(c) = cs.next() {
+                    author.push(c);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        author.push(c);
+    }
+    authors.push(author.into_iter().collect());
+
+    authors
+}
+
+/// `\thanks{...}` を取り出す
+///
+/// 戻り値は `\thanks` を除いた残りと, 各 `\thanks` の中身.
+pub(super) fn extract_thanks(cs: TexChars) -> (TexChars, Vec<TexChars>) {
+    let mut rest = Vec::new();
+    let mut thanks = Vec::new();
+    let mut cs = cs;
+
+    loop {
+        if cs.peek_command_name().as_deref() == Some("thanks") {
+            cs.consume_command_name();
+            thanks.push(cs.read_group().unwrap_or_default());
+            continue;
+        }
+
+        let Some(c) = cs.next() else {
+            break;
+        };
+        if c == TexChar::Backslash {
+            rest.push(c);
+            if let Some(c) = cs.next() {
+                rest.push(c);
+            }
+            continue;
+        }
+        rest.push(c);
+    }
+
+    (rest.into_iter().collect(), thanks)
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn split_authors() {
+        let authors = super::split_authors(
+            TexChars::from_str(r"Alice \and {Bob \and Carol}\and Dave\\ Univ").unwrap(),
+        );
+        let authors: Vec<_> = authors
+            .into_iter()
+            .map(|x| x.into_content_string())
+            .collect();
+
+        assert_eq!(authors, vec!["Alice", "{Bob \\and Carol}", r"Dave\\ Univ"]);
+    }
+
+    #[test]
+    fn extract_thanks() {
+        let (rest, thanks) =
+            super::extract_thanks(TexChars::from_str(r"Alice\thanks{Univ. {A}} Smith").unwrap());
+
+        assert_eq!(rest.into_content_string(), "Alice Smith");
+        assert_eq!(
+            thanks
+                .into_iter()
+                .map(|x| x.into_content_string())
+                .collect::<Vec<_>>(),
+            vec!["Univ. {A}"]
+        );
+    }
+}
@@ -1,5 +1,13 @@
+use crate::citation::CitationInfo;
+use crate::figure::{FigureInfo, ImageInfo};
+use crate::heading::HeadingInfo;
 use crate::key::Key;
+use crate::list::ListInfo;
+use crate::macros::{Definition, EnvironmentInfo};
 use crate::math_expr::MathExprParseResult;
+use crate::metadata::MetadataDecl;
+use crate::reference::ReferenceInfo;
+use crate::table::TableInfo;
 
 #[derive(Debug)]
 pub(super) enum Node {
@@ -8,9 +16,36 @@ pub(super) enum Node {
     RawString(String),
     InlineCommand(Option<String>),
     MathExpr(MathExprParseResult),
+    Heading(HeadingInfo),
+    Table(TableInfo),
+    List(ListInfo),
+    Figure(FigureInfo),
+    Image(ImageInfo),
+    Metadata(MetadataDecl),
+    Definition(Definition),
+    Environment(EnvironmentInfo),
+    Label(String),
+    Reference(ReferenceInfo),
+    Citation(CitationInfo),
+    MakeTitle,
 }
 
 impl Node {
+    /// 子の Key のうち, 条件に合うものだけを残す
+    pub(crate) fn retain_keys(&mut self, mut f: impl FnMut(&Key) -> bool) {
+        let f = &mut f;
+        match self {
+            Node::ParagraphList(Some(ks)) | Node::Paragraph(Some(ks)) => ks.retain(|k| f(k)),
+            Node::Heading(info) => info.retain_keys(f),
+            Node::Table(info) => info.retain_keys(f),
+            Node::List(info) => info.retain_keys(f),
+            Node::Figure(info) => info.retain_keys(f),
+            Node::Metadata(decl) => decl.retain_keys(f),
+            Node::Environment(info) => info.retain_keys(f),
+            _ => {}
+        }
+    }
+
     // pub fn is_ok(&self) -> bool {
     //     use Node::*;
     //
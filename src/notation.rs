@@ -0,0 +1,165 @@
+use crate::key::Key;
+use crate::macros::Macros;
+use crate::node::Node;
+use crate::result_map::ResultMap;
+use std::collections::HashMap;
+
+/// 記号一覧の一項目
+#[derive(Debug)]
+pub(super) struct Notation {
+    /// `\R` のように `\` を含む名前
+    pub name: String,
+    /// 文書やライブラリで定義された命令か
+    pub user_macro: bool,
+    /// 最初に現れた数式
+    pub first: Key,
+    /// 最初に現れた数式の中身での位置 (文字単位, 終わりを含まない).
+    /// 展開や書き方の統一で中身が変わっていれば None
+    pub span: Option<(usize, usize)>,
+    /// 定義しているディスプレイ数式
+    pub definition: Option<Key>,
+    /// 集めたときの `first` の中身
+    content: String,
+}
+
+/// 数式の構造を表すだけで記号ではない命令
+fn is_structural(name: &str) -> bool {
+    matches!(
+        name,
+        "begin" | "end" | "left" | "right" | "middle" | "tag" | "label" | "nonumber" | "notag"
+    )
+}
+
+/// 定義を表す演算子
+const DEFINITION_OPERATORS: [&str; 3] = [":=", "\\defeq", "\\coloneqq"];
+
+/// 数式で使われた制御語を出現順に集める
+///
+/// `:=`, `\defeq`, `\coloneqq` のあるディスプレイ数式では, 演算子の左辺にある制御語をそこで定義されたものとする.
+pub(super) fn collect(rmap: &ResultMap, macros: &Macros) -> Vec<Notation> {
+    let mut notations: Vec<Notation> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for (key, node) in rmap.iter() {
+        let Node::MathExpr(me) = node else {
+            continue;
+        };
+        let content = me.content_str();
+
+        for (name, span) in control_words(content) {
+            if is_structural(&name[1..]) || index.contains_key(&name) {
+                continue;
+            }
+            index.insert(name.clone(), notations.len());
+            notations.push(Notation {
+                user_macro: macros.get(&name).is_some(),
+                name,
+                first: key.clone(),
+                span: Some(span),
+                definition: None,
+                content: content.to_string(),
+            });
+        }
+
+        if me.is_display() {
+            for name in defined_names(content) {
+                if let Some(&i) = index.get(&name) {
+                    notations[i].definition.get_or_insert_with(|| key.clone());
+                }
+            }
+        }
+    }
+
+    notations
+}
+
+/// 展開や書き方の統一で中身の変わった数式では, 位置が合わないので捨てる
+pub(super) fn forget_moved_spans(notations: &mut [Notation], rmap: &ResultMap) {
+    for notation in notations {
+        let same = matches!(
+            rmap.get(&notation.first),
+            Some(Node::MathExpr(me)) if me.content_str() == notation.content
+        );
+        if !same {
+            notation.span = None;
+        }
+    }
+}
+
+/// 制御語とその位置. `\,` などの制御記号は含めない
+pub(super) fn control_words(content: &str) -> Vec<(String, (usize, usize))> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut words = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i] != '\\' {
+            i += 1;
+            continue;
+        }
+        let len = chars[i + 1..]
+            .iter()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .count();
+        if len == 0 {
+            i += 2;
+            continue;
+        }
+        let end = i + 1 + len;
+        words.push((chars[i..end].iter().collect(), (i, end)));
+        i = end;
+    }
+
+    words
+}
+
+/// 各行の定義の演算子の左辺にある制御語
+fn defined_names(content: &str) -> Vec<String> {
+    content
+        .split("\\\\")
+        .filter_map(|row| {
+            DEFINITION_OPERATORS
+                .iter()
+                .filter_map(|op| row.find(op))
+                .min()
+                .map(|pos| &row[..pos])
+        })
+        .flat_map(|lhs| control_words(lhs).into_iter().map(|(name, _)| name))
+        .filter(|name| !is_structural(&name[1..]))
+        .collect()
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn 制御語の位置() {
+        assert_eq!(
+            control_words(r"\alpha\,x + \R^2"),
+            vec![
+                (r"\alpha".to_string(), (0, 6)),
+                (r"\R".to_string(), (12, 14))
+            ]
+        );
+    }
+
+    #[test]
+    fn 定義の左辺() {
+        assert_eq!(
+            defined_names(r"\norm{x} := \sqrt{x \cdot x}"),
+            vec![r"\norm"]
+        );
+        assert_eq!(
+            defined_names(r"\R_+ &\coloneqq \{x\} \\ f(x) &= \Gamma(x)"),
+            vec![r"\R"]
+        );
+        assert_eq!(
+            defined_names(r"\begin{aligned} \lambda \defeq 1 \end{aligned}"),
+            vec![r"\lambda"]
+        );
+        assert!(defined_names(r"\alpha = 1").is_empty());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::asset::AssetResolver;
+use crate::bib::Bibliography;
+use crate::citation::CitationStyle;
+use crate::expand::Limits;
+use crate::library::MacroLibrary;
+
+#[derive(Default)]
+pub struct ParseOptions {
+    asset_resolver: Option<Box<dyn AssetResolver>>,
+    math_ast: bool,
+    mathml: bool,
+    check_katex: bool,
+    dollar_heuristics: bool,
+    canonical_math: bool,
+    expand_macros: bool,
+    max_expansion_depth: Option<usize>,
+    max_expansion_tokens: Option<usize>,
+    macro_library: Option<MacroLibrary>,
+    bibliography: Option<Bibliography>,
+    citation_style: CitationStyle,
+}
+
+impl ParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn asset_resolver(mut self, resolver: impl AssetResolver + 'static) -> Self {
+        self.asset_resolver = Some(Box::new(resolver));
+        self
+    }
+
+    /// 数式の構文木を `ast` として出力する
+    pub fn math_ast(mut self, enabled: bool) -> Self {
+        self.math_ast = enabled;
+        self
+    }
+
+    /// 数式を MathML に変換して `mathml` として出力する
+    pub fn mathml(mut self, enabled: bool) -> Self {
+        self.mathml = enabled;
+        self
+    }
+
+    /// KaTeX が対応していない制御綴や環境を警告する
+    pub fn check_katex(mut self, enabled: bool) -> Self {
+        self.check_katex = enabled;
+        self
+    }
+
+    /// `$5 and $10` のような通貨の `$` を数式として読まない
+    pub fn dollar_heuristics(mut self, enabled: bool) -> Self {
+        self.dollar_heuristics = enabled;
+        self
+    }
+
+    /// 数式の空白や添字の波括弧, 区切りの書き方をそろえる
+    ///
+    /// 同じ表示になる数式に同じ Key が振られるので, 描画結果を使い回しやすくなる.
+    pub fn canonical_math(mut self, enabled: bool) -> Self {
+        self.canonical_math = enabled;
+        self
+    }
+
+    /// 数式の中の, 文書で定義された命令を展開する
+    ///
+    /// MathML や装飾なしの文字列など, 利用者の定義を解さない出力のために使う.
+    pub fn expand_macros(mut self, enabled: bool) -> Self {
+        self.expand_macros = enabled;
+        self
+    }
+
+    /// 入れ子になった展開の深さの上限. 既定値は 64
+    pub fn max_expansion_depth(mut self, depth: usize) -> Self {
+        self.max_expansion_depth = Some(depth);
+        self
+    }
+
+    /// 一つの数式での展開の回数と出力の字句数の合計の上限. 既定値は 10000
+    pub fn max_expansion_tokens(mut self, tokens: usize) -> Self {
+        self.max_expansion_tokens = Some(tokens);
+        self
+    }
+
+    /// 文書より先に読み込んでおく命令や環境の定義
+    pub fn macro_library(mut self, library: MacroLibrary) -> Self {
+        self.macro_library = Some(library);
+        self
+    }
+
+    /// `\cite` を解決する文献
+    pub fn bibliography(mut self, bibliography: Bibliography) -> Self {
+        self.bibliography = Some(bibliography);
+        self
+    }
+
+    /// 引用の表記の形式. 既定値は番号
+    pub fn citation_style(mut self, style: CitationStyle) -> Self {
+        self.citation_style = style;
+        self
+    }
+
+    pub(crate) fn get_asset_resolver(&self) -> Option<&dyn AssetResolver> {
+        self.asset_resolver.as_deref()
+    }
+
+    pub(crate) fn get_macro_library(&self) -> Option<&MacroLibrary> {
+        self.macro_library.as_ref()
+    }
+
+    pub(crate) fn get_bibliography(&self) -> Option<&Bibliography> {
+        self.bibliography.as_ref()
+    }
+
+    pub(crate) fn get_citation_style(&self) -> CitationStyle {
+        self.citation_style
+    }
+
+    pub(crate) fn is_math_ast_enabled(&self) -> bool {
+        self.math_ast
+    }
+
+    pub(crate) fn is_mathml_enabled(&self) -> bool {
+        self.mathml
+    }
+
+    pub(crate) fn is_katex_check_enabled(&self) -> bool {
+        self.check_katex
+    }
+
+    pub(crate) fn is_dollar_heuristics_enabled(&self) -> bool {
+        self.dollar_heuristics
+    }
+
+    pub(crate) fn is_canonical_math_enabled(&self) -> bool {
+        self.canonical_math
+    }
+
+    pub(crate) fn is_macro_expansion_enabled(&self) -> bool {
+        self.expand_macros
+    }
+
+    pub(crate) fn expansion_limits(&self) -> Limits {
+        Limits {
+            depth: self.max_expansion_depth.unwrap_or(64),
+            tokens: self.max_expansion_tokens.unwrap_or(10_000),
+        }
+    }
+}
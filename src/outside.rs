@@ -1,22 +1,153 @@
-use crate::outside::schema::{convert_key, convert_to_entry, ParseResult};
+use crate::key::Key;
+use crate::options::ParseOptions;
+use crate::outside::schema::{
+    convert_diagnostics, convert_environments, convert_key, convert_labels, convert_macros,
+    convert_metadata, convert_notation, convert_outline, convert_theorems, convert_to_entry,
+    DocumentTables, ParseResult, ParseResultOk, WorkspaceDocument, WorkspaceResult,
+};
 use crate::parser::{parse_paragraphs, ParseOk};
+use crate::plain_text;
+use crate::theorem_graph::{self, TheoremGraph};
+use crate::workspace::{self, Workspace};
+use std::collections::HashMap;
 
 pub fn parse_paragraphs_to_json(input: &str) -> ParseResult {
-    let result = parse_paragraphs(input);
+    parse_paragraphs_to_json_with(input, &ParseOptions::default())
+}
+
+pub fn parse_paragraphs_to_json_with(input: &str, options: &ParseOptions) -> ParseResult {
+    let result = parse_paragraphs(input, options);
 
     match result {
         Err(e) => ParseResult::new_error(e.to_string()),
-        Ok(ParseOk { rmap, char_count }) => {
-            let hash_table = rmap.hash_table();
-            let root = convert_key(rmap.root(), &hash_table);
-            let entries = rmap
-                .into_iter()
-                .map(|(key, node)| convert_to_entry(key, node, &hash_table))
-                .collect::<Vec<_>>();
+        Ok(ok) => {
+            let hash_table = ok.rmap.hash_table(&ok.dependencies);
+            convert_result(ok, &hash_table, &HashMap::new())
+        }
+    }
+}
 
-            ParseResult::new_ok(root, entries, char_count)
+/// 複数の文書を読み, 文書をまたぐ参照を解決する
+///
+/// 参照先が他の文書にあれば, 参照の `document` にその文書の名前が入り,
+/// `target` はその文書の項目を指す.
+pub fn parse_workspace_to_json(workspace: &Workspace, options: &ParseOptions) -> WorkspaceResult {
+    let mut parsed = Vec::new();
+    let mut documents = Vec::new();
+    for (name, source) in workspace.documents() {
+        match parse_paragraphs(source, options) {
+            Ok(ok) => parsed.push((name, ok)),
+            Err(e) => documents.push(WorkspaceDocument {
+                name: name.to_owned(),
+                result: ParseResult::new_error(e.to_string()),
+            }),
         }
     }
+    workspace::resolve(&mut parsed);
+
+    let hash_tables: Vec<_> = parsed
+        .iter()
+        .map(|(_, ok)| ok.rmap.hash_table(&ok.dependencies))
+        .collect();
+    let tables: DocumentTables = parsed
+        .iter()
+        .zip(&hash_tables)
+        .map(|((name, _), table)| (*name, table))
+        .collect();
+    let labels = parsed
+        .iter()
+        .zip(&hash_tables)
+        .flat_map(|((name, ok), table)| convert_labels(name, &ok.labels, table))
+        .collect();
+
+    for ((name, ok), hash_table) in parsed.into_iter().zip(&hash_tables) {
+        documents.push(WorkspaceDocument {
+            name: name.to_owned(),
+            result: convert_result(ok, hash_table, &tables),
+        });
+    }
+    let order: Vec<_> = workspace.documents().map(|(name, _)| name).collect();
+    documents.sort_by_key(|x| order.iter().position(|name| *name == x.name));
+
+    WorkspaceResult { documents, labels }
+}
+
+fn convert_result(
+    ok: ParseOk,
+    hash_table: &HashMap<Key, String>,
+    documents: &DocumentTables,
+) -> ParseResult {
+    let ParseOk {
+        rmap,
+        char_count,
+        diagnostics,
+        metadata,
+        preamble,
+        macros,
+        notation,
+        dependencies,
+        bibliography,
+        ..
+    } = ok;
+
+    // 根から Key を振るので, 根の Key は必ずある
+    let root = convert_key(rmap.root(), hash_table).expect("the root is always hashed");
+    let outline = convert_outline(&rmap, hash_table);
+    let diagnostics = convert_diagnostics(diagnostics, hash_table);
+    let metadata = convert_metadata(metadata, &rmap, hash_table);
+    let environment_definitions = convert_environments(&macros);
+    let theorem_definitions = convert_theorems(&macros);
+    let notation = convert_notation(notation, hash_table);
+    let (macros, macro_definitions) = convert_macros(&macros);
+    let entries = rmap
+        .into_iter()
+        .filter_map(|(key, node)| convert_to_entry(key, node, hash_table, &dependencies, documents))
+        .collect::<Vec<_>>();
+
+    ParseResult::new_ok(ParseResultOk {
+        root,
+        entries,
+        count: char_count,
+        outline,
+        diagnostics,
+        metadata,
+        preamble,
+        macros,
+        macro_definitions,
+        environment_definitions,
+        theorem_definitions,
+        notation,
+        bibliography: bibliography.into_iter().map(Into::into).collect(),
+    })
+}
+
+/// 文書を装飾なしの文字列に変換する
+///
+/// 検索用の索引や通知のプレビューに使う. 数式は Unicode の文字列になる.
+pub fn parse_paragraphs_to_plain_text(input: &str) -> Result<String, ParseError> {
+    parse_paragraphs_to_plain_text_with(input, &ParseOptions::default())
+}
+
+pub fn parse_paragraphs_to_plain_text_with(
+    input: &str,
+    options: &ParseOptions,
+) -> Result<String, ParseError> {
+    let ParseOk { rmap, .. } = parse_paragraphs(input, options)?;
+    Ok(plain_text::export(&rmap))
+}
+
+/// 定理, 補題, 証明から参照しているラベルへの依存関係のグラフを作る
+pub fn parse_theorem_graph(
+    input: &str,
+    options: &ParseOptions,
+) -> Result<TheoremGraph, ParseError> {
+    let ParseOk {
+        rmap,
+        labels,
+        macros,
+        ..
+    } = parse_paragraphs(input, options)?;
+    Ok(theorem_graph::build(&rmap, &labels, &macros))
 }
 
 pub(crate) const MAX_INPUT_LENGTH: usize = 100_000;
@@ -28,4 +159,1391 @@ pub enum ParseError {
     TooLongInput,
 }
 
-mod schema;
+pub(crate) mod schema;
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bib::Bibliography;
+    use crate::citation::CitationStyle;
+    use crate::library::MacroLibrary;
+    use serde_json::{json, Value};
+
+    fn to_value(input: &str) -> Value {
+        serde_json::to_value(parse_paragraphs_to_json(input)).unwrap()
+    }
+
+    #[test]
+    fn outline() {
+        let input = r"\section{Intro}
+        本文
+
+        \subsection{Detail}
+        \section*{Appendix}";
+
+        let result = parse_paragraphs_to_json(input);
+        let ParseResult::Ok(ok) = &result else {
+            panic!()
+        };
+        let outline = ok.outline();
+        assert_eq!(outline.len(), 2);
+        assert_eq!(outline[0].title, "Intro");
+        assert_eq!(outline[0].children[0].number.as_deref(), Some("1.1"));
+        assert_eq!(outline[1].number, None);
+        assert_eq!(outline[1].anchor, "appendix");
+
+        let value = to_value(input);
+        assert_eq!(value["outline"][0]["children"][0]["title"], json!("Detail"));
+    }
+
+    #[test]
+    fn outline_anchors_are_unique() {
+        let result = parse_paragraphs_to_json(r"\section{Intro}\section{Intro}\section{Intro 1}");
+        let ParseResult::Ok(ok) = &result else {
+            panic!()
+        };
+        let anchors: Vec<_> = ok.outline().iter().map(|x| x.anchor.as_str()).collect();
+        assert_eq!(anchors, vec!["intro", "intro-1", "intro-1-1"]);
+    }
+
+    /// `kind` の最初の項目の Key
+    fn first_key(input: &str, kind: &str) -> Value {
+        let value = to_value(input);
+        let entry = value["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|x| x["value"]["kind"] == kind)
+            .unwrap();
+        entry["key"].clone()
+    }
+
+    #[test]
+    fn heading_key_does_not_depend_on_position() {
+        assert_eq!(
+            first_key(r"\section{Intro}", "heading"),
+            first_key("前の段落\n\n\\section{Intro}", "heading")
+        );
+        assert_ne!(
+            first_key(r"\section{Intro}", "heading"),
+            first_key(r"\section{Outro}", "heading")
+        );
+    }
+
+    #[test]
+    fn table_key_does_not_depend_on_position() {
+        let table = r"\begin{tabular}{ll} a & b \\ c & d \end{tabular}";
+        assert_eq!(
+            first_key(table, "table"),
+            first_key(&format!("前の段落\n\n{}", table), "table")
+        );
+        // セルの区切りが変われば Key も変わる
+        assert_ne!(
+            first_key(table, "table"),
+            first_key(r"\begin{tabular}{ll} a b & \\ c & d \end{tabular}", "table")
+        );
+    }
+
+    #[test]
+    fn figure_key_does_not_depend_on_position() {
+        let figure = r"\begin{figure}
+        \includegraphics{a.png}
+        \caption{Plot}
+        \end{figure}";
+        assert_eq!(
+            first_key(figure, "figure"),
+            first_key(&format!("前の段落\n\n{}", figure), "figure")
+        );
+    }
+
+    #[test]
+    fn list_key_does_not_depend_on_position() {
+        let list = r"\begin{enumerate}
+        \item one
+        \item two
+        \end{enumerate}";
+        assert_eq!(
+            first_key(list, "list"),
+            first_key(&format!("前の段落\n\n{}", list), "list")
+        );
+    }
+
+    /// 項目やメタデータから参照されているのに, 取り除かれた Key
+    fn dangling_keys(input: &str) -> Vec<Key> {
+        let ParseOk { rmap, metadata, .. } =
+            parse_paragraphs(input, &ParseOptions::default()).unwrap();
+
+        let mut referenced = Vec::new();
+        for text in metadata
+            .title
+            .iter()
+            .chain(&metadata.authors)
+            .chain(&metadata.date)
+        {
+            referenced.extend(text.content().iter().cloned());
+            referenced.extend(text.footnotes().iter().flatten().cloned());
+        }
+        referenced.extend(metadata.abstract_.iter().flatten().cloned());
+
+        let keys: Vec<_> = rmap.iter().map(|(key, _)| key.clone()).collect();
+        for (_, mut node) in rmap {
+            node.retain_keys(|k| {
+                referenced.push(k.clone());
+                true
+            });
+        }
+        referenced.retain(|k| !keys.contains(k));
+        referenced
+    }
+
+    #[test]
+    fn nested_metadata() {
+        let inputs = [
+            r"\author{a\title{b}}",
+            r"\title{\author{b}}",
+            r"\begin{abstract}\title{x}\end{abstract}",
+            r"\begin{tabular}{ll} a & \title{x} \\ \end{tabular}",
+            r"\section{A \title{x}}",
+            r"\begin{figure}\caption{\title{x}}\end{figure}",
+            r"\begin{itemize}\item \title{x} \item b\end{itemize}",
+            r"\newtheorem{thm}{Theorem}\begin{thm}\title{x}\end{thm}",
+        ];
+        for input in inputs {
+            assert_eq!(dangling_keys(input), vec![], "{}", input);
+            // Key のない項目を出力しない
+            assert!(matches!(
+                parse_paragraphs_to_json(input),
+                ParseResult::Ok(_)
+            ));
+        }
+
+        let value = to_value(r"\author{a\title{b}}");
+        assert_eq!(
+            value["metadata"]["authors"][0]["keys"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn nested_definitions() {
+        let inputs = [
+            r"\title{\newcommand{\R}{x}}",
+            r"\begin{abstract}\newcommand{\R}{x}\end{abstract}",
+            r"\begin{tabular}{ll} a & \newcommand{\R}{x} \\ \end{tabular}",
+            r"\section{A \newcommand{\R}{x}}",
+            r"\begin{figure}\caption{\newcommand{\R}{x}}\end{figure}",
+            r"\begin{itemize}\item \newcommand{\R}{x} \item b\end{itemize}",
+            r"\newtheorem{thm}{Theorem}\begin{thm}\newcommand{\R}{x}\end{thm}",
+        ];
+        for input in inputs {
+            assert_eq!(dangling_keys(input), vec![], "{}", input);
+            let value = to_value(input);
+            assert_eq!(value["macros"]["\\R"], json!("x"), "{}", input);
+        }
+    }
+
+    #[test]
+    fn nested_nocite() {
+        let inputs = [
+            r"\title{\nocite{a}}",
+            r"\begin{abstract}\nocite{a}\end{abstract}",
+            r"\begin{tabular}{ll} a & \nocite{a} \\ \end{tabular}",
+            r"\section{A \nocite{a}}",
+            r"\begin{figure}\caption{\nocite{a}}\end{figure}",
+            r"\begin{itemize}\item \nocite{a} \item b\end{itemize}",
+            r"\newtheorem{thm}{Theorem}\begin{thm}\nocite{a}\end{thm}",
+        ];
+        for input in inputs {
+            assert_eq!(dangling_keys(input), vec![], "{}", input);
+            let value = to_value(input);
+            let kinds: Vec<_> = value["entries"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|x| &x["value"]["kind"])
+                .collect();
+            assert!(!kinds.contains(&&json!("cite")), "{}", input);
+        }
+    }
+
+    #[test]
+    fn environment_key_does_not_depend_on_position() {
+        let define = r"\newenvironment{note}{}{}";
+        let env = r"\begin{note} 本文 \end{note}";
+        assert_eq!(
+            first_key(&format!("{}\n\n{}", define, env), "env"),
+            first_key(&format!("{}\n\n前の段落\n\n{}", define, env), "env")
+        );
+    }
+
+    #[test]
+    fn heading_entry() {
+        let value = to_value(r"\section[Short]{Long $X$}");
+        let heading = value["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|x| x["value"]["kind"] == "heading")
+            .unwrap();
+
+        assert_eq!(heading["value"]["level"], json!(1));
+        assert_eq!(heading["value"]["number"], json!("1"));
+        assert_eq!(heading["value"]["short_title"], json!("Short"));
+        assert_eq!(heading["value"]["anchor"], json!("long-x"));
+        assert_eq!(heading["value"]["title"].as_array().unwrap().len(), 2);
+        assert_eq!(value["outline"][0]["title"], json!("Short"));
+    }
+
+    #[test]
+    fn figure_entry() {
+        let input = r"\graphicspath{{figs/}}
+        \begin{figure}[htbp]
+        \centering
+        \includegraphics[width=0.5\linewidth]{plot}
+        \includegraphics{missing.png}
+        \caption[短い]{グラフ $y = x^2$}
+        \label{fig:plot}
+        \end{figure}";
+
+        let resolver =
+            |path: &str| (path == "figs/plot.png").then(|| format!("https://example.com/{}", path));
+        let options = ParseOptions::new().asset_resolver(resolver);
+        let value = serde_json::to_value(parse_paragraphs_to_json_with(input, &options)).unwrap();
+
+        let entries = value["entries"].as_array().unwrap();
+        let figure = entries
+            .iter()
+            .find(|x| x["value"]["kind"] == "figure")
+            .unwrap();
+
+        assert_eq!(figure["value"]["env"], json!("figure"));
+        assert_eq!(figure["value"]["placement"], json!("htbp"));
+        assert_eq!(figure["value"]["label"], json!("fig:plot"));
+        assert_eq!(figure["value"]["short_caption"], json!("短い"));
+        assert_eq!(figure["value"]["caption"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            figure["value"]["images"][0],
+            json!({
+                "path": "plot",
+                "options": {"width": "0.5\\linewidth"},
+                "resolved": "https://example.com/figs/plot.png",
+            })
+        );
+        assert_eq!(figure["value"]["images"][1]["resolved"], json!(null));
+
+        assert_eq!(value["diagnostics"].as_array().unwrap().len(), 1);
+        assert_eq!(value["diagnostics"][0]["code"], json!("unresolved_image"));
+        assert_eq!(value["diagnostics"][0]["severity"], json!("error"));
+        assert_eq!(value["diagnostics"][0]["key"], figure["key"]);
+
+        // graphicspath は本文に残らない
+        assert!(entries.iter().all(|x| x["value"]["kind"] != "il_cmd"));
+    }
+
+    #[test]
+    fn table_float_with_subfigures() {
+        let value = to_value(
+            r"\begin{table}
+        \begin{tabular}{c}a\end{tabular}
+        \caption{表}
+        \end{table}
+
+        \begin{figure}
+        \begin{subfigure}{0.4\textwidth}\includegraphics{a}\caption{A}\end{subfigure}
+        \begin{subfigure}{0.4\textwidth}\includegraphics{b}\end{subfigure}
+        \end{figure}",
+        );
+        let entries = value["entries"].as_array().unwrap();
+        let floats: Vec<_> = entries
+            .iter()
+            .filter(|x| x["value"]["kind"] == "figure")
+            .collect();
+
+        let table_key = &floats[0]["value"]["content"][0];
+        let table = entries.iter().find(|x| &x["key"] == table_key).unwrap();
+        assert_eq!(table["value"]["kind"], json!("table"));
+
+        let subfigures = floats[1]["value"]["subfigures"].as_array().unwrap();
+        assert_eq!(subfigures.len(), 2);
+        assert_eq!(subfigures[1]["images"][0]["path"], json!("b"));
+        assert_eq!(value["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn metadata() {
+        let value = to_value(
+            r"\title{層と $X$ のコホモロジー}
+        \author{Alice\thanks{Univ. A} \and Bob}
+        \date{2024}
+        \maketitle
+
+        \begin{abstract}
+        要旨1.
+
+        要旨2.
+        \end{abstract}
+
+        本文",
+        );
+        let metadata = &value["metadata"];
+        let entries = value["entries"].as_array().unwrap();
+
+        assert_eq!(metadata["title"]["text"], json!("層と X のコホモロジー"));
+        assert_eq!(metadata["title"]["keys"].as_array().unwrap().len(), 3);
+        assert_eq!(metadata["authors"][0]["text"], json!("Alice"));
+        assert_eq!(
+            metadata["authors"][0]["footnotes"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(metadata["authors"][1]["text"], json!("Bob"));
+        assert_eq!(metadata["date"]["text"], json!("2024"));
+        assert_eq!(metadata["abstract"].as_array().unwrap().len(), 2);
+        assert_eq!(metadata["maketitle"], json!(true));
+
+        // 宣言は本文から取り除かれ, \maketitle の位置だけが残る
+        let root = entries.iter().find(|x| x["key"] == value["root"]).unwrap();
+        let kinds: Vec<_> = root["value"]["keys"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|k| entries.iter().find(|x| &x["key"] == k).unwrap()["value"]["kind"].clone())
+            .collect();
+        assert_eq!(kinds, vec![json!("maketitle"), json!("para")]);
+
+        // 参照されているキーはすべて entries に含まれる
+        let footnote = &metadata["authors"][0]["footnotes"][0][0];
+        assert!(entries.iter().any(|x| &x["key"] == footnote));
+    }
+
+    #[test]
+    fn table_entry() {
+        let value = to_value(
+            r"表は
+        \begin{tabular}{|l|c|}
+        \hline
+        名前 & $x^2$ \\ \hline
+        \multicolumn{2}{r}{合計} \\
+        \hline
+        \end{tabular}
+        の通り.",
+        );
+        let entries = value["entries"].as_array().unwrap();
+        let table = entries
+            .iter()
+            .find(|x| x["value"]["kind"] == "table")
+            .unwrap();
+
+        assert_eq!(table["value"]["env"], json!("tabular"));
+        assert_eq!(
+            table["value"]["columns"][1],
+            json!({"align": "c", "width": null})
+        );
+        assert_eq!(table["value"]["vrules"], json!([1, 1, 1]));
+        assert_eq!(
+            table["value"]["rows"][0]["rules"],
+            json!([{"kind": "hline"}])
+        );
+        assert_eq!(table["value"]["rows"][1]["cells"][0]["colspan"], json!(2));
+        assert_eq!(table["value"]["rows"][1]["cells"][0]["align"], json!("r"));
+        assert_eq!(table["value"]["trailing_rules"], json!([{"kind": "hline"}]));
+
+        let math_key = &table["value"]["rows"][0]["cells"][1]["keys"][0];
+        let math = entries.iter().find(|x| &x["key"] == math_key).unwrap();
+        assert_eq!(math["value"]["kind"], json!("il_math"));
+
+        let paras = entries
+            .iter()
+            .filter(|x| x["value"]["kind"] == "para")
+            .count();
+        assert_eq!(paras, 2);
+    }
+
+    #[test]
+    fn math_ast() {
+        let input = r"$x^2$ と \[\frac{a}{b}\]";
+
+        let value = to_value(input);
+        let entries = value["entries"].as_array().unwrap();
+        assert!(entries.iter().all(|x| x["value"].get("ast").is_none()));
+
+        let options = ParseOptions::new().math_ast(true);
+        let value = serde_json::to_value(parse_paragraphs_to_json_with(input, &options)).unwrap();
+        let entries = value["entries"].as_array().unwrap();
+        let il_math = entries
+            .iter()
+            .find(|x| x["value"]["kind"] == "il_math")
+            .unwrap();
+        assert_eq!(
+            il_math["value"]["ast"],
+            json!([{
+                "type": "scripts",
+                "base": {"type": "symbol", "value": "x"},
+                "sub": null,
+                "sup": {"type": "number", "value": "2"},
+            }])
+        );
+        let ds_math = entries
+            .iter()
+            .find(|x| x["value"]["kind"] == "ds_math")
+            .unwrap();
+        assert_eq!(ds_math["value"]["ast"][0]["name"], json!("frac"));
+    }
+
+    #[test]
+    fn mathml() {
+        let options = ParseOptions::new().mathml(true);
+        let value = serde_json::to_value(parse_paragraphs_to_json_with(
+            r"$\frac{1}{2}$ と $\unknown x$",
+            &options,
+        ))
+        .unwrap();
+        let entries = value["entries"].as_array().unwrap();
+        let maths: Vec<_> = entries
+            .iter()
+            .filter(|x| x["value"]["kind"] == "il_math")
+            .collect();
+
+        assert_eq!(
+            maths[0]["value"]["mathml"],
+            json!(
+                r#"<math xmlns="http://www.w3.org/1998/Math/MathML" display="inline"><mfrac><mn>1</mn><mn>2</mn></mfrac></math>"#
+            )
+        );
+        assert!(maths[1]["value"]["mathml"]
+            .as_str()
+            .unwrap()
+            .contains("<merror>"));
+        assert!(maths.iter().all(|x| x["value"].get("ast").is_none()));
+
+        let diagnostics = value["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["severity"], json!("warning"));
+        assert_eq!(diagnostics[0]["code"], json!("unsupported_math"));
+        assert_eq!(diagnostics[0]["key"], maths[1]["key"]);
+    }
+
+    #[test]
+    fn plain_text() {
+        let text = parse_paragraphs_to_plain_text(
+            r"\section{Limit of $a_n$}
+        数列 $\alpha_{i}^{2} \to \infty$ について
+        \[\frac{a}{b}\]
+        が成り立つ.
+
+        次の段落",
+        )
+        .unwrap();
+
+        assert_eq!(
+            text,
+            "1 Limit of aₙ\n\n数列 αᵢ² → ∞ について\n(a)/(b)\nが成り立つ.\n\n次の段落"
+        );
+    }
+
+    #[test]
+    fn katex_check() {
+        let input = r"$x + \R$ と $\frac{1}{2}$";
+
+        let value = to_value(input);
+        assert_eq!(value["diagnostics"], json!([]));
+
+        let options = ParseOptions::new().check_katex(true);
+        let value = serde_json::to_value(parse_paragraphs_to_json_with(input, &options)).unwrap();
+        let entries = value["entries"].as_array().unwrap();
+        let maths: Vec<_> = entries
+            .iter()
+            .filter(|x| x["value"]["kind"] == "il_math")
+            .collect();
+
+        assert_eq!(maths[0]["value"]["status"], json!("warning"));
+        assert_eq!(maths[1]["value"]["status"], json!("ok"));
+        assert_eq!(
+            value["diagnostics"],
+            json!([{
+                "severity": "warning",
+                "code": "katex_unsupported",
+                "message": "\\R is not supported by KaTeX",
+                "key": maths[0]["key"],
+                "offset": 4,
+            }])
+        );
+    }
+
+    #[test]
+    fn katex_check_with_definitions() {
+        let input = r"\newcommand{\R}{\mathbb{R}}
+        \DeclareMathOperator{\Hom}{Hom}
+        \def\foo#1{#1}
+        \newcommand{\pair}[2][x]{(#1, #2)}
+
+        $\R \Hom \foo{a}$ と $\pair{b}$";
+        let options = ParseOptions::new().check_katex(true);
+        let value = serde_json::to_value(parse_paragraphs_to_json_with(input, &options)).unwrap();
+        let maths: Vec<_> = value["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|x| x["value"]["kind"] == "il_math")
+            .collect();
+
+        // 省略可能引数のあるマクロは KaTeX に渡されない
+        assert_eq!(maths[0]["value"]["status"], json!("ok"));
+        assert_eq!(maths[1]["value"]["status"], json!("warning"));
+        let messages: Vec<_> = value["diagnostics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["message"].as_str().unwrap())
+            .collect();
+        assert_eq!(messages, vec![r"\pair is not supported by KaTeX"]);
+    }
+
+    #[test]
+    fn math_errors() {
+        let value = to_value(r"\[Z \cong \left{A \oplus B\right. .\] と $\begin{cases} x");
+        let entries = value["entries"].as_array().unwrap();
+
+        let ds_math = entries
+            .iter()
+            .find(|x| x["value"]["kind"] == "ds_math")
+            .unwrap();
+        assert_eq!(ds_math["value"]["status"], json!("error"));
+        assert_eq!(
+            ds_math["value"]["errors"],
+            json!([
+                {"reason": "invalid_delimiter", "offset": 13},
+                {"reason": "unclosed_brace", "offset": 13},
+            ])
+        );
+
+        let il_math = entries
+            .iter()
+            .find(|x| x["value"]["kind"] == "il_math")
+            .unwrap();
+        assert_eq!(
+            il_math["value"]["errors"],
+            json!([
+                {"reason": "unclosed_environment", "offset": 0, "environment": "cases"},
+                {"reason": "unclosed_math", "offset": 15},
+            ])
+        );
+    }
+
+    #[test]
+    fn nested_math_delimiter() {
+        let value = to_value(r"$\text{if $n$ is odd}$ と \( a \[ b \] \)");
+        let entries = value["entries"].as_array().unwrap();
+        let maths: Vec<_> = entries
+            .iter()
+            .filter(|x| x["value"]["kind"] == "il_math")
+            .collect();
+
+        assert_eq!(maths[0]["value"]["content"], json!(r"\text{if $n$ is odd}"));
+        assert_eq!(maths[0]["value"]["status"], json!("ok"));
+
+        assert_eq!(maths[1]["value"]["status"], json!("error"));
+        assert_eq!(
+            maths[1]["value"]["errors"],
+            json!([{"reason": "nested_delimiter", "offset": 2, "delimiter": "\\["}])
+        );
+        assert_eq!(
+            value["diagnostics"],
+            json!([{
+                "severity": "error",
+                "code": "nested_math_delimiter",
+                "message": "\\[ appears inside math",
+                "key": maths[1]["key"],
+                "offset": 2,
+            }])
+        );
+    }
+
+    #[test]
+    fn equation_numbers() {
+        let value = to_value(
+            r"\begin{equation}
+        a = b \label{eq:first}
+        \end{equation}
+        \begin{align}
+        c &= d \\
+        e &= f \nonumber \\
+        g &= h \tag{A} \label{eq:star}
+        \end{align}
+        \begin{equation*} x \end{equation*}
+
+        \eqref{eq:first} と \ref{eq:star}, \ref{sec:intro}, \eqref{eq:none}.
+        \section{Intro}\label{sec:intro}",
+        );
+        let entries = value["entries"].as_array().unwrap();
+        let maths: Vec<_> = entries
+            .iter()
+            .filter(|x| x["value"]["kind"] == "ds_math")
+            .collect();
+
+        assert_eq!(maths[0]["value"]["environment"], json!("equation"));
+        assert_eq!(maths[0]["value"]["content"], json!("a = b"));
+        assert_eq!(
+            maths[0]["value"]["equations"],
+            json!([{"number": "1", "label": "eq:first"}])
+        );
+        assert_eq!(
+            maths[1]["value"]["equations"],
+            json!([
+                {"number": "2", "label": null},
+                {"number": null, "label": null},
+                {"number": "A", "label": "eq:star"},
+            ])
+        );
+        assert_eq!(maths[2]["value"]["environment"], json!("equation*"));
+        assert!(maths[2]["value"].get("equations").is_none());
+
+        let refs: Vec<_> = entries
+            .iter()
+            .filter(|x| x["value"]["kind"] == "ref")
+            .map(|x| (x["value"]["command"].clone(), x["value"]["number"].clone()))
+            .collect();
+        assert_eq!(
+            refs,
+            vec![
+                (json!("eqref"), json!("1")),
+                (json!("ref"), json!("A")),
+                (json!("ref"), json!("1")),
+                (json!("eqref"), json!(null)),
+            ]
+        );
+
+        let ref_keys: Vec<_> = entries
+            .iter()
+            .filter(|x| x["value"]["kind"] == "ref")
+            .map(|x| &x["key"])
+            .collect();
+        assert_ne!(ref_keys[0], ref_keys[1]);
+        assert_ne!(ref_keys[2], ref_keys[3]);
+
+        let diagnostics = value["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["code"], json!("unresolved_reference"));
+        assert_eq!(
+            diagnostics[0]["message"],
+            json!("\\eqref{eq:none} refers to an undefined label")
+        );
+    }
+
+    #[test]
+    fn canonical_math() {
+        let input = r"$x^{2}$ と \(x ^ 2\) と $$\alpha  x$$ と \[\alpha x\] と $\left( a$";
+        let keys = |options: &ParseOptions| {
+            let value =
+                serde_json::to_value(parse_paragraphs_to_json_with(input, options)).unwrap();
+            value["entries"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter(|x| x["value"]["kind"].as_str().unwrap().ends_with("_math"))
+                .map(|x| (x["key"].clone(), x["value"]["content"].clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let maths = keys(&ParseOptions::new());
+        assert_ne!(maths[0].0, maths[1].0);
+        assert_ne!(maths[2].0, maths[3].0);
+
+        let maths = keys(&ParseOptions::new().canonical_math(true));
+        assert_eq!(maths[0], maths[1]);
+        assert_eq!(maths[0].1, json!("x^2"));
+        assert_eq!(maths[2], maths[3]);
+        assert_eq!(maths[2].1, json!(r"\alpha x"));
+        // 誤りのある数式はそのまま
+        assert_eq!(maths[4].1, json!(r"\left( a"));
+    }
+
+    #[test]
+    fn macros() {
+        let value = to_value(
+            r"\documentclass{article}
+        \newcommand{\R}{\mathbb{R}}
+        \providecommand{\R}{R}
+        \begin{document}
+        \newcommand*\pair[2][x]{(#1,#2)}
+        \def\swap#1#2{#2#1}
+        $\pair{a} \in \R$ と \renewcommand{\R}{\mathbf{R}}$\R$
+        \end{document}",
+        );
+
+        assert_eq!(
+            value["macros"],
+            // `\\pair` は KaTeX では必須引数2つの命令になってしまうので含めない
+            json!({
+                "\\R": "\\mathbf{R}",
+                "\\swap": "#2#1",
+            })
+        );
+        assert_eq!(
+            value["macro_definitions"][0],
+            json!({"name": "\\R", "args": 0, "default": null, "body": "\\mathbf{R}"})
+        );
+        assert_eq!(
+            value["macro_definitions"][1],
+            json!({"name": "\\pair", "args": 2, "default": "x", "body": "(#1,#2)"})
+        );
+
+        // 定義は本文に残らない
+        let entries = value["entries"].as_array().unwrap();
+        assert!(entries.iter().all(|x| x["value"]["kind"] != "il_cmd"));
+        let paras: Vec<_> = entries
+            .iter()
+            .filter(|x| x["value"]["kind"] == "para")
+            .collect();
+        assert_eq!(paras.len(), 1);
+        assert_eq!(paras[0]["value"]["keys"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn expand_macros() {
+        let input = r"\newcommand{\lp}{\left(}
+        \newcommand{\R}{\mathbb{R}}
+        \newcommand{\loop}{x \loop}
+        $\lp a \right) \in \R$ と $\loop$";
+
+        let value = to_value(input);
+        let maths: Vec<_> = value["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|x| x["value"]["kind"] == "il_math")
+            .cloned()
+            .collect();
+        assert_eq!(maths[0]["value"]["status"], json!("error"));
+
+        let options = ParseOptions::new()
+            .expand_macros(true)
+            .max_expansion_depth(8);
+        let value = serde_json::to_value(parse_paragraphs_to_json_with(input, &options)).unwrap();
+        let maths: Vec<_> = value["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|x| x["value"]["kind"] == "il_math")
+            .cloned()
+            .collect();
+        assert_eq!(maths[0]["value"]["status"], json!("ok"));
+        assert_eq!(
+            maths[0]["value"]["content"],
+            json!(r"\left( a \right) \in \mathbb{R}")
+        );
+        assert_eq!(maths[1]["value"]["content"], json!(r"\loop"));
+        assert_eq!(
+            value["diagnostics"],
+            json!([{
+                "severity": "warning",
+                "code": "macro_expansion_limit",
+                "message": "expansion of \\loop exceeded the depth limit of 8",
+                "key": maths[1]["key"],
+                "offset": null,
+            }])
+        );
+
+        let text = parse_paragraphs_to_plain_text_with(input, &options).unwrap();
+        assert!(text.starts_with("(a) ∈ ℝ"), "{}", text);
+    }
+
+    #[test]
+    fn declare_math_operator_and_environments() {
+        let input = r"\DeclareMathOperator{\Hom}{Hom}
+        \DeclareMathOperator*{\argmax}{arg\,max}
+        \newenvironment{claim}[1][]{\textbf{Claim #1.}}{}
+
+        \begin{claim}[A]
+        $\Hom(X, Y)$ は空でない.
+
+        二段落目.
+        \end{claim}";
+
+        let value = to_value(input);
+        assert_eq!(
+            value["macros"],
+            json!({
+                "\\Hom": "\\operatorname{Hom}",
+                "\\argmax": "\\operatorname*{arg\\,max}",
+            })
+        );
+        assert_eq!(
+            value["environment_definitions"],
+            json!([{
+                "name": "claim",
+                "args": 1,
+                "default": "",
+                "begin": "\\textbf{Claim #1.}",
+                "end": "",
+            }])
+        );
+
+        let entries = value["entries"].as_array().unwrap();
+        let env = entries
+            .iter()
+            .find(|x| x["value"]["kind"] == "env")
+            .unwrap();
+        assert_eq!(env["value"]["name"], json!("claim"));
+        assert_eq!(env["value"]["args"], json!(["A"]));
+        assert_eq!(env["value"]["keys"].as_array().unwrap().len(), 2);
+        let root = entries.iter().find(|x| x["key"] == value["root"]).unwrap();
+        assert_eq!(root["value"]["keys"], json!([env["key"]]));
+
+        let options = ParseOptions::new().expand_macros(true);
+        let value = serde_json::to_value(parse_paragraphs_to_json_with(input, &options)).unwrap();
+        let entries = value["entries"].as_array().unwrap();
+        assert!(entries.iter().all(|x| x["value"]["kind"] != "env"));
+        let math = entries
+            .iter()
+            .find(|x| x["value"]["kind"] == "il_math")
+            .unwrap();
+        assert_eq!(math["value"]["content"], json!(r"\operatorname{Hom}(X, Y)"));
+    }
+
+    #[test]
+    fn macro_library() {
+        let library = MacroLibrary::from_tex(
+            r"\newcommand{\R}{\mathbb{R}}
+            \newcommand{\N}{\mathbb{N}}
+            \newtheorem{thm}{Theorem}",
+        );
+        let options = ParseOptions::new()
+            .macro_library(library)
+            .expand_macros(true);
+
+        // 同じ設定で何度読んでもライブラリの定義が使われる
+        for _ in 0..2 {
+            let input = r"\renewcommand{\N}{\mathbb{Z}_{\ge 0}}
+
+            \begin{thm}[Archimedes]
+            $x \in \R$, $n \in \N$.
+            \end{thm}";
+            let value =
+                serde_json::to_value(parse_paragraphs_to_json_with(input, &options)).unwrap();
+            assert_eq!(value["macros"]["\\R"], json!("\\mathbb{R}"));
+            assert_eq!(value["macros"]["\\N"], json!("\\mathbb{Z}_{\\ge 0}"));
+            assert_eq!(value["theorem_definitions"][0]["title"], json!("Theorem"));
+
+            let entries = value["entries"].as_array().unwrap();
+            let env = entries
+                .iter()
+                .find(|x| x["value"]["kind"] == "env")
+                .unwrap();
+            assert_eq!(env["value"]["name"], json!("thm"));
+            assert_eq!(env["value"]["args"], json!(["Archimedes"]));
+            let contents: Vec<_> = entries
+                .iter()
+                .filter(|x| x["value"]["kind"] == "il_math")
+                .map(|x| x["value"]["content"].clone())
+                .collect();
+            assert_eq!(
+                contents,
+                vec![
+                    json!(r"x \in \mathbb{R}"),
+                    json!(r"n \in \mathbb{Z}_{\ge 0}")
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn notation() {
+        let input = r"\newcommand{\R}{\mathbb{R}}
+        $x \in \R$ とし, ノルムを
+        \[ \norm{x} \coloneqq \sqrt{x \cdot x} \]
+        で定める. $\norm{x} \geq 0$.";
+
+        let value = to_value(input);
+        let entries = value["entries"].as_array().unwrap();
+        let key_of = |content: &str| {
+            entries
+                .iter()
+                .find(|x| x["value"]["content"] == content)
+                .unwrap()["key"]
+                .clone()
+        };
+        let inline = key_of(r"x \in \R");
+        let display = key_of(r"\norm{x} \coloneqq \sqrt{x \cdot x}");
+
+        let names: Vec<_> = value["notation"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                r"\in",
+                r"\R",
+                r"\norm",
+                r"\coloneqq",
+                r"\sqrt",
+                r"\cdot",
+                r"\geq"
+            ]
+        );
+        assert_eq!(
+            value["notation"][1],
+            json!({
+                "name": "\\R",
+                "user_macro": true,
+                "first": inline,
+                "span": [6, 8],
+                "definition": null,
+            })
+        );
+        assert_eq!(value["notation"][2]["first"], display);
+        assert_eq!(value["notation"][2]["definition"], display);
+        assert_eq!(value["notation"][2]["user_macro"], json!(false));
+    }
+
+    #[test]
+    fn notation_span_after_transforms() {
+        let input = r"\newcommand{\R}{\mathbb{R}}
+        $x ^ {2}   + \R$ と $\R$";
+        let spans = |options: &ParseOptions| {
+            let value =
+                serde_json::to_value(parse_paragraphs_to_json_with(input, options)).unwrap();
+            value["notation"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|x| (x["name"].as_str().unwrap().to_string(), x["span"].clone()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            spans(&ParseOptions::new()),
+            vec![(r"\R".to_string(), json!([10, 12]))]
+        );
+        // 中身が変わった数式の位置は出さない
+        let options = ParseOptions::new().canonical_math(true).expand_macros(true);
+        assert_eq!(spans(&options), vec![(r"\R".to_string(), Value::Null)]);
+        let options = ParseOptions::new().canonical_math(true);
+        assert_eq!(spans(&options), vec![(r"\R".to_string(), Value::Null)]);
+    }
+
+    #[test]
+    fn macro_dependencies() {
+        let key_of = |input: &str, content: &str| {
+            let value = to_value(input);
+            value["entries"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|x| x["value"]["content"] == content)
+                .unwrap()
+                .clone()
+        };
+        let doc = |r: &str| {
+            format!(
+                r"\newcommand{{\R}}{{{}}}
+                \newcommand{{\Rp}}{{\R_{{>0}}}}
+                $x \in \Rp$ と $y = 1$",
+                r
+            )
+        };
+
+        let before = key_of(&doc(r"\mathbb{R}"), r"x \in \Rp");
+        let after = key_of(&doc(r"\mathbf{R}"), r"x \in \Rp");
+        assert_eq!(before["macros"], json!([r"\R", r"\Rp"]));
+        assert_ne!(before["key"], after["key"]);
+
+        let before = key_of(&doc(r"\mathbb{R}"), "y = 1");
+        let after = key_of(&doc(r"\mathbf{R}"), "y = 1");
+        assert_eq!(before.get("macros"), None);
+        assert_eq!(before["key"], after["key"]);
+    }
+
+    #[test]
+    fn cross_references() {
+        let value = to_value(
+            r"\newtheorem{thm}{Theorem}[section]
+        \newtheorem{lem}[thm]{Lemma}
+        \section{Intro}\label{sec:intro}
+        \begin{thm}[Fermat]\label{thm:f}
+        本文
+        \end{thm}
+        \begin{lem}\label{lem:a}
+        \begin{enumerate}
+        \item one
+        \item two \label{it:two}
+        \end{enumerate}
+        \end{lem}
+        \begin{figure}
+        \caption{Plot}\label{fig:p}
+        \end{figure}
+        \begin{equation} x \label{eq:x} \end{equation}
+        \label{thm:f}
+
+        \Cref{thm:f}, \cref{lem:a}, \autoref{sec:intro}, \nameref{thm:f},
+        \ref{it:two}, \cref{fig:p}, \Cref{eq:x}, \ref{nope}",
+        );
+        let entries = value["entries"].as_array().unwrap();
+        let find = |kind: &str, name: &str| {
+            entries
+                .iter()
+                .find(|x| x["value"]["kind"] == kind && x["value"]["name"] == name)
+                .unwrap()
+        };
+        let thm = find("env", "thm");
+        assert_eq!(thm["value"]["number"], json!("1.1"));
+        assert_eq!(find("env", "lem")["value"]["number"], json!("1.2"));
+
+        let list = entries
+            .iter()
+            .find(|x| x["value"]["kind"] == "list")
+            .unwrap();
+        assert_eq!(list["value"]["items"][1]["marker"], json!("2."));
+        assert_eq!(list["value"]["items"][1]["number"], json!("2"));
+
+        let refs: Vec<_> = entries
+            .iter()
+            .filter(|x| x["value"]["kind"] == "ref")
+            .map(|x| x["value"]["text"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            refs,
+            vec![
+                "Theorem 1.1",
+                "lemma 1.2",
+                "section 1",
+                "Fermat",
+                "2",
+                "fig. 1",
+                "Eq. (1)",
+                "??"
+            ]
+        );
+
+        let thm_ref = entries
+            .iter()
+            .find(|x| x["value"]["kind"] == "ref" && x["value"]["command"] == "Cref")
+            .unwrap();
+        assert_eq!(thm_ref["value"]["target"], thm["key"]);
+        assert_eq!(thm_ref["value"]["anchor"], json!("thm:f"));
+        let section_ref = entries
+            .iter()
+            .find(|x| x["value"]["command"] == "autoref")
+            .unwrap();
+        assert_eq!(section_ref["value"]["anchor"], json!("intro"));
+
+        let codes: Vec<_> = value["diagnostics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["code"].as_str().unwrap())
+            .collect();
+        assert_eq!(codes, vec!["duplicate_label", "unresolved_reference"]);
+    }
+
+    #[test]
+    fn reference_without_label() {
+        let value = to_value(r"(\ref) と \cref* {a}");
+        let kinds: Vec<_> = value["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|x| x["value"]["kind"] == "il_cmd" || x["value"]["kind"] == "ref")
+            .map(|x| {
+                (
+                    &x["value"]["kind"],
+                    &x["value"]["content"],
+                    &x["value"]["label"],
+                )
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (&json!("il_cmd"), &json!(r"\ref"), &Value::Null),
+                (&json!("ref"), &Value::Null, &json!("a")),
+            ]
+        );
+        let messages: Vec<_> = value["diagnostics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["message"].as_str().unwrap())
+            .collect();
+        assert_eq!(messages, vec![r"\cref{a} refers to an undefined label"]);
+    }
+
+    #[test]
+    fn cref_lists() {
+        let value = to_value(
+            r"\newtheorem{thm}{Theorem}
+        \begin{thm}\label{thm:a} 本文 \end{thm}
+        \begin{thm}\label{thm:b} 本文 \end{thm}
+        \begin{align}
+        x &= y \nonumber \label{eq:plain} \\
+        y &= z \label{eq:z}
+        \end{align}
+
+        \cref{thm:a,thm:b}, \Cref{thm:a, eq:z}, \cref{thm:b,nope}, \ref{eq:plain}",
+        );
+        let entries = value["entries"].as_array().unwrap();
+        let refs: Vec<_> = entries
+            .iter()
+            .filter(|x| x["value"]["kind"] == "ref")
+            .map(|x| &x["value"])
+            .collect();
+        let texts: Vec<_> = refs.iter().map(|x| x["text"].as_str().unwrap()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "theorems 1 and 2",
+                "Theorem 1 and Eq. (1)",
+                "theorem 2 and ??",
+                "??"
+            ]
+        );
+
+        let theorems: Vec<_> = entries
+            .iter()
+            .filter(|x| x["value"]["kind"] == "env")
+            .map(|x| &x["key"])
+            .collect();
+        assert_eq!(refs[0]["label"], json!("thm:a,thm:b"));
+        assert_eq!(refs[0]["target"], *theorems[0]);
+        let targets: Vec<_> = refs[0]["targets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| (x["label"].as_str().unwrap(), &x["target"]))
+            .collect();
+        assert_eq!(
+            targets,
+            vec![("thm:a", theorems[0]), ("thm:b", theorems[1])]
+        );
+        assert_eq!(refs[3].get("targets"), None);
+        assert_ne!(refs[3]["target"], Value::Null);
+
+        // 見つからないのは `nope` だけ
+        let diagnostics: Vec<_> = value["diagnostics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["message"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![r"\cref{nope} refers to an undefined label"]
+        );
+    }
+
+    #[test]
+    fn citations() {
+        let bibliography = Bibliography::from_bib(
+            r#"@string{aw = "Addison-Wesley"}
+            @book{knuth, author = {Knuth, Donald E.}, title = {The {\TeX}book},
+              publisher = aw, year = 1984}
+            @article{lamport, author = "Leslie Lamport", title = "LaTeX",
+              journal = "J. " # "Things", year = {1994}}
+            @misc{other, title = {Other}}"#,
+        )
+        .unwrap();
+        let input = r"\cite{lamport}, \citep[see][p.~3]{knuth, lamport}, \cite{nope}.
+        \nocite{other}
+        \bibliographystyle{plain}
+        \bibliography{refs}";
+
+        let options = ParseOptions::new().bibliography(bibliography.clone());
+        let value = serde_json::to_value(parse_paragraphs_to_json_with(input, &options)).unwrap();
+        let entries = value["entries"].as_array().unwrap();
+        let cites: Vec<_> = entries
+            .iter()
+            .filter(|x| x["value"]["kind"] == "cite")
+            .map(|x| x["value"]["text"].as_str().unwrap())
+            .collect();
+        assert_eq!(cites, vec!["[1]", "[see 2, 1, p. 3]", "[?]"]);
+        let cite = entries
+            .iter()
+            .find(|x| x["value"]["kind"] == "cite" && x["value"]["command"] == "citep")
+            .unwrap();
+        assert_eq!(cite["value"]["keys"], json!(["knuth", "lamport"]));
+        assert_eq!(cite["value"]["prenote"], json!("see"));
+        assert_eq!(cite["value"]["postnote"], json!("p. 3"));
+
+        let keys: Vec<_> = value["bibliography"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| (x["key"].as_str().unwrap(), x["label"].as_str().unwrap()))
+            .collect();
+        assert_eq!(keys, vec![("lamport", "1"), ("knuth", "2"), ("other", "3")]);
+        assert_eq!(
+            value["bibliography"][1]["text"],
+            json!("Donald E. Knuth. The TeXbook. Addison-Wesley, 1984.")
+        );
+        assert_eq!(
+            value["bibliography"][0]["fields"]["journal"],
+            json!("J. Things")
+        );
+
+        let codes: Vec<_> = value["diagnostics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["code"].as_str().unwrap())
+            .collect();
+        assert_eq!(codes, vec!["unknown_citation"]);
+
+        let options = ParseOptions::new()
+            .bibliography(bibliography)
+            .citation_style(CitationStyle::AuthorYear);
+        let value = serde_json::to_value(parse_paragraphs_to_json_with(input, &options)).unwrap();
+        let cites: Vec<_> = value["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|x| x["value"]["kind"] == "cite")
+            .map(|x| x["value"]["text"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            cites,
+            vec![
+                "Lamport (1994)",
+                "(see Knuth, 1984; Lamport, 1994, p. 3)",
+                "?"
+            ]
+        );
+    }
+
+    #[test]
+    fn workspace() {
+        let mut workspace = Workspace::new();
+        workspace.add_document(
+            "groups",
+            r"\newtheorem{thm}{Theorem}
+            \section{Groups}
+            \begin{thm}\label{thm:lagrange} 本文 \end{thm}",
+        );
+        workspace.add_document(
+            "rings",
+            r"\externaldocument[G-]{groups}
+            \newtheorem{lem}{Lemma}
+            \begin{lem}\label{lem:ideal} 本文 \end{lem}
+            \ref{lem:ideal}, \ref{G-thm:lagrange}, \ref{groups:thm:lagrange},
+            \ref{G-thm:none}, \ref{nope}",
+        );
+        let value = serde_json::to_value(parse_workspace_to_json(&workspace, &ParseOptions::new()))
+            .unwrap();
+
+        let labels: Vec<_> = value["labels"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| {
+                (
+                    x["document"].as_str().unwrap(),
+                    x["label"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            vec![("groups", "thm:lagrange"), ("rings", "lem:ideal")]
+        );
+
+        let groups = &value["documents"][0];
+        assert_eq!(groups["name"], json!("groups"));
+        let theorem = groups["result"]["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|x| x["value"]["kind"] == "env")
+            .unwrap();
+        assert_eq!(value["labels"][0]["target"], theorem["key"]);
+
+        let rings = &value["documents"][1]["result"];
+        let refs: Vec<_> = rings["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|x| x["value"]["kind"] == "ref")
+            .map(|x| &x["value"])
+            .collect();
+        let texts: Vec<_> = refs.iter().map(|x| x["text"].as_str().unwrap()).collect();
+        assert_eq!(texts, vec!["1", "1", "1", "??", "??"]);
+        assert_eq!(refs[0]["document"], Value::Null);
+        assert_eq!(refs[1]["document"], json!("groups"));
+        assert_eq!(refs[1]["target"], theorem["key"]);
+        assert_eq!(refs[2]["target"], theorem["key"]);
+
+        let codes: Vec<_> = rings["diagnostics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["code"].as_str().unwrap())
+            .collect();
+        assert_eq!(codes, vec!["dangling_reference", "unresolved_reference"]);
+    }
+
+    #[test]
+    fn theorem_graph() {
+        let input = r"\newtheorem{thm}{Theorem}
+        \newtheorem{lem}[thm]{Lemma}
+        \section{Main}\label{sec:main}
+        \begin{equation} x = y \label{eq:xy} \end{equation}
+        \begin{lem}\label{lem:a} By \eqref{eq:xy}. \end{lem}
+        \begin{lem}\label{lem:b} 本文 \end{lem}
+        \begin{proof} See \cref{lem:a} and \ref{sec:main}. \end{proof}
+        \begin{thm}\label{thm:main} 本文 \end{thm}
+        \begin{proof}[Proof of \cref{lem:b}] Trivial. \end{proof}
+        \begin{proof} Use \cref{lem:a,lem:b}, \ref{lem:b} and \ref{nope}. \end{proof}
+        \begin{thm}\label{thm:alone} 本文 \end{thm}
+        \begin{thm} ラベルなし \end{thm}";
+        let graph = parse_theorem_graph(input, &ParseOptions::new()).unwrap();
+
+        let edges: Vec<_> = graph
+            .edges()
+            .iter()
+            .map(|x| (x.from.as_str(), x.to.as_str()))
+            .collect();
+        assert_eq!(
+            edges,
+            vec![
+                ("lem:a", "eq:xy"),
+                ("lem:b", "lem:a"),
+                ("lem:b", "sec:main"),
+                ("thm:main", "lem:a"),
+                ("thm:main", "lem:b"),
+            ]
+        );
+
+        let lemma = &graph.nodes()["lem:b"];
+        assert_eq!(lemma.env, "lem");
+        assert_eq!(lemma.title.as_deref(), Some("Lemma"));
+        assert_eq!(lemma.number.as_deref(), Some("2"));
+        assert_eq!(graph.nodes()["sec:main"].env, "section");
+        assert_eq!(graph.nodes()["eq:xy"].number.as_deref(), Some("1"));
+        assert_eq!(
+            graph.nodes().keys().collect::<Vec<_>>(),
+            vec![
+                "eq:xy",
+                "lem:a",
+                "lem:b",
+                "sec:main",
+                "thm:alone",
+                "thm:main"
+            ]
+        );
+        assert_eq!(graph.nodes()["thm:alone"].number.as_deref(), Some("4"));
+
+        let value: Value = serde_json::from_str(&graph.to_json()).unwrap();
+        assert_eq!(value["nodes"]["thm:main"]["number"], json!("3"));
+        assert!(graph
+            .to_dot()
+            .contains("\"thm:main\" [label=\"Theorem 3\"];\n  \"lem:a\""));
+    }
+}
@@ -1,24 +1,34 @@
+use crate::citation::BibItem;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::equation::Equation;
+use crate::figure::{FigureInfo, ImageInfo};
 use crate::key::Key;
+use crate::list::ListInfo;
+use crate::macros::{Dependencies, Macros};
+use crate::math_ast::MathNode;
+use crate::math_balance::{MathError, MathErrorReason};
+use crate::metadata::{MetaText, Metadata};
 use crate::node::Node;
+use crate::notation::Notation;
+use crate::preamble::Preamble;
+use crate::reference::Labels;
+use crate::result_map::ResultMap;
+use crate::table::{ColumnAlign, TableColumn, TableInfo, TableRule};
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Debug, Serialize)]
 #[serde(tag = "status")]
 pub enum ParseResult {
     #[serde(rename = "ok")]
-    Ok(ParseResultOk),
+    Ok(Box<ParseResultOk>),
     #[serde(rename = "error")]
     Error(ParseResultError),
 }
 
 impl ParseResult {
-    pub(super) fn new_ok(root: EntryKey, entries: Vec<Entry>, char_count: usize) -> Self {
-        Self::Ok(ParseResultOk {
-            root,
-            entries,
-            count: char_count,
-        })
+    pub(super) fn new_ok(result: ParseResultOk) -> Self {
+        Self::Ok(Box::new(result))
     }
 
     pub(super) fn new_error(message: String) -> Self {
@@ -28,9 +38,128 @@ impl ParseResult {
 
 #[derive(Debug, Serialize)]
 pub struct ParseResultOk {
-    root: EntryKey,
-    entries: Vec<Entry>,
-    count: usize,
+    pub(super) root: EntryKey,
+    pub(super) entries: Vec<Entry>,
+    pub(super) count: usize,
+    pub(super) outline: Vec<OutlineItem>,
+    pub(super) diagnostics: Vec<DiagnosticEntry>,
+    pub(super) metadata: DocumentMetadata,
+    pub(super) preamble: Option<Preamble>,
+    /// KaTeX の `macros` オプションにそのまま渡せる, 名前から本体への対応
+    ///
+    /// KaTeX は省略可能引数を扱えないので, 省略可能引数のある命令は `macro_definitions` にだけ入れる.
+    pub(super) macros: BTreeMap<String, String>,
+    pub(super) macro_definitions: Vec<MacroDefinition>,
+    pub(super) environment_definitions: Vec<EnvironmentDefinition>,
+    pub(super) theorem_definitions: Vec<TheoremDefinition>,
+    pub(super) notation: Vec<NotationEntry>,
+    pub(super) bibliography: Vec<BibliographyItem>,
+}
+
+impl ParseResultOk {
+    pub fn outline(&self) -> &[OutlineItem] {
+        &self.outline
+    }
+
+    pub fn diagnostics(&self) -> &[DiagnosticEntry] {
+        &self.diagnostics
+    }
+
+    pub fn metadata(&self) -> &DocumentMetadata {
+        &self.metadata
+    }
+
+    pub fn preamble(&self) -> Option<&Preamble> {
+        self.preamble.as_ref()
+    }
+
+    pub fn macros(&self) -> &BTreeMap<String, String> {
+        &self.macros
+    }
+
+    pub fn macro_definitions(&self) -> &[MacroDefinition] {
+        &self.macro_definitions
+    }
+
+    pub fn environment_definitions(&self) -> &[EnvironmentDefinition] {
+        &self.environment_definitions
+    }
+
+    pub fn theorem_definitions(&self) -> &[TheoremDefinition] {
+        &self.theorem_definitions
+    }
+
+    pub fn notation(&self) -> &[NotationEntry] {
+        &self.notation
+    }
+
+    pub fn bibliography(&self) -> &[BibliographyItem] {
+        &self.bibliography
+    }
+}
+
+/// 命令の定義. KaTeX の `macros` では表せない省略可能引数の既定値も含む
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct MacroDefinition {
+    pub name: String,
+    pub args: usize,
+    pub default: Option<String>,
+    pub body: String,
+}
+
+/// `\newenvironment` による環境の定義
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct EnvironmentDefinition {
+    pub name: String,
+    pub args: usize,
+    pub default: Option<String>,
+    pub begin: String,
+    pub end: String,
+}
+
+/// `\newtheorem` による定理環境の定義
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct TheoremDefinition {
+    pub name: String,
+    pub title: String,
+    pub numbered: bool,
+    pub counter: Option<String>,
+    pub within: Option<String>,
+}
+
+/// 複数の文書をまとめて読んだ結果
+#[derive(Debug, Serialize)]
+pub struct WorkspaceResult {
+    pub(super) documents: Vec<WorkspaceDocument>,
+    /// 全文書のラベル. 文書を加えた順, 各文書の中ではラベルの順に並ぶ
+    pub(super) labels: Vec<WorkspaceLabel>,
+}
+
+impl WorkspaceResult {
+    pub fn documents(&self) -> &[WorkspaceDocument] {
+        &self.documents
+    }
+
+    pub fn labels(&self) -> &[WorkspaceLabel] {
+        &self.labels
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkspaceDocument {
+    pub name: String,
+    pub result: ParseResult,
+}
+
+/// ある文書の `\label`
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct WorkspaceLabel {
+    pub document: String,
+    pub label: String,
+    /// `document` の中の項目
+    pub target: EntryKey,
+    pub number: Option<String>,
+    pub anchor: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -43,10 +172,103 @@ pub(super) struct Entry {
     key: EntryKey,
     // #[serde(flatten)]
     value: EntryValue,
+    /// 使っている命令や環境の名前. 定義を変えると Key も変わる
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    macros: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct EntryKey(String);
+
+impl EntryKey {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
 #[derive(Debug, Serialize)]
-pub(super) struct EntryKey(String);
+pub struct DiagnosticEntry {
+    pub severity: DiagnosticSeverity,
+    pub code: String,
+    pub message: String,
+    pub key: Option<EntryKey>,
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticSeverity {
+    #[serde(rename = "error")]
+    Error,
+    #[serde(rename = "warning")]
+    Warning,
+}
+
+/// 記号一覧の一項目. 数式で使われた制御語を最初に現れた順に並べる
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct NotationEntry {
+    pub name: String,
+    pub user_macro: bool,
+    /// 最初に現れた数式
+    pub first: EntryKey,
+    /// `first` の数式の中身での文字単位の位置 `[start, end)`.
+    /// 展開や書き方の統一で中身が書かれたままでなければ null
+    pub span: Option<(usize, usize)>,
+    /// `:=` などで定義しているディスプレイ数式
+    pub definition: Option<EntryKey>,
+}
+
+/// 文献一覧の一項目
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct BibliographyItem {
+    pub key: String,
+    /// 番号形式では番号, 著者年形式では `Knuth 1984` のような著者と年
+    pub label: String,
+    /// `article` などの種類
+    pub kind: String,
+    /// 整形した文字列
+    pub text: String,
+    /// 波括弧などを除いたフィールドの値
+    pub fields: BTreeMap<String, String>,
+}
+
+impl From<BibItem> for BibliographyItem {
+    fn from(item: BibItem) -> Self {
+        Self {
+            key: item.key,
+            label: item.label,
+            kind: item.kind,
+            text: item.text,
+            fields: item.fields.into_iter().collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DocumentMetadata {
+    pub title: Option<MetadataText>,
+    pub authors: Vec<MetadataText>,
+    pub date: Option<MetadataText>,
+    #[serde(rename = "abstract")]
+    pub abstract_: Option<Vec<EntryKey>>,
+    pub maketitle: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MetadataText {
+    pub keys: Vec<EntryKey>,
+    pub text: String,
+    pub footnotes: Vec<Vec<EntryKey>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OutlineItem {
+    pub key: EntryKey,
+    pub level: usize,
+    pub number: Option<String>,
+    pub title: String,
+    pub anchor: String,
+    pub children: Vec<OutlineItem>,
+}
 
 #[derive(Debug, Serialize)]
 #[serde(tag = "kind")]
@@ -63,6 +285,26 @@ enum EntryValue {
     InlineMath(EVMath),
     #[serde(rename = "ds_math")]
     DisplayMath(EVMath),
+    #[serde(rename = "heading")]
+    Heading(EVHeading),
+    #[serde(rename = "table")]
+    Table(EVTable),
+    #[serde(rename = "figure")]
+    Figure(EVFigure),
+    #[serde(rename = "image")]
+    Image(EVImage),
+    #[serde(rename = "maketitle")]
+    MakeTitle,
+    #[serde(rename = "list")]
+    List(EVList),
+    #[serde(rename = "env")]
+    Environment(EVEnvironment),
+    #[serde(rename = "label")]
+    Label(EVLabel),
+    #[serde(rename = "ref")]
+    Reference(EVReference),
+    #[serde(rename = "cite")]
+    Citation(EVCitation),
 }
 
 #[derive(Debug, Serialize)]
@@ -108,6 +350,272 @@ impl EVInlineCommand {
 struct EVMath {
     status: EVMathStatus,
     content: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<EVMathError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    environment: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    equations: Vec<EVEquation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ast: Option<Vec<MathNode>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mathml: Option<String>,
+}
+
+/// ディスプレイ数式の一行の番号. 番号のない行は number が null
+#[derive(Debug, Serialize)]
+struct EVEquation {
+    number: Option<String>,
+    label: Option<String>,
+}
+
+impl From<&Equation> for EVEquation {
+    fn from(eq: &Equation) -> Self {
+        Self {
+            number: eq.number().map(|x| x.to_owned()),
+            label: eq.label().map(|x| x.to_owned()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EVEnvironment {
+    name: String,
+    args: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    number: Option<String>,
+    keys: Vec<EntryKey>,
+}
+
+#[derive(Debug, Serialize)]
+struct EVList {
+    env: String,
+    items: Vec<EVItem>,
+}
+
+#[derive(Debug, Serialize)]
+struct EVItem {
+    marker: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    number: Option<String>,
+    keys: Vec<EntryKey>,
+}
+
+impl EVList {
+    fn new(info: &ListInfo, hash_table: &HashMap<Key, String>) -> Self {
+        Self {
+            env: info.env().to_owned(),
+            items: info
+                .items()
+                .iter()
+                .map(|x| EVItem {
+                    marker: x.marker().to_owned(),
+                    number: x.number().map(|x| x.to_owned()),
+                    keys: convert_keys(x.content().to_vec(), hash_table),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EVLabel {
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+struct EVReference {
+    command: String,
+    label: String,
+    /// 他の文書の項目を参照していれば, その文書の名前
+    #[serde(skip_serializing_if = "Option::is_none")]
+    document: Option<String>,
+    number: Option<String>,
+    /// 参照先の項目. 解決できなければ None
+    target: Option<EntryKey>,
+    /// 文書中での表記 (`Theorem 2` や `eq. (1)` など)
+    text: String,
+    /// リンク先のアンカー. 見出しはその `anchor`, それ以外はラベル
+    anchor: Option<String>,
+    /// `\cref{a,b}` のように複数のラベルがあれば, それぞれの参照先.
+    /// 上の `document` などは最初のラベルのもの
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    targets: Vec<EVReferenceTarget>,
+}
+
+#[derive(Debug, Serialize)]
+struct EVReferenceTarget {
+    label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    document: Option<String>,
+    number: Option<String>,
+    target: Option<EntryKey>,
+    anchor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct EVCitation {
+    command: String,
+    keys: Vec<String>,
+    prenote: Option<String>,
+    postnote: Option<String>,
+    /// 文書中での表記 (`[1, p. 3]` や `Knuth (1984)` など)
+    text: String,
+}
+
+#[derive(Debug, Serialize)]
+struct EVHeading {
+    level: usize,
+    number: Option<String>,
+    title: Vec<EntryKey>,
+    short_title: Option<String>,
+    anchor: String,
+}
+
+#[derive(Debug, Serialize)]
+struct EVTable {
+    env: String,
+    width: Option<String>,
+    columns: Vec<TableColumn>,
+    vrules: Vec<usize>,
+    rows: Vec<EVTableRow>,
+    trailing_rules: Vec<TableRule>,
+}
+
+#[derive(Debug, Serialize)]
+struct EVTableRow {
+    rules: Vec<TableRule>,
+    cells: Vec<EVTableCell>,
+}
+
+#[derive(Debug, Serialize)]
+struct EVTableCell {
+    colspan: usize,
+    rowspan: usize,
+    align: Option<ColumnAlign>,
+    keys: Vec<EntryKey>,
+}
+
+impl EVTable {
+    fn new(info: TableInfo, hash_table: &HashMap<Key, String>) -> Self {
+        let rows = info
+            .rows()
+            .iter()
+            .map(|row| EVTableRow {
+                rules: row.rules().to_vec(),
+                cells: row
+                    .cells()
+                    .iter()
+                    .map(|cell| EVTableCell {
+                        colspan: cell.colspan(),
+                        rowspan: cell.rowspan(),
+                        align: cell.align().cloned(),
+                        keys: convert_keys(cell.content().to_vec(), hash_table),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Self {
+            env: info.env().to_owned(),
+            width: info.width().map(|x| x.to_owned()),
+            columns: info.spec().columns().to_vec(),
+            vrules: info.spec().vrules().to_vec(),
+            rows,
+            trailing_rules: info.trailing_rules().to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EVFigure {
+    env: String,
+    placement: Option<String>,
+    images: Vec<EVImage>,
+    content: Vec<EntryKey>,
+    caption: Option<Vec<EntryKey>>,
+    short_caption: Option<String>,
+    label: Option<String>,
+    number: Option<String>,
+    subfigures: Vec<EVFigure>,
+}
+
+impl EVFigure {
+    fn new(info: &FigureInfo, hash_table: &HashMap<Key, String>) -> Self {
+        Self {
+            env: info.env().to_owned(),
+            placement: info.placement().map(|x| x.to_owned()),
+            images: info.images().iter().map(EVImage::new).collect(),
+            content: convert_keys(info.content().to_vec(), hash_table),
+            caption: info
+                .caption()
+                .map(|ks| convert_keys(ks.to_vec(), hash_table)),
+            short_caption: info.short_caption().map(|x| x.to_owned()),
+            label: info.label().map(|x| x.to_owned()),
+            number: info.number().map(|x| x.to_owned()),
+            subfigures: info
+                .subfigures()
+                .iter()
+                .map(|x| EVFigure::new(x, hash_table))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EVImage {
+    path: String,
+    options: BTreeMap<String, String>,
+    resolved: Option<String>,
+}
+
+impl EVImage {
+    fn new(image: &ImageInfo) -> Self {
+        Self {
+            path: image.path().to_owned(),
+            options: image.options().clone(),
+            resolved: image.resolved().map(|x| x.to_owned()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EVMathError {
+    reason: &'static str,
+    offset: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    environment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expected: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delimiter: Option<String>,
+}
+
+impl From<MathError> for EVMathError {
+    fn from(error: MathError) -> Self {
+        let (environment, expected) = match error.reason() {
+            MathErrorReason::UnclosedEnvironment(env) | MathErrorReason::UnmatchedEnd(env) => {
+                (Some(env.clone()), None)
+            }
+            MathErrorReason::MismatchedEnvironment { begin, end } => {
+                (Some(end.clone()), Some(begin.clone()))
+            }
+            _ => (None, None),
+        };
+
+        let delimiter = match error.reason() {
+            MathErrorReason::NestedDelimiter(delimiter) => Some(delimiter.clone()),
+            _ => None,
+        };
+
+        Self {
+            reason: error.reason().code(),
+            offset: error.offset(),
+            environment,
+            expected,
+            delimiter,
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -116,9 +624,23 @@ enum EVMathStatus {
     Ok,
     #[serde(rename = "error")]
     Error,
+    #[serde(rename = "warning")]
+    Warning,
 }
 
-pub(super) fn convert_to_entry(key: Key, node: Node, hash_table: &HashMap<Key, String>) -> Entry {
+pub(super) fn convert_to_entry(
+    key: Key,
+    node: Node,
+    hash_table: &HashMap<Key, String>,
+    dependencies: &Dependencies,
+    documents: &DocumentTables,
+) -> Option<Entry> {
+    let entry_key = convert_key(key.clone(), hash_table)?;
+    let macros = dependencies
+        .get(&key)
+        .map(|defs| defs.iter().map(|x| x.name().to_owned()).collect())
+        .unwrap_or_default();
+
     let value = match node {
         Node::ParagraphList(Some(ks)) => {
             EntryValue::Paragraphs(EVKeys::new(convert_keys(ks, hash_table)))
@@ -128,42 +650,309 @@ pub(super) fn convert_to_entry(key: Key, node: Node, hash_table: &HashMap<Key, S
         }
         Node::RawString(s) => EntryValue::Text(EVText::new(s)),
         Node::InlineCommand(Some(s)) => EntryValue::InlineCommand(EVInlineCommand::new(s)),
-        Node::MathExpr(v) => {
-            let status = if v.is_ok() {
-                EVMathStatus::Ok
-            } else {
+        Node::MathExpr(mut v) => {
+            let status = if !v.is_ok() {
                 EVMathStatus::Error
+            } else if v.has_warning() {
+                EVMathStatus::Warning
+            } else {
+                EVMathStatus::Ok
             };
             let is_inline = v.is_inline();
             let is_display = v.is_display();
+            let errors = v.take_errors().into_iter().map(Into::into).collect();
+            let environment = v.env().map(|x| x.to_owned());
+            let equations = v.equations().iter().map(Into::into).collect();
+            let ast = v.take_ast();
+            let mathml = v.take_mathml();
             let content = v.content();
 
             if is_inline {
-                EntryValue::InlineMath(EVMath { status, content })
+                EntryValue::InlineMath(EVMath {
+                    status,
+                    content,
+                    errors,
+                    environment,
+                    equations,
+                    ast,
+                    mathml,
+                })
             } else if is_display {
-                EntryValue::DisplayMath(EVMath { status, content })
+                EntryValue::DisplayMath(EVMath {
+                    status,
+                    content,
+                    errors,
+                    environment,
+                    equations,
+                    ast,
+                    mathml,
+                })
             } else {
                 unreachable!()
             }
         }
+        Node::Heading(info) => EntryValue::Heading(EVHeading {
+            level: info.level().depth(),
+            number: info.number().map(|x| x.to_owned()),
+            title: convert_keys(info.title().to_vec(), hash_table),
+            short_title: info.short_title().map(|x| x.to_owned()),
+            anchor: info.anchor().to_owned(),
+        }),
+        Node::Table(info) => EntryValue::Table(EVTable::new(info, hash_table)),
+        Node::Figure(info) => EntryValue::Figure(EVFigure::new(&info, hash_table)),
+        Node::Image(image) => EntryValue::Image(EVImage::new(&image)),
+        Node::MakeTitle => EntryValue::MakeTitle,
+        Node::List(info) => EntryValue::List(EVList::new(&info, hash_table)),
+        Node::Environment(info) => EntryValue::Environment(EVEnvironment {
+            name: info.name().to_owned(),
+            args: info.args().to_vec(),
+            number: info.number().map(|x| x.to_owned()),
+            keys: convert_keys(info.content().to_vec(), hash_table),
+        }),
+        Node::Label(name) => EntryValue::Label(EVLabel { name }),
+        Node::Reference(info) => {
+            let targets: Vec<_> = info
+                .targets()
+                .map(|(label, target)| EVReferenceTarget {
+                    label: label.to_owned(),
+                    document: target.and_then(|x| x.document()).map(|x| x.to_owned()),
+                    number: target.and_then(|x| x.number()).map(|x| x.to_owned()),
+                    target: target.and_then(|x| {
+                        let table = x.document().map_or(hash_table, |x| documents[x]);
+                        convert_key(x.key().clone(), table)
+                    }),
+                    anchor: target.and_then(|x| x.anchor()).map(|x| x.to_owned()),
+                })
+                .collect();
+            let first = targets.first();
+
+            EntryValue::Reference(EVReference {
+                command: info.command().to_owned(),
+                label: info.label(),
+                document: first.and_then(|x| x.document.clone()),
+                number: first.and_then(|x| x.number.clone()),
+                target: first.and_then(|x| x.target.clone()),
+                text: info.display_text(),
+                anchor: first.and_then(|x| x.anchor.clone()),
+                targets: if targets.len() > 1 {
+                    targets
+                } else {
+                    Vec::new()
+                },
+            })
+        }
+        Node::Citation(info) => EntryValue::Citation(EVCitation {
+            command: info.command().to_owned(),
+            keys: info.keys().to_vec(),
+            prenote: info.prenote().map(|x| x.to_owned()),
+            postnote: info.postnote().map(|x| x.to_owned()),
+            text: info.display_text(),
+        }),
         _ => EntryValue::Text(EVText::new("unknown")), // todo
     };
 
-    Entry {
-        key: convert_key(key, hash_table),
+    Some(Entry {
+        key: entry_key,
         value,
-    }
+        macros,
+    })
 }
 
-pub(super) fn convert_key(key: Key, hash_table: &HashMap<Key, String>) -> EntryKey {
-    EntryKey(hash_table.get(&key).unwrap().to_owned())
+/// 文書の名前から, その文書の Key の表
+pub(super) type DocumentTables<'a> = HashMap<&'a str, &'a HashMap<Key, String>>;
+
+pub(super) fn convert_labels(
+    document: &str,
+    labels: &Labels,
+    hash_table: &HashMap<Key, String>,
+) -> Vec<WorkspaceLabel> {
+    let mut labels: Vec<_> = labels
+        .iter()
+        .filter_map(|(label, target)| {
+            Some(WorkspaceLabel {
+                document: document.to_owned(),
+                label: label.to_owned(),
+                target: convert_key(target.key().clone(), hash_table)?,
+                number: target.number().map(|x| x.to_owned()),
+                anchor: target.anchor().map(|x| x.to_owned()),
+            })
+        })
+        .collect();
+    labels.sort_by(|a, b| a.label.cmp(&b.label));
+    labels
+}
+
+/// 取り除かれた項目の Key は None になる
+pub(super) fn convert_key(key: Key, hash_table: &HashMap<Key, String>) -> Option<EntryKey> {
+    hash_table.get(&key).map(|x| EntryKey(x.to_owned()))
 }
 
+/// 取り除かれた項目の Key は飛ばす
 fn convert_keys(
     keys: impl IntoIterator<Item = Key>,
     hash_table: &HashMap<Key, String>,
 ) -> Vec<EntryKey> {
     keys.into_iter()
-        .map(|k| convert_key(k, hash_table))
+        .filter_map(|k| convert_key(k, hash_table))
         .collect::<Vec<_>>()
 }
+
+pub(super) fn convert_outline(
+    rmap: &ResultMap,
+    hash_table: &HashMap<Key, String>,
+) -> Vec<OutlineItem> {
+    let mut roots: Vec<OutlineItem> = Vec::new();
+    // 各深さで現在開いている見出しへの経路
+    let mut stack: Vec<OutlineItem> = Vec::new();
+
+    fn close(stack: &mut Vec<OutlineItem>, roots: &mut Vec<OutlineItem>) {
+        let item = stack.pop().unwrap();
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(item),
+            None => roots.push(item),
+        }
+    }
+
+    for (key, node) in rmap.iter() {
+        if let Node::Heading(info) = node {
+            let Some(entry_key) = convert_key(key.clone(), hash_table) else {
+                continue;
+            };
+            let title = match info.short_title() {
+                Some(s) => s.to_owned(),
+                None => rmap.plain_text_at(key),
+            };
+            let item = OutlineItem {
+                key: entry_key,
+                level: info.level().depth(),
+                number: info.number().map(|x| x.to_owned()),
+                title,
+                anchor: info.anchor().to_owned(),
+                children: Vec::new(),
+            };
+
+            while stack.last().is_some_and(|x| x.level >= item.level) {
+                close(&mut stack, &mut roots);
+            }
+            stack.push(item);
+        }
+    }
+
+    while !stack.is_empty() {
+        close(&mut stack, &mut roots);
+    }
+
+    roots
+}
+
+pub(super) fn convert_diagnostics(
+    diagnostics: Vec<Diagnostic>,
+    hash_table: &HashMap<Key, String>,
+) -> Vec<DiagnosticEntry> {
+    diagnostics
+        .into_iter()
+        .map(|d| DiagnosticEntry {
+            severity: match d.severity() {
+                Severity::Error => DiagnosticSeverity::Error,
+                Severity::Warning => DiagnosticSeverity::Warning,
+            },
+            code: d.code().to_owned(),
+            message: d.message().to_owned(),
+            key: d.key().and_then(|k| convert_key(k.clone(), hash_table)),
+            offset: d.offset(),
+        })
+        .collect()
+}
+
+pub(super) fn convert_metadata(
+    metadata: Metadata,
+    rmap: &ResultMap,
+    hash_table: &HashMap<Key, String>,
+) -> DocumentMetadata {
+    let convert_text = |x: MetaText| MetadataText {
+        keys: convert_keys(x.content().to_vec(), hash_table),
+        text: x
+            .content()
+            .iter()
+            .map(|k| rmap.plain_text_at(k))
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(" "),
+        footnotes: x
+            .footnotes()
+            .iter()
+            .map(|ks| convert_keys(ks.to_vec(), hash_table))
+            .collect(),
+    };
+
+    DocumentMetadata {
+        title: metadata.title.map(convert_text),
+        authors: metadata.authors.into_iter().map(convert_text).collect(),
+        date: metadata.date.map(convert_text),
+        abstract_: metadata.abstract_.map(|ks| convert_keys(ks, hash_table)),
+        maketitle: metadata.maketitle,
+    }
+}
+
+pub(super) fn convert_macros(macros: &Macros) -> (BTreeMap<String, String>, Vec<MacroDefinition>) {
+    let map = macros
+        .iter()
+        .filter(|x| x.default().is_none())
+        .map(|x| (x.name().to_owned(), x.body().to_owned()))
+        .collect();
+    let definitions = macros
+        .iter()
+        .map(|x| MacroDefinition {
+            name: x.name().to_owned(),
+            args: x.args(),
+            default: x.default().map(|x| x.to_owned()),
+            body: x.body().to_owned(),
+        })
+        .collect();
+
+    (map, definitions)
+}
+
+pub(super) fn convert_environments(macros: &Macros) -> Vec<EnvironmentDefinition> {
+    macros
+        .environments()
+        .map(|x| EnvironmentDefinition {
+            name: x.name().to_owned(),
+            args: x.args(),
+            default: x.default().map(|x| x.to_owned()),
+            begin: x.begin().to_owned(),
+            end: x.end().to_owned(),
+        })
+        .collect()
+}
+
+pub(super) fn convert_theorems(macros: &Macros) -> Vec<TheoremDefinition> {
+    macros
+        .theorems()
+        .map(|x| TheoremDefinition {
+            name: x.name().to_owned(),
+            title: x.title().to_owned(),
+            numbered: x.numbered(),
+            counter: x.counter().map(|x| x.to_owned()),
+            within: x.within().map(|x| x.to_owned()),
+        })
+        .collect()
+}
+
+pub(super) fn convert_notation(
+    notation: Vec<Notation>,
+    hash_table: &HashMap<Key, String>,
+) -> Vec<NotationEntry> {
+    notation
+        .into_iter()
+        .filter_map(|x| {
+            Some(NotationEntry {
+                name: x.name,
+                user_macro: x.user_macro,
+                first: convert_key(x.first, hash_table)?,
+                span: x.span,
+                definition: x.definition.and_then(|k| convert_key(k, hash_table)),
+            })
+        })
+        .collect()
+}
@@ -1,20 +1,67 @@
-use crate::key::KeyCounter;
-use crate::math_expr::{MathDisc, MathExprParseResult};
+use crate::asset;
+use crate::citation::{self, BibItem, CitationInfo};
+use crate::diagnostic::Diagnostic;
+use crate::equation;
+use crate::expand;
+use crate::figure::{self, FigureInfo};
+use crate::heading::{self, HeadingInfo, HeadingLevel};
+use crate::key::{Key, KeyCounter};
+use crate::list::{self, ItemInfo, ListInfo};
+use crate::macros::{self, Dependencies, EnvironmentInfo, Macros};
+use crate::math_ast;
+use crate::math_balance::{self, MathError, MathErrorReason};
+use crate::math_expr::{self, MathDisc, MathExprParseResult};
+use crate::metadata::{self, MetaText, Metadata, MetadataDecl};
 use crate::node::Node;
+use crate::notation::{self, Notation};
+use crate::options::ParseOptions;
 use crate::outside::ParseError;
 use crate::outside::MAX_INPUT_LENGTH;
+use crate::preamble::{self, Preamble};
+use crate::reference::{self, Labels, ReferenceInfo};
 use crate::result_map::ResultMap;
+use crate::table::{self, TableInfo, TableRow};
 use crate::tex_char::TexChar;
 use crate::tex_chars::TexChars;
+use crate::workspace::ExternalDocument;
+use std::collections::HashMap;
 use std::str::FromStr;
 
 #[derive(Debug)]
 pub(super) struct ParseOk {
     pub rmap: ResultMap,
     pub char_count: usize,
+    pub diagnostics: Vec<Diagnostic>,
+    pub metadata: Metadata,
+    pub preamble: Option<Preamble>,
+    pub macros: Macros,
+    pub notation: Vec<Notation>,
+    pub dependencies: Dependencies,
+    pub bibliography: Vec<BibItem>,
+    pub labels: Labels,
+    pub external_documents: Vec<ExternalDocument>,
 }
 
-pub(super) fn parse_paragraphs(input: &str) -> Result<ParseOk, ParseError> {
+/// 段落を読むときの状態
+///
+/// Key を出現順に振り, 文書で定義された環境を参照する.
+struct Context {
+    kc: KeyCounter,
+    macros: Macros,
+    /// 読んでいる `enumerate` の各深さの現在の番号
+    enumerate: Vec<String>,
+    itemize_depth: usize,
+    /// `\externaldocument` で読み込む文書
+    external_documents: Vec<ExternalDocument>,
+}
+
+impl Context {
+    fn count(&mut self) -> Key {
+        self.kc.count()
+    }
+}
+
+pub(super) fn parse_paragraphs(input: &str, options: &ParseOptions) -> Result<ParseOk, ParseError> {
     let input = correct_lines(input.to_string());
 
     let char_count = input.chars().count();
@@ -22,13 +69,58 @@ pub(super) fn parse_paragraphs(input: &str) -> Result<ParseOk, ParseError> {
         return Err(ParseError::TooLongInput);
     }
 
-    let mut kc = KeyCounter::new();
-    let key = kc.count();
+    let graphics_paths = asset::graphics_paths(&input);
+
+    // 読み込んだライブラリの定義は文書の定義で上書きされる
+    let library = options
+        .get_macro_library()
+        .map(|x| x.macros().clone())
+        .unwrap_or_default();
+    let mut cx = Context {
+        kc: KeyCounter::new(),
+        macros: library.clone(),
+        enumerate: Vec::new(),
+        itemize_depth: 0,
+        external_documents: Vec::new(),
+    };
+    cx.macros.scan(&input);
+    let key = cx.count();
+
+    // 完全な文書であれば本文のみを段落として読む
+    let (preamble, body) = match preamble::split_document(&input) {
+        Some((preamble, body)) => {
+            let preamble = TexChars::from_str(preamble).unwrap();
+            let (preamble, maps) = parse_preamble(preamble, &mut cx);
+            (Some((preamble, maps)), body.to_string())
+        }
+        None => (None, input),
+    };
 
-    let ps = parse_into_paragraphs(input);
+    let mut diagnostics = Vec::new();
+    let body = if options.is_macro_expansion_enabled() {
+        let limits = options.expansion_limits();
+        match expand::expand_environments(&body, &cx.macros, limits) {
+            Ok(body) => body,
+            Err(e) => {
+                diagnostics.push(expand::limit_diagnostic(e, limits, None));
+                body
+            }
+        }
+    } else {
+        body
+    };
+
+    let ps = parse_into_paragraphs(body);
     let ps: Vec<_> = ps
         .into_iter()
-        .map(|cs| parse_paragraph(cs, &mut kc))
+        .map(|cs| {
+            if options.is_dollar_heuristics_enabled() {
+                math_expr::mark_literal_dollars(cs)
+            } else {
+                cs
+            }
+        })
+        .flat_map(|cs| parse_paragraph(cs, &mut cx))
         .collect();
 
     let mut rmap = ResultMap::new(
@@ -37,7 +129,162 @@ pub(super) fn parse_paragraphs(input: &str) -> Result<ParseOk, ParseError> {
     );
     rmap.merge(ps);
 
-    Ok(ParseOk { rmap, char_count })
+    let mut macros = library;
+    let preamble = preamble.map(|(preamble, maps)| {
+        rmap.merge(maps);
+        for source in &preamble.definitions {
+            macros.define_source(source);
+        }
+        preamble
+    });
+
+    heading::assign_numbers(&mut rmap);
+    equation::assign_numbers(&mut rmap);
+    // メタデータの中の定義も取り除かれるよう, 先に定義を集める
+    macros::collect(&mut rmap, &mut macros);
+    let mut metadata = metadata::collect(&mut rmap);
+    let mut notation = notation::collect(&rmap, &macros);
+    // 展開すると中身から命令の名前が消えるので, 先に調べておく
+    let dependencies = macros::dependencies(&rmap, &macros);
+
+    if options.is_macro_expansion_enabled() {
+        diagnostics.extend(expand::expand_math(
+            &mut rmap,
+            &macros,
+            options.expansion_limits(),
+        ));
+    }
+    diagnostics.extend(build_math_outputs(&mut rmap, &macros, options));
+    notation::forget_moved_spans(&mut notation, &rmap);
+    let (labels, reference_diagnostics) = reference::resolve(&mut rmap, &macros);
+    diagnostics.extend(reference_diagnostics);
+    let (bibliography, citation_diagnostics) = citation::resolve(
+        &mut rmap,
+        options.get_bibliography(),
+        options.get_citation_style(),
+    );
+    diagnostics.extend(citation_diagnostics);
+    // メタデータの中の `\nocite` も取り除かれている
+    metadata.retain_keys(&mut |k| rmap.get(k).is_some());
+    if let Some(resolver) = options.get_asset_resolver() {
+        diagnostics.extend(figure::resolve_images(&mut rmap, &graphics_paths, resolver));
+    }
+
+    Ok(ParseOk {
+        rmap,
+        char_count,
+        diagnostics,
+        metadata,
+        preamble,
+        macros,
+        notation,
+        dependencies,
+        bibliography,
+        labels,
+        external_documents: cx.external_documents,
+    })
+}
+
+/// 入れ子になった数式の区切りを報告し,
+/// オプションに応じて数式の書き方をそろえ, 構文木や MathML を作り, KaTeX との互換性を調べる
+fn build_math_outputs(
+    rmap: &mut ResultMap,
+    macros: &Macros,
+    options: &ParseOptions,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    let keys: Vec<_> = rmap
+        .iter()
+        .filter(|(_, node)| matches!(node, Node::MathExpr(_)))
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    for key in keys {
+        let Some(Node::MathExpr(me)) = rmap.get_mut(&key) else {
+            continue;
+        };
+        if options.is_canonical_math_enabled() {
+            me.canonicalize();
+        }
+        for error in me.errors() {
+            if let MathErrorReason::NestedDelimiter(delimiter) = error.reason() {
+                diagnostics.push(
+                    Diagnostic::error(
+                        "nested_math_delimiter",
+                        format!("{} appears inside math", delimiter),
+                        Some(key.clone()),
+                    )
+                    .with_offset(error.offset()),
+                );
+            }
+        }
+        if options.is_math_ast_enabled() {
+            me.build_ast();
+        }
+        if options.is_mathml_enabled() {
+            for name in me.build_mathml() {
+                diagnostics.push(Diagnostic::warning(
+                    "unsupported_math",
+                    format!("{} cannot be converted to MathML", name),
+                    Some(key.clone()),
+                ));
+            }
+        }
+        if options.is_katex_check_enabled() {
+            for x in me.check_katex(macros) {
+                diagnostics.push(
+                    Diagnostic::warning(
+                        "katex_unsupported",
+                        format!("{} is not supported by KaTeX", x.name),
+                        Some(key.clone()),
+                    )
+                    .with_offset(x.offset),
+                );
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// プリアンブルを読む
+///
+/// メタデータの宣言は本文と同じく ResultMap として返す.
+fn parse_preamble(mut cs: TexChars, cx: &mut Context) -> (Preamble, Vec<ResultMap>) {
+    let mut result = Preamble::default();
+    let mut maps = Vec::new();
+
+    loop {
+        match cs.peek_command_name().as_deref() {
+            Some("documentclass") => {
+                result.document_class = preamble::read_packages(&mut cs).into_iter().next();
+            }
+            Some("usepackage" | "RequirePackage") => {
+                result.packages.extend(preamble::read_packages(&mut cs));
+            }
+            Some(name) if MetadataDecl::match_command(name) => {
+                maps.push(parse_metadata_command(&mut cs, cx));
+            }
+            Some(name) if preamble::is_definition(name) => {
+                result.definitions.push(preamble::read_definition(&mut cs));
+            }
+            Some("externaldocument") => {
+                cx.external_documents
+                    .extend(ExternalDocument::read(&mut cs));
+            }
+            Some(_) => {
+                cs.consume_command_name();
+            }
+            None => {
+                if cs.next().is_none() {
+                    break;
+                }
+            }
+        }
+    }
+
+    (result, maps)
 }
 
 const EOL: &str = "\n";
@@ -56,16 +303,130 @@ fn correct_lines(input: String) -> String {
 }
 
 fn parse_into_paragraphs(input: String) -> Vec<TexChars> {
-    input
-        .split("\n\n") // todo EOL定数を使う
+    split_paragraphs(&input)
+        .into_iter()
         .filter(|x| !x.is_empty())
         .map(|x| TexChars::from_str(x).unwrap())
         .collect()
 }
 
-fn parse_paragraph(mut cs: TexChars, kc: &mut KeyCounter) -> ResultMap {
-    let key = kc.count();
+/// 空行で段落に分ける
+///
+/// 閉じている環境 `\begin{..}` ... `\end{..}` の内部の空行では分けない.
+fn split_paragraphs(input: &str) -> Vec<&str> {
+    const BLANK_LINE: &str = "\n\n"; // todo EOL定数を使う
+
+    let env_name = |s: &str| s.find('}').map(|end| s[..end].to_string());
+    // 環境ごとの最後の `\end{name}` の位置. これより後ろの `\begin{name}` は閉じていない
+    let mut last_end = HashMap::new();
+    for (i, _) in input.match_indices("\\end{") {
+        if let Some(name) = env_name(&input[i + "\\end{".len()..]) {
+            last_end.insert(name, i);
+        }
+    }
+
+    let mut ps = Vec::new();
+    // 開いている環境の名前
+    let mut open: Vec<String> = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < input.len() {
+        let rest = &input[i..];
+
+        if open.is_empty() && rest.starts_with(BLANK_LINE) {
+            ps.push(&input[start..i]);
+            i += BLANK_LINE.len();
+            start = i;
+            continue;
+        }
+
+        if let Some(name) = rest.strip_prefix("\\begin{").and_then(env_name) {
+            if last_end.get(&name).is_some_and(|&end| end > i) {
+                open.push(name);
+            }
+        } else if let Some(name) = rest.strip_prefix("\\end{").and_then(env_name) {
+            // 対応する `\begin` より内側で閉じていない環境も閉じる
+            if let Some(depth) = open.iter().rposition(|x| *x == name) {
+                open.truncate(depth);
+            }
+        }
+
+        i += rest.chars().next().unwrap().len_utf8();
+    }
+    ps.push(&input[start..]);
+
+    ps
+}
+
+fn parse_paragraph(mut cs: TexChars, cx: &mut Context) -> Vec<ResultMap> {
+    let mut blocks = Vec::new();
+
+    loop {
+        let key = cx.count();
+        let maps = parse_inline(&mut cs, cx, true);
+        if !maps.is_empty() {
+            let mut map = ResultMap::new(
+                key,
+                Node::Paragraph(Some(maps.iter().map(|x| x.root()).collect())),
+            );
+            map.merge(maps);
+            blocks.push(map);
+        }
+
+        if let Some(map) = parse_block(&mut cs, cx) {
+            blocks.push(map);
+            continue;
+        }
+
+        break;
+    }
+
+    blocks
+}
+
+fn is_block_env(name: &str) -> bool {
+    table::is_table_env(name)
+        || figure::is_float_env(name)
+        || list::is_list_env(name)
+        || name == "abstract"
+        || name == "proof"
+}
+
+fn match_block_begin(cs: &TexChars, cx: &Context) -> bool {
+    HeadingLevel::match_begin(cs).is_some()
+        || cs.peek_command_name().as_deref() == Some("maketitle")
+        || cs
+            .peek_begin_env()
+            .is_some_and(|x| is_block_env(&x) || cx.macros.is_block_env(&x))
+}
+
+fn parse_block(cs: &mut TexChars, cx: &mut Context) -> Option<ResultMap> {
+    if let Some(level) = HeadingLevel::match_begin(cs) {
+        return Some(parse_heading(cs, cx, level));
+    }
+
+    if cs.peek_command_name().as_deref() == Some("maketitle") {
+        cs.consume_command_name();
+        return Some(ResultMap::new(cx.count(), Node::MakeTitle));
+    }
+
+    match cs.peek_begin_env() {
+        Some(name) if table::is_table_env(&name) => Some(parse_table(cs, cx)),
+        Some(name) if figure::is_float_env(&name) => Some(parse_figure(cs, cx)),
+        Some(name) if list::is_list_env(&name) => Some(parse_list(cs, cx)),
+        Some(name) if name == "abstract" => Some(parse_abstract(cs, cx)),
+        Some(name) if name == "proof" || cx.macros.is_block_env(&name) => {
+            Some(parse_environment(cs, cx))
+        }
+        _ => None,
+    }
+}
 
+/// 段落内の要素を読む
+///
+/// `in_paragraph` が真のときは見出しなどのブロック要素の手前で止まる.
+fn parse_inline(cs: &mut TexChars, cx: &mut Context, in_paragraph: bool) -> Vec<ResultMap> {
     let mut maps = Vec::new();
     let mut buffer: Vec<TexChar> = Vec::new();
 
@@ -74,23 +435,119 @@ fn parse_paragraph(mut cs: TexChars, kc: &mut KeyCounter) -> ResultMap {
             let content = buffer_to_content_string(&mut buffer);
             if !content.is_empty() {
                 let node = Node::RawString(content);
-                maps.push(ResultMap::new(kc.count(), node));
+                maps.push(ResultMap::new(cx.count(), node));
             }
             buffer.clear();
         };
     }
 
     loop {
-        if let Some(disc) = MathDisc::match_begin(&cs) {
+        if in_paragraph && match_block_begin(cs, cx) {
             push_raw_string!();
-            let map = parse_math_expr(&mut cs, kc, disc);
+            break;
+        }
+
+        if let Some(disc) = MathDisc::match_begin(cs) {
+            push_raw_string!();
+            let map = parse_math_expr(cs, cx, disc);
             maps.push(map);
             continue;
         }
 
+        match cs.peek_command_name().as_deref() {
+            Some("includegraphics") => {
+                push_raw_string!();
+                let image = figure::read_includegraphics(cs);
+                maps.push(ResultMap::new(cx.count(), Node::Image(image)));
+                continue;
+            }
+            Some(name) if MetadataDecl::match_command(name) => {
+                push_raw_string!();
+                let map = parse_metadata_command(cs, cx);
+                maps.push(map);
+                continue;
+            }
+            Some(name) if macros::is_definition_command(name) => {
+                if let Some(def) = macros::read_definition(cs) {
+                    push_raw_string!();
+                    maps.push(ResultMap::new(cx.count(), Node::Definition(def)));
+                    continue;
+                }
+            }
+            Some("label") => {
+                push_raw_string!();
+                cs.consume_command_name();
+                let label = cs.read_group().unwrap_or_default().into_raw_string();
+                maps.push(ResultMap::new(
+                    cx.count(),
+                    Node::Label(label.trim().to_string()),
+                ));
+                continue;
+            }
+            // 引数のない `\ref` は他の命令と同じく扱う
+            Some(name) if ReferenceInfo::match_command(name) && cs.command_has_group() => {
+                push_raw_string!();
+                let command = cs.consume_command_name().unwrap();
+                cs.consume_star(); // `\autoref*` などのリンクなしの形
+                let label = cs.read_group().unwrap().into_raw_string();
+                let info = ReferenceInfo::new(command, &label);
+                maps.push(ResultMap::new(cx.count(), Node::Reference(info)));
+                continue;
+            }
+            Some(name) if CitationInfo::match_command(name) => {
+                push_raw_string!();
+                let command = cs.consume_command_name().unwrap();
+                cs.consume_star();
+                let mut notes = Vec::new();
+                while notes.len() < 2 {
+                    match cs.read_optional() {
+                        Some(note) => notes.push(note.into_content_string()),
+                        None => break,
+                    }
+                }
+                let keys = cs.read_group().unwrap_or_default().into_raw_string();
+                let keys = keys
+                    .split(',')
+                    .map(|x| x.trim().to_string())
+                    .filter(|x| !x.is_empty())
+                    .collect();
+                let info = CitationInfo::new(command, keys, notes);
+                maps.push(ResultMap::new(cx.count(), Node::Citation(info)));
+                continue;
+            }
+            Some("bibliography" | "bibliographystyle" | "addbibresource") => {
+                // 文献は ParseOptions で渡す
+                push_raw_string!();
+                cs.consume_command_name();
+                cs.read_optional();
+                cs.read_group();
+                continue;
+            }
+            Some("externaldocument") => {
+                push_raw_string!();
+                let external = ExternalDocument::read(cs);
+                cx.external_documents.extend(external);
+                continue;
+            }
+            Some("printbibliography") => {
+                push_raw_string!();
+                cs.consume_command_name();
+                cs.read_optional();
+                continue;
+            }
+            Some("graphicspath") => {
+                // 画像の探索パスは parse_paragraphs で集めている
+                push_raw_string!();
+                cs.consume_command_name();
+                cs.read_group();
+                continue;
+            }
+            _ => {}
+        }
+
         if cs.next_is(TexChar::Backslash) {
             push_raw_string!();
-            let map = parse_inline_command(&mut cs, kc);
+            let map = parse_inline_command(cs, cx);
             maps.push(map);
             continue;
         }
@@ -103,17 +560,318 @@ fn parse_paragraph(mut cs: TexChars, kc: &mut KeyCounter) -> ResultMap {
         }
     }
 
+    maps
+}
+
+fn parse_heading(cs: &mut TexChars, cx: &mut Context, level: HeadingLevel) -> ResultMap {
+    let key = cx.count();
+
+    cs.consume_command_name();
+    let starred = cs.consume_star();
+    let short_title = cs.read_optional().map(|x| x.into_content_string());
+    let mut title = cs.read_group().unwrap_or_default();
+
+    let maps = parse_inline(&mut title, cx, false);
+    let info = HeadingInfo::new(
+        level,
+        starred,
+        short_title,
+        maps.iter().map(|x| x.root()).collect(),
+    );
+
+    let mut map = ResultMap::new(key, Node::Heading(info));
+    map.merge(maps);
+
+    map
+}
+
+fn parse_table(cs: &mut TexChars, cx: &mut Context) -> ResultMap {
+    let key = cx.count();
+
+    let env = cs.consume_begin_env().unwrap();
+    let width = if table::has_width_arg(&env) {
+        cs.read_group().map(|x| x.into_content_string())
+    } else {
+        None
+    };
+    cs.read_optional(); // 縦位置の指定
+    let spec = table::parse_column_spec(&cs.read_group().unwrap_or_default().into_raw_string());
+
+    let (rows, trailing_rules) = table::split_rows(cs.read_env_body(&env));
+
+    let mut maps = Vec::new();
+    let rows = rows
+        .into_iter()
+        .map(|row| {
+            let cells = row
+                .cells
+                .into_iter()
+                .map(|cell| {
+                    let mut cell = table::read_cell(cell);
+                    let ms = parse_inline(&mut cell.content, cx, false);
+                    let keys = ms.iter().map(|x| x.root()).collect();
+                    maps.extend(ms);
+                    cell.into_cell(keys)
+                })
+                .collect();
+            TableRow::new(row.rules, cells)
+        })
+        .collect();
+
+    let info = TableInfo::new(env, width, spec, rows, trailing_rules);
+    let mut map = ResultMap::new(key, Node::Table(info));
+    map.merge(maps);
+
+    map
+}
+
+fn parse_figure(cs: &mut TexChars, cx: &mut Context) -> ResultMap {
+    let key = cx.count();
+
+    let env = cs.consume_begin_env().unwrap();
+    let placement = cs.read_optional().map(|x| x.into_raw_string());
+    let body = cs.read_env_body(&env);
+
+    let mut maps = Vec::new();
+    let info = parse_float_body(FigureInfo::new(env, placement), body, cx, &mut maps);
+
+    let mut map = ResultMap::new(key, Node::Figure(info));
+    map.merge(maps);
+
+    map
+}
+
+/// 図表環境の中身を読む
+///
+/// 画像・キャプション・ラベル・表・サブ図以外は読み飛ばす.
+fn parse_float_body(
+    mut info: FigureInfo,
+    mut cs: TexChars,
+    cx: &mut Context,
+    maps: &mut Vec<ResultMap>,
+) -> FigureInfo {
+    loop {
+        match cs.peek_begin_env() {
+            Some(name) if figure::is_subfloat_env(&name) => {
+                cs.consume_begin_env();
+                let placement = cs.read_optional().map(|x| x.into_raw_string());
+                cs.read_group(); // 幅
+                let body = cs.read_env_body(&name);
+                let sub = parse_float_body(FigureInfo::new(name, placement), body, cx, maps);
+                info.push_subfigure(sub);
+                continue;
+            }
+            Some(_) => {
+                if let Some(map) = parse_block(&mut cs, cx) {
+                    info.push_content(map.root());
+                    maps.push(map);
+                    continue;
+                }
+            }
+            None => {}
+        }
+
+        match cs.peek_command_name().as_deref() {
+            Some("includegraphics") => {
+                info.push_image(figure::read_includegraphics(&mut cs));
+                continue;
+            }
+            Some("caption") => {
+                cs.consume_command_name();
+                let short_caption = cs.read_optional().map(|x| x.into_content_string());
+                let mut caption = cs.read_group().unwrap_or_default();
+                let ms = parse_inline(&mut caption, cx, false);
+                info.set_caption(short_caption, ms.iter().map(|x| x.root()).collect());
+                maps.extend(ms);
+                continue;
+            }
+            Some("label") => {
+                cs.consume_command_name();
+                let label = cs.read_group().unwrap_or_default().into_raw_string();
+                info.set_label(label.trim().to_string());
+                continue;
+            }
+            _ => {}
+        }
+
+        if cs.next().is_none() {
+            break;
+        }
+    }
+
+    info
+}
+
+fn parse_metadata_command(cs: &mut TexChars, cx: &mut Context) -> ResultMap {
+    let key = cx.count();
+
+    let name = cs.consume_command_name().unwrap();
+    cs.read_optional(); // beamer などの短い版は使わない
+    let arg = cs.read_group().unwrap_or_default();
+
+    let mut maps = Vec::new();
+    let decl = match name.as_str() {
+        "title" => MetadataDecl::Title(parse_meta_text(arg, cx, &mut maps)),
+        "author" => MetadataDecl::Author(
+            metadata::split_authors(arg)
+                .into_iter()
+                .map(|x| parse_meta_text(x, cx, &mut maps))
+                .collect(),
+        ),
+        _ => MetadataDecl::Date(parse_meta_text(arg, cx, &mut maps)),
+    };
+
+    let mut map = ResultMap::new(key, Node::Metadata(decl));
+    map.merge(maps);
+
+    map
+}
+
+fn parse_meta_text(cs: TexChars, cx: &mut Context, maps: &mut Vec<ResultMap>) -> MetaText {
+    let (mut rest, thanks) = metadata::extract_thanks(cs);
+
+    let ms = parse_inline(&mut rest, cx, false);
+    let content = ms.iter().map(|x| x.root()).collect();
+    maps.extend(ms);
+
+    let footnotes = thanks
+        .into_iter()
+        .map(|mut x| {
+            let ms = parse_inline(&mut x, cx, false);
+            let keys = ms.iter().map(|x| x.root()).collect();
+            maps.extend(ms);
+            keys
+        })
+        .collect();
+
+    MetaText::new(content, footnotes)
+}
+
+fn parse_abstract(cs: &mut TexChars, cx: &mut Context) -> ResultMap {
+    let key = cx.count();
+
+    let env = cs.consume_begin_env().unwrap();
+    let body = cs.read_env_body(&env);
+
+    let ps: Vec<_> = parse_into_paragraphs(body.into_raw_string())
+        .into_iter()
+        .flat_map(|cs| parse_paragraph(cs, cx))
+        .collect();
+
     let mut map = ResultMap::new(
         key,
-        Node::Paragraph(Some(maps.iter().map(|x| x.root()).collect())),
+        Node::Metadata(MetadataDecl::Abstract(
+            ps.iter().map(|x| x.root()).collect(),
+        )),
     );
+    map.merge(ps);
+
+    map
+}
+
+/// 箇条書きを読む
+///
+/// 各項目の中身は段落として読む. 入れ子の深さに応じて記号と番号を決める.
+fn parse_list(cs: &mut TexChars, cx: &mut Context) -> ResultMap {
+    let key = cx.count();
+
+    let env = cs.consume_begin_env().unwrap();
+    let mut body = cs.read_env_body(&env);
+    body.read_optional(); // enumitem などの書式の指定
+
+    let mut maps = Vec::new();
+    let mut items = Vec::new();
+    let mut n = 0;
+    for (label, content) in list::split_items(body) {
+        let (marker, number) = match env.as_str() {
+            "enumerate" => {
+                let depth = cx.enumerate.len();
+                if label.is_none() {
+                    n += 1;
+                }
+                let value = list::enumerate_value(depth, n);
+                let marker = list::enumerate_marker(depth, &value);
+                cx.enumerate.push(value);
+                let number = label
+                    .is_none()
+                    .then(|| list::enumerate_number(&cx.enumerate));
+                (label.unwrap_or(marker), number)
+            }
+            "itemize" => {
+                let marker = list::itemize_marker(cx.itemize_depth);
+                cx.itemize_depth += 1;
+                (label.unwrap_or(marker), None)
+            }
+            _ => (label.unwrap_or_default(), None),
+        };
+
+        let ps: Vec<_> = parse_into_paragraphs(content)
+            .into_iter()
+            .flat_map(|cs| parse_paragraph(cs, cx))
+            .collect();
+        items.push(ItemInfo::new(
+            marker,
+            number,
+            ps.iter().map(|x| x.root()).collect(),
+        ));
+        maps.extend(ps);
+
+        match env.as_str() {
+            "enumerate" => {
+                cx.enumerate.pop();
+            }
+            "itemize" => cx.itemize_depth -= 1,
+            _ => {}
+        }
+    }
+
+    let mut map = ResultMap::new(key, Node::List(ListInfo::new(env, items)));
     map.merge(maps);
 
     map
 }
 
-fn parse_math_expr(cs: &mut TexChars, kc: &mut KeyCounter, disc: MathDisc) -> ResultMap {
+/// `\newenvironment` や `\newtheorem` で定義された環境を読む
+///
+/// 中身は段落として読み, 引数は文字列のまま持つ.
+/// 定理環境は `\begin{thm}[Fermat]` の省略可能引数だけを読む.
+fn parse_environment(cs: &mut TexChars, cx: &mut Context) -> ResultMap {
+    let key = cx.count();
+
+    let name = cs.consume_begin_env().unwrap();
+    let (count, default) = match cx.macros.environment(&name) {
+        Some(def) => (def.args(), def.default().map(|x| x.to_string())),
+        None => (0, None),
+    };
+
+    let mut args = Vec::new();
+    // 定理の注釈や `\begin{proof}[Proof of ..]`
+    if cx.macros.theorem(&name).is_some() || name == "proof" {
+        args.extend(cs.read_optional().map(|x| x.into_raw_string()));
+    }
+    if let Some(default) = default {
+        args.push(cs.read_optional().map_or(default, |x| x.into_raw_string()));
+    }
+    while args.len() < count {
+        args.push(cs.read_group().unwrap_or_default().into_raw_string());
+    }
+
+    let body = cs.read_env_body(&name);
+    let ps: Vec<_> = parse_into_paragraphs(body.into_raw_string())
+        .into_iter()
+        .flat_map(|cs| parse_paragraph(cs, cx))
+        .collect();
+
+    let info = EnvironmentInfo::new(name, args, ps.iter().map(|x| x.root()).collect());
+    let mut map = ResultMap::new(key, Node::Environment(info));
+    map.merge(ps);
+
+    map
+}
+
+fn parse_math_expr(cs: &mut TexChars, cx: &mut Context, disc: MathDisc) -> ResultMap {
     disc.consume_begin(cs);
+    let before = cs.clone();
 
     let mut buffer = Vec::new();
     let mut match_end = false;
@@ -125,6 +883,22 @@ fn parse_math_expr(cs: &mut TexChars, kc: &mut KeyCounter, disc: MathDisc) -> Re
             break;
         }
 
+        // `\text{..}` の中の `$` は数式を閉じない
+        if let Some(name) = cs
+            .peek_command_name()
+            .filter(|x| math_ast::is_text_command(x))
+        {
+            cs.consume_command_name();
+            buffer.push(TexChar::Backslash);
+            buffer.extend(name.chars().map(TexChar::from));
+            if let Some(group) = cs.read_group() {
+                buffer.push(TexChar::LBrace);
+                buffer.extend(group);
+                buffer.push(TexChar::RBrace);
+            }
+            continue;
+        }
+
         if let Some(c) = cs.next() {
             buffer.push(c);
         } else {
@@ -132,17 +906,72 @@ fn parse_math_expr(cs: &mut TexChars, kc: &mut KeyCounter, disc: MathDisc) -> Re
         }
     }
 
+    // 閉じていなければ, 数式らしい範囲だけを誤りとし, 残りは通常通り読む
+    if !match_end {
+        *cs = before;
+        let extent = unclosed_math_extent(cs, &disc);
+        buffer = cs.by_ref().take(extent).collect();
+    }
+
     let content = buffer_to_content_string(&mut buffer);
-    let node = if match_end {
-        MathExprParseResult::ok(content, disc)
+    let (content, equations) = if disc.is_display() {
+        equation::extract(&content, disc.env())
     } else {
-        MathExprParseResult::err(content, disc)
+        (content, Vec::new())
     };
+    let mut errors = math_balance::validate(&content);
+    if !match_end {
+        errors.push(MathError::new(
+            MathErrorReason::UnclosedMath,
+            content.chars().count(),
+        ));
+    }
+    let node = MathExprParseResult::new(content, disc, errors).with_equations(equations);
 
-    ResultMap::new(kc.count(), Node::MathExpr(node))
+    ResultMap::new(cx.count(), Node::MathExpr(node))
 }
 
-fn parse_inline_command(cs: &mut TexChars, kc: &mut KeyCounter) -> ResultMap {
+/// 閉じていない数式の範囲を推測する
+///
+/// インライン数式は改行や, 文章の続く文末の句読点まで.
+/// ディスプレイ数式は空行や, 次のディスプレイ数式の開始まで.
+fn unclosed_math_extent(cs: &TexChars, disc: &MathDisc) -> usize {
+    use TexChar::*;
+
+    let chars: Vec<_> = cs.iter().collect();
+    let is_text = |c: Option<&&TexChar>| matches!(c, Some(Char(c)) if c.is_alphabetic());
+
+    for (i, &c) in chars.iter().enumerate() {
+        let next = chars.get(i + 1);
+
+        let stop = if disc.is_inline() {
+            match c {
+                Return => true,
+                Char('。' | '．') => true,
+                Period | Char('?' | '!') => match next {
+                    None => true,
+                    Some(Whitespace | Return) => is_text(chars.get(i + 2)),
+                    Some(Char(c)) => !c.is_ascii(),
+                    _ => false,
+                },
+                _ => false,
+            }
+        } else {
+            matches!(
+                (c, next),
+                (Return, Some(Return)) | (Dollar, Some(Dollar)) | (Backslash, Some(LBracket))
+            )
+        };
+
+        if stop {
+            return i;
+        }
+    }
+
+    chars.len()
+}
+
+fn parse_inline_command(cs: &mut TexChars, cx: &mut Context) -> ResultMap {
     let mut buffer = Vec::new();
 
     buffer.push(cs.next().unwrap());
@@ -161,7 +990,7 @@ fn parse_inline_command(cs: &mut TexChars, kc: &mut KeyCounter) -> ResultMap {
 
     let content = buffer_to_content_string(&mut buffer);
 
-    ResultMap::new(kc.count(), Node::InlineCommand(Some(content)))
+    ResultMap::new(cx.count(), Node::InlineCommand(Some(content)))
 }
 
 fn buffer_to_content_string(cs: &mut Vec<TexChar>) -> String {
@@ -183,7 +1012,7 @@ mod tests {
             let input = "a".repeat(MAX_INPUT_LENGTH + 1);
 
             assert!(matches!(
-                parse_paragraphs(&input),
+                parse_paragraphs(&input, &ParseOptions::default()),
                 Err(ParseError::TooLongInput)
             ));
         }
@@ -193,7 +1022,7 @@ mod tests {
             let input = "a".repeat(MAX_INPUT_LENGTH) + " %foo";
 
             assert!(!matches!(
-                parse_paragraphs(&input),
+                parse_paragraphs(&input, &ParseOptions::default()),
                 Err(ParseError::TooLongInput)
             ));
         }
@@ -217,7 +1046,7 @@ mod tests {
             お手伝いできるかもしれません。
             ";
 
-            println!("{:#?}", parse_paragraphs(input));
+            println!("{:#?}", parse_paragraphs(input, &ParseOptions::default()));
         }
 
         #[test]
@@ -228,7 +1057,239 @@ mod tests {
         \[Z \cong \left{A \oplus B\right. .\]
         例えば$Y2$は$$x_2 \otimes y_2$$である.";
 
-            println!("{:#?}", parse_paragraphs(input));
+            println!("{:#?}", parse_paragraphs(input, &ParseOptions::default()));
+        }
+    }
+
+    mod parse_heading {
+        use super::*;
+
+        fn headings(input: &str) -> Vec<(usize, Option<String>, String)> {
+            let ParseOk { rmap, .. } = parse_paragraphs(input, &ParseOptions::default()).unwrap();
+            rmap.iter()
+                .filter_map(|(_, node)| match node {
+                    Node::Heading(info) => Some((
+                        info.level().depth(),
+                        info.number().map(|x| x.to_owned()),
+                        info.anchor().to_owned(),
+                    )),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        #[test]
+        fn 見出しで段落が分かれる() {
+            let ParseOk { rmap, .. } =
+                parse_paragraphs("前文\\section{Intro}\n本文", &ParseOptions::default()).unwrap();
+            let kinds: Vec<_> = rmap
+                .iter()
+                .filter_map(|(_, node)| match node {
+                    Node::Paragraph(_) => Some("para"),
+                    Node::Heading(_) => Some("heading"),
+                    _ => None,
+                })
+                .collect();
+
+            assert_eq!(kinds, vec!["para", "heading", "para"]);
+        }
+
+        #[test]
+        fn 番号付け() {
+            let input = r"\section{Intro}
+            \subsection{Motivation}
+            \subsection*{Aside}
+            \subsection{Goal}
+            \section[Short]{Main $X$ result}
+            \subsubsection{Detail}
+            \paragraph{Note}";
+
+            assert_eq!(
+                headings(input),
+                vec![
+                    (1, Some("1".to_string()), "intro".to_string()),
+                    (2, Some("1.1".to_string()), "motivation".to_string()),
+                    (2, None, "aside".to_string()),
+                    (2, Some("1.2".to_string()), "goal".to_string()),
+                    (1, Some("2".to_string()), "main-x-result".to_string()),
+                    (3, Some("2.0.1".to_string()), "detail".to_string()),
+                    (4, None, "note".to_string()),
+                ]
+            );
+        }
+
+        #[test]
+        fn chapterがあれば章番号から数える() {
+            let input = r"\chapter{A}
+            \section{B}
+
+            \chapter{C}
+            \section{B}";
+
+            assert_eq!(
+                headings(input),
+                vec![
+                    (0, Some("1".to_string()), "a".to_string()),
+                    (1, Some("1.1".to_string()), "b".to_string()),
+                    (0, Some("2".to_string()), "c".to_string()),
+                    (1, Some("2.1".to_string()), "b-1".to_string()),
+                ]
+            );
+        }
+    }
+
+    mod parse_math_expr {
+        use super::*;
+
+        fn inlines(input: &str) -> Vec<(&'static str, String)> {
+            inlines_with(input, &ParseOptions::default())
+        }
+
+        fn inlines_with(input: &str, options: &ParseOptions) -> Vec<(&'static str, String)> {
+            let ParseOk { rmap, .. } = parse_paragraphs(input, options).unwrap();
+            rmap.iter()
+                .filter_map(|(_, node)| match node {
+                    Node::RawString(s) => Some(("text", s.to_owned())),
+                    Node::MathExpr(me) if me.is_ok() => Some(("math", me.content_str().to_owned())),
+                    Node::MathExpr(me) => Some(("error", me.content_str().to_owned())),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        #[test]
+        fn 閉じていないインライン数式は改行まで() {
+            assert_eq!(
+                inlines("前 $x + y について\n次の行"),
+                vec![
+                    ("text", "前".to_string()),
+                    ("error", "x + y について".to_string()),
+                    ("text", "次の行".to_string()),
+                ]
+            );
+        }
+
+        #[test]
+        fn 閉じていないインライン数式は文末まで() {
+            assert_eq!(
+                inlines("Let $x = 1.5. Then y holds"),
+                vec![
+                    ("text", "Let".to_string()),
+                    ("error", "x = 1.5".to_string()),
+                    ("text", ". Then y holds".to_string()),
+                ]
+            );
+            assert_eq!(
+                inlines("$a^2。次"),
+                vec![("error", "a^2".to_string()), ("text", "。次".to_string()),]
+            );
+        }
+
+        #[test]
+        fn 通貨のドル記号() {
+            let options = ParseOptions::new().dollar_heuristics(true);
+            let heuristic = |input| inlines_with(input, &options);
+
+            assert_eq!(
+                heuristic("it costs $5 and $10"),
+                vec![("text", "it costs $5 and $10".to_string())]
+            );
+            assert_eq!(
+                heuristic("$x$ と $$y$$"),
+                vec![
+                    ("math", "x".to_string()),
+                    ("text", "と".to_string()),
+                    ("math", "y".to_string()),
+                ]
+            );
+            assert_eq!(
+                heuristic("$ y$ or $z $"),
+                vec![("text", "$ y$ or $z $".to_string())]
+            );
+            assert_eq!(heuristic("$x$1"), vec![("text", "$x$1".to_string())]);
+            // `\text{..}` の中の `$` で閉じない
+            assert_eq!(
+                heuristic(r"A $\text{if $n$ odd}$ B"),
+                vec![
+                    ("text", "A".to_string()),
+                    ("math", r"\text{if $n$ odd}".to_string()),
+                    ("text", "B".to_string()),
+                ]
+            );
+            assert_eq!(
+                heuristic(r"$\mbox {$ n $ odd}$ and $5"),
+                vec![
+                    ("math", r"\mbox{$ n $ odd}".to_string()),
+                    ("text", "and $5".to_string()),
+                ]
+            );
+
+            // 既定では従来通り
+            assert_eq!(
+                inlines("it costs $5 and $10")[1],
+                ("math", "5 and".to_string())
+            );
+        }
+
+        #[test]
+        fn 閉じていないディスプレイ数式は次のディスプレイ数式まで() {
+            assert_eq!(
+                inlines(r"\[ a + b $$c$$ d"),
+                vec![
+                    ("error", "a + b".to_string()),
+                    ("math", "c".to_string()),
+                    ("text", "d".to_string()),
+                ]
+            );
+        }
+    }
+
+    mod parse_preamble {
+        use super::*;
+
+        #[test]
+        fn 本文のみを段落として読む() {
+            let input = r"\documentclass[a4paper]{article}
+            \usepackage{amsmath, amssymb}
+            \newcommand{\R}{\mathbb{R}}
+            \title{T}
+            \begin{document}
+            本文
+            \end{document}
+            後ろ";
+            let ParseOk {
+                rmap,
+                preamble,
+                metadata,
+                ..
+            } = parse_paragraphs(input, &ParseOptions::default()).unwrap();
+            let preamble = preamble.unwrap();
+
+            assert_eq!(
+                preamble.document_class.map(|x| (x.name, x.options)),
+                Some(("article".to_string(), vec!["a4paper".to_string()]))
+            );
+            assert_eq!(
+                preamble
+                    .packages
+                    .iter()
+                    .map(|x| x.name.as_str())
+                    .collect::<Vec<_>>(),
+                vec!["amsmath", "amssymb"]
+            );
+            assert_eq!(preamble.definitions, vec![r"\newcommand{\R}{\mathbb{R}}"]);
+            assert!(metadata.title.is_some());
+            assert_eq!(rmap.plain_text_at(&rmap.root()), "本文");
+        }
+
+        #[test]
+        fn 断片はそのまま読む() {
+            let ParseOk { rmap, preamble, .. } =
+                parse_paragraphs(r"\usepackage{x}本文", &ParseOptions::default()).unwrap();
+
+            // 従来どおり命令と文字列として読む
+            assert!(preamble.is_none());
+            assert_eq!(rmap.plain_text_at(&rmap.root()), "{x}本文");
         }
     }
 
@@ -278,6 +1339,50 @@ mod tests {
             );
         }
 
+        #[test]
+        fn 環境の中の空行では分けない() {
+            assert_eq!(
+                parse_into_paragraphs("a\n\n\\begin{x}b\n\nc\\end{x}\n\nd".to_string()),
+                vec![
+                    TexChars::from_str("a").unwrap(),
+                    TexChars::from_str("\\begin{x}b\n\nc\\end{x}").unwrap(),
+                    TexChars::from_str("d").unwrap(),
+                ]
+            );
+        }
+
+        #[test]
+        fn 環境の名前を対応させる() {
+            assert_eq!(
+                parse_into_paragraphs(
+                    "\\begin{x}a\\end{y}\n\nb\\begin{y}\\end{y}\\end{x}\n\nc".to_string()
+                ),
+                vec![
+                    TexChars::from_str("\\begin{x}a\\end{y}\n\nb\\begin{y}\\end{y}\\end{x}")
+                        .unwrap(),
+                    TexChars::from_str("c").unwrap(),
+                ]
+            );
+            assert_eq!(
+                parse_into_paragraphs("\\begin{x}\\begin{z}a\\end{x}\n\nb".to_string()),
+                vec![
+                    TexChars::from_str("\\begin{x}\\begin{z}a\\end{x}").unwrap(),
+                    TexChars::from_str("b").unwrap(),
+                ]
+            );
+        }
+
+        #[test]
+        fn 閉じていない環境は無視する() {
+            assert_eq!(
+                parse_into_paragraphs("\\begin{x}a\n\nb".to_string()),
+                vec![
+                    TexChars::from_str("\\begin{x}a").unwrap(),
+                    TexChars::from_str("b").unwrap(),
+                ]
+            );
+        }
+
         #[test]
         fn 連続した空行() {
             assert_eq!(
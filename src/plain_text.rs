@@ -0,0 +1,100 @@
+use crate::key::Key;
+use crate::math_text::{self, normalize_spaces};
+use crate::node::Node;
+use crate::result_map::ResultMap;
+
+/// 文書全体を装飾なしの文字列に書き出す
+///
+/// ブロックは空行で区切り, 数式は Unicode の文字列にする.
+pub(super) fn export(rmap: &ResultMap) -> String {
+    export_at(rmap, &rmap.root())
+}
+
+fn export_at(rmap: &ResultMap, key: &Key) -> String {
+    match rmap.get(key) {
+        Some(Node::ParagraphList(Some(ks))) => ks
+            .iter()
+            .map(|k| export_at(rmap, k))
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        Some(Node::Paragraph(Some(ks))) => normalize_spaces(&join_inline(rmap, ks)),
+        // 段落内の改行は数式の前後を除いて空白とみなす
+        Some(Node::RawString(s)) => s.replace('\n', " "),
+        Some(Node::MathExpr(me)) => {
+            let s = math_text::math_to_unicode(me.content_str());
+            if me.is_display() {
+                format!("\n{}\n", s)
+            } else {
+                s
+            }
+        }
+        Some(Node::Reference(info)) => info.display_text(),
+        Some(Node::Citation(info)) => info.display_text(),
+        Some(Node::Environment(info)) => info
+            .content()
+            .iter()
+            .map(|k| export_at(rmap, k))
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        Some(Node::List(info)) => info
+            .items()
+            .iter()
+            .map(|item| {
+                let content = item
+                    .content()
+                    .iter()
+                    .map(|k| export_at(rmap, k))
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!("{} {}", item.marker(), content).trim().to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Some(Node::Heading(info)) => {
+            let title = inline_text(rmap, info.title());
+            match info.number() {
+                Some(number) => format!("{} {}", number, title),
+                None => title,
+            }
+        }
+        Some(Node::Table(info)) => info
+            .rows()
+            .iter()
+            .map(|row| {
+                row.cells()
+                    .iter()
+                    .map(|cell| inline_text(rmap, cell.content()))
+                    .collect::<Vec<_>>()
+                    .join(" | ")
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Some(Node::Figure(info)) => {
+            let mut blocks: Vec<_> = info
+                .content()
+                .iter()
+                .map(|k| export_at(rmap, k))
+                .filter(|s| !s.is_empty())
+                .collect();
+            if let Some(caption) = info.caption() {
+                blocks.push(inline_text(rmap, caption));
+            }
+            blocks.join("\n")
+        }
+        _ => String::new(),
+    }
+}
+
+fn join_inline(rmap: &ResultMap, ks: &[Key]) -> String {
+    ks.iter()
+        .map(|k| export_at(rmap, k))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn inline_text(rmap: &ResultMap, ks: &[Key]) -> String {
+    normalize_spaces(&join_inline(rmap, ks)).replace('\n', " ")
+}
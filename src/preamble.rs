@@ -0,0 +1,214 @@
+use crate::tex_char::TexChar;
+use crate::tex_chars::TexChars;
+use serde::Serialize;
+
+const BEGIN_DOCUMENT: &str = "\\begin{document}";
+const END_DOCUMENT: &str = "\\end{document}";
+
+#[derive(Debug, Default, Serialize, Clone, PartialEq, Eq)]
+pub struct Preamble {
+    pub document_class: Option<Package>,
+    pub packages: Vec<Package>,
+    pub definitions: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct Package {
+    pub name: String,
+    pub options: Vec<String>,
+}
+
+/// `\begin{document}` があればプリアンブルと本文に分ける
+pub(super) fn split_document(input: &str) -> Option<(&str, &str)> {
+    let begin = input.find(BEGIN_DOCUMENT)?;
+    let preamble = &input[..begin];
+
+    let body = &input[begin + BEGIN_DOCUMENT.len()..];
+    let body = match body.find(END_DOCUMENT) {
+        Some(end) => &body[..end],
+        None => body,
+    };
+
+    Some((preamble, body))
+}
+
+/// `\documentclass[opts]{cls}` や `\usepackage[opts]{a,b}` を読む
+pub(super) fn read_packages(cs: &mut TexChars) -> Vec<Package> {
+    cs.consume_command_name();
+
+    let options = cs
+        .read_optional()
+        .map(|x| split_list(&x.into_raw_string()))
+        .unwrap_or_default();
+    let names = cs
+        .read_group()
+        .map(|x| split_list(&x.into_raw_string()))
+        .unwrap_or_default();
+    cs.read_optional(); // 日付の指定
+
+    names
+        .into_iter()
+        .map(|name| Package {
+            name,
+            options: options.clone(),
+        })
+        .collect()
+}
+
+fn split_list(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_string())
+        .collect()
+}
+
+pub(super) fn is_definition(name: &str) -> bool {
+    matches!(
+        name,
+        "newcommand"
+            | "renewcommand"
+            | "providecommand"
+            | "DeclareRobustCommand"
+            | "def"
+            | "gdef"
+            | "edef"
+            | "xdef"
+            | "let"
+            | "DeclareMathOperator"
+            | "newenvironment"
+            | "renewenvironment"
+            | "newtheorem"
+            | "theoremstyle"
+            | "newcounter"
+            | "numberwithin"
+    )
+}
+
+/// 定義命令をその引数ごと読み, ソースをそのまま返す
+pub(super) fn read_definition(cs: &mut TexChars) -> String {
+    let before = cs.clone();
+
+    let name = cs.consume_command_name().unwrap_or_default();
+    cs.consume_star();
+
+    match name.as_str() {
+        "let" => {
+            read_token(cs);
+            cs.skip_whitespaces();
+            if cs.next_is(TexChar::Char('=')) {
+                cs.next();
+            }
+            read_token(cs);
+        }
+        "def" | "gdef" | "edef" | "xdef" => {
+            read_token(cs);
+            // パラメータ部 `#1#2` は本体の `{` まで読み飛ばす
+            while !cs.next_is(TexChar::LBrace) && cs.next().is_some() {}
+            cs.read_group();
+        }
+        _ => {
+            let mut first = true;
+            loop {
+                if cs.read_group().is_some() || cs.read_optional().is_some() {
+                    first = false;
+                    continue;
+                }
+                // `\newcommand\foo{...}` の形
+                if first && cs.peek_command_name().is_some() {
+                    read_token(cs);
+                    first = false;
+                    continue;
+                }
+                break;
+            }
+        }
+    }
+
+    let consumed = before.len() - cs.len();
+    before
+        .take(consumed)
+        .collect::<TexChars>()
+        .into_raw_string()
+        .trim()
+        .to_string()
+}
+
+/// 制御綴または1文字を読む
+fn read_token(cs: &mut TexChars) {
+    cs.skip_whitespaces();
+    if cs.consume_command_name().is_some() {
+        return;
+    }
+    if cs.next() == Some(TexChar::Backslash) {
+        cs.next();
+    }
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn split_document() {
+        assert_eq!(
+            super::split_document("pre\\begin{document}body\\end{document}post"),
+            Some(("pre", "body"))
+        );
+        assert_eq!(
+            super::split_document("pre\\begin{document}body"),
+            Some(("pre", "body"))
+        );
+        assert_eq!(super::split_document("fragment"), None);
+    }
+
+    #[test]
+    fn read_packages() {
+        let mut cs = TexChars::from_str(r"\usepackage[a4paper, final]{ amsmath,amssymb }").unwrap();
+        assert_eq!(
+            super::read_packages(&mut cs),
+            vec![
+                Package {
+                    name: "amsmath".to_string(),
+                    options: vec!["a4paper".to_string(), "final".to_string()]
+                },
+                Package {
+                    name: "amssymb".to_string(),
+                    options: vec!["a4paper".to_string(), "final".to_string()]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn read_definition() {
+        macro_rules! assert_definition {
+            ($input:expr, $expected:expr, $rest:expr) => {
+                let mut cs = TexChars::from_str($input).unwrap();
+                assert_eq!(super::read_definition(&mut cs), $expected);
+                assert_eq!(cs.into_content_string(), $rest);
+            };
+        }
+
+        assert_definition!(
+            "\\newcommand{\\R}{\\mathbb{R}}\n次",
+            r"\newcommand{\R}{\mathbb{R}}",
+            "次"
+        );
+        assert_definition!(
+            r"\newcommand*\pair[2][x]{(#1,#2)} 次",
+            r"\newcommand*\pair[2][x]{(#1,#2)}",
+            "次"
+        );
+        assert_definition!(r"\def\foo#1#2{#2#1}次", r"\def\foo#1#2{#2#1}", "次");
+        assert_definition!(r"\let\phi=\varphi 次", r"\let\phi=\varphi", "次");
+        assert_definition!(
+            r"\DeclareMathOperator*{\argmax}{arg\,max}",
+            r"\DeclareMathOperator*{\argmax}{arg\,max}",
+            ""
+        );
+    }
+}
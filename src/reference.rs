@@ -0,0 +1,781 @@
+use crate::diagnostic::Diagnostic;
+use crate::heading::HeadingLevel;
+use crate::key::Key;
+use crate::macros::Macros;
+use crate::metadata::MetadataDecl;
+use crate::node::Node;
+use crate::result_map::ResultMap;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+/// `\ref{..}` や `\cref{..}` などによる参照
+///
+/// cleveref の `\cref{a,b}` は複数のラベルを参照する.
+#[derive(Debug)]
+pub(super) struct ReferenceInfo {
+    command: String,
+    labels: Vec<String>,
+    /// ラベルごとの参照先. 解決できなければ None
+    targets: Vec<Option<ResolvedTarget>>,
+    text: Option<String>,
+}
+
+/// 解決した参照先
+#[derive(Debug, Clone)]
+pub(super) struct ResolvedTarget {
+    /// 他の文書のラベルであれば, その文書の名前
+    document: Option<String>,
+    /// `document` があればその文書の対象
+    target: Target,
+}
+
+// 参照先の Key は文書の他の部分を変えるだけで変わるので含めない
+impl Hash for ReferenceInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.command.hash(state);
+        self.labels.hash(state);
+        for x in &self.targets {
+            x.as_ref().map(|x| &x.document).hash(state);
+            x.as_ref().map(|x| &x.target.number).hash(state);
+            x.as_ref().map(|x| &x.target.anchor).hash(state);
+        }
+        self.text.hash(state);
+    }
+}
+
+impl ReferenceInfo {
+    /// `\cref` と `\Cref` ではラベルを `,` で分ける
+    pub(crate) fn new(command: String, label: &str) -> Self {
+        let labels: Vec<_> = if matches!(command.as_str(), "cref" | "Cref") {
+            label
+                .split(',')
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty())
+                .collect()
+        } else {
+            vec![label.trim().to_string()]
+        };
+
+        Self {
+            command,
+            targets: vec![None; labels.len()],
+            labels,
+            text: None,
+        }
+    }
+
+    pub(crate) fn match_command(name: &str) -> bool {
+        matches!(
+            name,
+            "ref" | "eqref" | "pageref" | "autoref" | "cref" | "Cref" | "nameref"
+        )
+    }
+
+    pub(crate) fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// 書かれたとおりのラベル. 複数あれば `,` でつなぐ
+    pub(crate) fn label(&self) -> String {
+        self.labels.join(",")
+    }
+
+    /// ラベルとその参照先
+    pub(crate) fn targets(&self) -> impl Iterator<Item = (&str, Option<&ResolvedTarget>)> {
+        self.labels
+            .iter()
+            .map(|x| x.as_str())
+            .zip(self.targets.iter().map(|x| x.as_ref()))
+    }
+
+    /// 文書中での表記. 解決できなかった参照は `??` とする
+    pub(crate) fn display_text(&self) -> String {
+        self.text.clone().unwrap_or_else(|| "??".to_string())
+    }
+
+    fn resolve_to(&mut self, index: usize, target: &Target, document: Option<&str>) {
+        self.targets[index] = Some(ResolvedTarget {
+            document: document.map(|x| x.to_string()),
+            target: target.clone(),
+        });
+
+        let targets: Vec<_> = self
+            .targets
+            .iter()
+            .map(|x| x.as_ref().map(|x| &x.target))
+            .collect();
+        self.text = match targets.as_slice() {
+            [Some(target)] => Some(target.format(&self.command)),
+            _ => Some(format_cref(&targets, self.command == "Cref")),
+        };
+    }
+}
+
+impl ResolvedTarget {
+    pub(crate) fn document(&self) -> Option<&str> {
+        self.document.as_deref()
+    }
+
+    pub(crate) fn key(&self) -> &Key {
+        &self.target.key
+    }
+
+    pub(crate) fn number(&self) -> Option<&str> {
+        self.target.number.as_deref()
+    }
+
+    pub(crate) fn anchor(&self) -> Option<&str> {
+        self.target.anchor.as_deref()
+    }
+}
+
+/// cleveref の `\cref{a,b,c}` の表記を作る
+///
+/// 同じ種類の対象が続けばまとめ, `theorems 1 and 2` や `eqs. (1), (2) and (3)` のようにする.
+fn format_cref(targets: &[Option<&Target>], capital: bool) -> String {
+    let mut groups: Vec<(Option<&TargetKind>, Vec<String>)> = Vec::new();
+    for target in targets {
+        let (kind, number) = match target {
+            Some(target) => (Some(&target.kind), target.cref_number()),
+            None => (None, "??".to_string()),
+        };
+        match groups.last_mut() {
+            Some((last, numbers)) if kind.is_some() && *last == kind => numbers.push(number),
+            _ => groups.push((kind, vec![number])),
+        }
+    }
+
+    let groups: Vec<_> = groups
+        .into_iter()
+        .map(|(kind, numbers)| {
+            let name = kind.and_then(|x| x.cref_name(capital)).map(|name| {
+                if numbers.len() == 1 {
+                    name
+                } else if let Some(name) = name.strip_suffix('.') {
+                    format!("{}s.", name)
+                } else {
+                    format!("{}s", name)
+                }
+            });
+            let numbers = join_and(numbers);
+            match name {
+                Some(name) => format!("{} {}", name, numbers),
+                None => numbers,
+            }
+        })
+        .collect();
+    join_and(groups)
+}
+
+/// `a`, `a and b`, `a, b and c`
+fn join_and(mut items: Vec<String>) -> String {
+    match items.pop() {
+        Some(last) if items.is_empty() => last,
+        Some(last) => format!("{} and {}", items.join(", "), last),
+        None => String::new(),
+    }
+}
+
+/// 参照される対象の種類
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TargetKind {
+    Heading(HeadingLevel),
+    /// `\newtheorem` の環境. 表示名を持つ
+    Theorem(String),
+    Equation,
+    Figure,
+    Table,
+    Item,
+    /// 番号の付くものの外にある `\label`
+    Other,
+}
+
+impl TargetKind {
+    /// hyperref の `\autoref` での名前
+    fn autoref_name(&self) -> Option<&str> {
+        use HeadingLevel::*;
+
+        match self {
+            TargetKind::Heading(Chapter) => Some("chapter"),
+            TargetKind::Heading(Section) => Some("section"),
+            TargetKind::Heading(Subsection) => Some("subsection"),
+            TargetKind::Heading(Subsubsection) => Some("subsubsection"),
+            TargetKind::Heading(Paragraph) => Some("paragraph"),
+            TargetKind::Theorem(title) => Some(title),
+            TargetKind::Equation => Some("Equation"),
+            TargetKind::Figure => Some("Figure"),
+            TargetKind::Table => Some("Table"),
+            TargetKind::Item => Some("item"),
+            TargetKind::Other => None,
+        }
+    }
+
+    /// cleveref の `\cref` (小文字) と `\Cref` (大文字) での名前
+    fn cref_name(&self, capital: bool) -> Option<String> {
+        use HeadingLevel::*;
+
+        let (lower, upper) = match self {
+            TargetKind::Heading(Chapter) => ("chapter", "Chapter"),
+            TargetKind::Heading(Paragraph) => ("paragraph", "Paragraph"),
+            TargetKind::Heading(_) => ("section", "Section"),
+            TargetKind::Theorem(title) => {
+                return Some(if capital {
+                    title.clone()
+                } else {
+                    title.to_lowercase()
+                });
+            }
+            TargetKind::Equation => ("eq.", "Eq."),
+            TargetKind::Figure => ("fig.", "Fig."),
+            TargetKind::Table => ("table", "Table"),
+            TargetKind::Item => ("item", "Item"),
+            TargetKind::Other => return None,
+        };
+        Some(if capital { upper } else { lower }.to_string())
+    }
+}
+
+/// `\label` の付いた対象
+#[derive(Debug, Clone)]
+pub(super) struct Target {
+    key: Key,
+    kind: TargetKind,
+    number: Option<String>,
+    /// 見出しのアンカー. 見出し以外はラベルをそのままアンカーとする
+    anchor: Option<String>,
+    /// `\nameref` での表記 (見出しやキャプション, 定理の注釈)
+    name: Option<String>,
+}
+
+impl Target {
+    pub(crate) fn key(&self) -> &Key {
+        &self.key
+    }
+
+    pub(crate) fn number(&self) -> Option<&str> {
+        self.number.as_deref()
+    }
+
+    pub(crate) fn anchor(&self) -> Option<&str> {
+        self.anchor.as_deref()
+    }
+
+    /// 対象の種類の名前. 定理環境は `theorem` とする
+    pub(crate) fn kind_name(&self) -> &str {
+        match &self.kind {
+            TargetKind::Heading(_) => self.kind.autoref_name().unwrap(),
+            TargetKind::Theorem(_) => "theorem",
+            TargetKind::Equation => "equation",
+            TargetKind::Figure => "figure",
+            TargetKind::Table => "table",
+            TargetKind::Item => "item",
+            TargetKind::Other => "label",
+        }
+    }
+
+    /// cleveref は数式番号を括弧で囲む
+    fn cref_number(&self) -> String {
+        let number = self.number.as_deref().unwrap_or("??");
+        if self.kind == TargetKind::Equation {
+            format!("({})", number)
+        } else {
+            number.to_string()
+        }
+    }
+
+    /// 参照の表記を作る
+    fn format(&self, command: &str) -> String {
+        let number = self.number.as_deref().unwrap_or("??");
+        let with_name = |name: Option<String>, cleveref: bool| {
+            let number = if cleveref {
+                self.cref_number()
+            } else {
+                number.to_string()
+            };
+            match name {
+                Some(name) => format!("{} {}", name, number),
+                None => number,
+            }
+        };
+
+        match command {
+            "eqref" => format!("({})", number),
+            "autoref" => with_name(self.kind.autoref_name().map(|x| x.to_string()), false),
+            "cref" => with_name(self.kind.cref_name(false), true),
+            "Cref" => with_name(self.kind.cref_name(true), true),
+            "nameref" => self.name.clone().unwrap_or_else(|| number.to_string()),
+            // ページの概念がないので `\pageref` は参照先の番号で代用する
+            _ => number.to_string(),
+        }
+    }
+}
+
+/// ラベルから対象への対応
+pub(super) type Labels = HashMap<String, Target>;
+
+/// 定理, 図, 表に番号を振り, ラベルを集めて参照を解決する
+///
+/// 本文を木としてたどり, `\label` は LaTeX と同じく直前に番号の進んだ対象
+/// (見出し, 定理環境, 図表, 箇条書きの項目) に付くものとする.
+/// 環境の中の対象は環境を出ると元に戻る.
+pub(super) fn resolve(rmap: &mut ResultMap, macros: &Macros) -> (Labels, Vec<Diagnostic>) {
+    let mut resolver = Resolver {
+        macros,
+        visited: HashSet::new(),
+        labels: HashMap::new(),
+        diagnostics: Vec::new(),
+        current: None,
+        headings: Default::default(),
+        theorems: HashMap::new(),
+        figures: 0,
+        tables: 0,
+    };
+
+    resolver.visit(rmap, &rmap.root());
+    // 本文から参照されないノード (要旨など) の中のラベルも集める
+    let keys: Vec<_> = rmap.iter().map(|(key, _)| key.clone()).collect();
+    for key in keys {
+        if !resolver.visited.contains(&key) {
+            resolver.current = None;
+            resolver.visit(rmap, &key);
+        }
+    }
+
+    let keys: Vec<_> = rmap
+        .iter()
+        .filter(|(_, node)| matches!(node, Node::Reference(_)))
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    let mut diagnostics = resolver.diagnostics;
+    for key in keys {
+        let Some(Node::Reference(info)) = rmap.get_mut(&key) else {
+            continue;
+        };
+        for index in 0..info.labels.len() {
+            match resolver.labels.get(&info.labels[index]) {
+                Some(target) => info.resolve_to(index, target, None),
+                None => diagnostics.push(unresolved(info, index, &key)),
+            }
+        }
+    }
+
+    (resolver.labels, diagnostics)
+}
+
+/// 他の文書のラベルの探し方の結果
+pub(super) enum ExternalTarget<'a> {
+    /// 文書名とその文書の対象
+    Found(&'a str, &'a Target),
+    /// 文書は分かったがラベルがない
+    Dangling(&'a str),
+    NotFound,
+}
+
+/// 文書の中で解決できなかった参照を, `lookup` で他の文書のラベルから探す
+///
+/// 解決した参照の `unresolved_reference` は取り除き,
+/// 参照先の文書は分かるがラベルがなければ `dangling_reference` に置き換える.
+pub(super) fn resolve_external<'a>(
+    rmap: &mut ResultMap,
+    diagnostics: &mut Vec<Diagnostic>,
+    lookup: impl Fn(&str) -> ExternalTarget<'a>,
+) {
+    let keys: Vec<_> = rmap
+        .iter()
+        .filter(
+            |(_, node)| matches!(node, Node::Reference(x) if x.targets.iter().any(|x| x.is_none())),
+        )
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    // 見直した参照の `unresolved_reference` は作り直す
+    let mut handled = HashSet::new();
+    let mut added = Vec::new();
+    for key in keys {
+        let Some(Node::Reference(info)) = rmap.get_mut(&key) else {
+            continue;
+        };
+        for index in 0..info.labels.len() {
+            if info.targets[index].is_some() {
+                continue;
+            }
+            match lookup(&info.labels[index]) {
+                ExternalTarget::Found(document, target) => {
+                    info.resolve_to(index, target, Some(document));
+                }
+                ExternalTarget::Dangling(document) => added.push(Diagnostic::warning(
+                    "dangling_reference",
+                    format!(
+                        "\\{}{{{}}} refers to a label that is not defined in {}",
+                        info.command, info.labels[index], document
+                    ),
+                    Some(key.clone()),
+                )),
+                ExternalTarget::NotFound => added.push(unresolved(info, index, &key)),
+            }
+        }
+        handled.insert(key);
+    }
+
+    diagnostics.retain(|x| {
+        x.code() != "unresolved_reference" || x.key().is_none_or(|k| !handled.contains(k))
+    });
+    diagnostics.extend(added);
+}
+
+/// `index` 番目のラベルが見つからない
+fn unresolved(info: &ReferenceInfo, index: usize, key: &Key) -> Diagnostic {
+    Diagnostic::warning(
+        "unresolved_reference",
+        format!(
+            "\\{}{{{}}} refers to an undefined label",
+            info.command, info.labels[index]
+        ),
+        Some(key.clone()),
+    )
+}
+
+struct Resolver<'a> {
+    macros: &'a Macros,
+    visited: HashSet<Key>,
+    labels: HashMap<String, Target>,
+    diagnostics: Vec<Diagnostic>,
+    /// 次の `\label` が付く対象
+    current: Option<Target>,
+    /// 各深さの直前の見出しの番号
+    headings: [Option<String>; 5],
+    /// 定理のカウンタごとの (番号の前に付ける見出しの番号, 値)
+    theorems: HashMap<String, (Option<String>, usize)>,
+    figures: usize,
+    tables: usize,
+}
+
+impl Resolver<'_> {
+    fn visit(&mut self, rmap: &mut ResultMap, key: &Key) {
+        if !self.visited.insert(key.clone()) {
+            return;
+        }
+
+        match rmap.get(key) {
+            Some(Node::ParagraphList(Some(ks)) | Node::Paragraph(Some(ks))) => {
+                let ks = ks.clone();
+                self.visit_all(rmap, &ks);
+            }
+            Some(Node::Metadata(MetadataDecl::Abstract(ks))) => {
+                let ks = ks.clone();
+                self.visit_all(rmap, &ks);
+            }
+            Some(Node::Table(info)) => {
+                let ks: Vec<_> = info.content_keys().cloned().collect();
+                self.visit_all(rmap, &ks);
+            }
+            Some(Node::Heading(info)) => {
+                let level = *info.level();
+                let depth = level.depth();
+                let number = info.number().map(|x| x.to_string());
+                let title = info.title().to_vec();
+                let name = title
+                    .iter()
+                    .map(|k| rmap.plain_text_at(k))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+                self.headings[depth] = number.clone();
+                self.headings[depth + 1..]
+                    .iter_mut()
+                    .for_each(|x| *x = None);
+                self.current = Some(Target {
+                    key: key.clone(),
+                    kind: TargetKind::Heading(level),
+                    number,
+                    anchor: Some(info.anchor().to_string()),
+                    name: Some(name),
+                });
+                self.visit_all(rmap, &title);
+            }
+            Some(Node::MathExpr(me)) => {
+                let labels: Vec<_> = me
+                    .equations()
+                    .iter()
+                    .filter_map(|eq| {
+                        Some((eq.label()?.to_string(), eq.number().map(|x| x.to_string())))
+                    })
+                    .collect();
+                // `\nonumber` の行のラベルも番号なしで登録する
+                for (label, number) in labels {
+                    let target = Target {
+                        key: key.clone(),
+                        kind: TargetKind::Equation,
+                        number,
+                        anchor: None,
+                        name: None,
+                    };
+                    self.define(label, target, key);
+                }
+            }
+            Some(Node::Figure(_)) => self.visit_figure(rmap, key),
+            Some(Node::Environment(info)) => {
+                let content = info.content().to_vec();
+                let Some(def) = self.macros.theorem(info.name()) else {
+                    self.visit_all(rmap, &content);
+                    return;
+                };
+
+                let number = def.numbered().then(|| self.step_theorem(info.name()));
+                let target = Target {
+                    key: key.clone(),
+                    kind: TargetKind::Theorem(def.title().to_string()),
+                    number: number.clone(),
+                    anchor: None,
+                    name: Some(
+                        info.args()
+                            .first()
+                            .cloned()
+                            .unwrap_or_else(|| def.title().to_string()),
+                    ),
+                };
+                if let Some(Node::Environment(info)) = rmap.get_mut(key) {
+                    info.set_number(number);
+                }
+                self.visit_scoped(rmap, target, &content);
+            }
+            Some(Node::List(info)) => {
+                let items: Vec<_> = info
+                    .items()
+                    .iter()
+                    .map(|x| (x.number().map(|x| x.to_string()), x.content().to_vec()))
+                    .collect();
+                for (number, content) in items {
+                    match number {
+                        Some(number) => {
+                            let target = Target {
+                                key: key.clone(),
+                                kind: TargetKind::Item,
+                                number: Some(number),
+                                anchor: None,
+                                name: None,
+                            };
+                            self.visit_scoped(rmap, target, &content);
+                        }
+                        None => self.visit_all(rmap, &content),
+                    }
+                }
+            }
+            Some(Node::Label(label)) => {
+                let label = label.clone();
+                let target = self.current.clone().unwrap_or(Target {
+                    key: key.clone(),
+                    kind: TargetKind::Other,
+                    number: None,
+                    anchor: None,
+                    name: None,
+                });
+                self.define(label, target, key);
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_all(&mut self, rmap: &mut ResultMap, keys: &[Key]) {
+        for k in keys {
+            self.visit(rmap, k);
+        }
+    }
+
+    /// 環境の中だけ `\label` の対象を変える
+    fn visit_scoped(&mut self, rmap: &mut ResultMap, target: Target, keys: &[Key]) {
+        let outer = self.current.replace(target);
+        self.visit_all(rmap, keys);
+        self.current = outer;
+    }
+
+    /// 図表に番号を振る. 番号はキャプションのあるものにだけ振り, サブ図は親の番号に a, b, ... を付ける
+    fn visit_figure(&mut self, rmap: &mut ResultMap, key: &Key) {
+        let Some(Node::Figure(info)) = rmap.get_mut(key) else {
+            return;
+        };
+
+        let is_table = info.env().starts_with("table");
+        let (kind, counter) = if is_table {
+            (TargetKind::Table, &mut self.tables)
+        } else {
+            (TargetKind::Figure, &mut self.figures)
+        };
+        let number = info.caption().is_some().then(|| {
+            *counter += 1;
+            counter.to_string()
+        });
+        info.set_number(number.clone());
+
+        let mut labels = Vec::new();
+        let mut subnumber = 0;
+        for sub in info.subfigures_mut() {
+            let n = match (&number, sub.caption().is_some()) {
+                (Some(number), true) => {
+                    subnumber += 1;
+                    let letter = char::from_u32('a' as u32 + subnumber - 1).unwrap_or('?');
+                    Some(format!("{}{}", number, letter))
+                }
+                _ => None,
+            };
+            sub.set_number(n.clone());
+            if let Some(label) = sub.label() {
+                labels.push((label.to_string(), n, sub.caption().map(|x| x.to_vec())));
+            }
+        }
+        if let Some(label) = info.label() {
+            labels.push((
+                label.to_string(),
+                number.clone(),
+                info.caption().map(|x| x.to_vec()),
+            ));
+        }
+        let child_keys = info.child_keys();
+
+        for (label, number, caption) in labels {
+            let name = caption.map(|ks| {
+                ks.iter()
+                    .map(|k| rmap.plain_text_at(k))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            });
+            let target = Target {
+                key: key.clone(),
+                kind: kind.clone(),
+                number,
+                anchor: None,
+                name,
+            };
+            self.define(label, target, key);
+        }
+
+        let target = Target {
+            key: key.clone(),
+            kind,
+            number,
+            anchor: None,
+            name: None,
+        };
+        self.visit_scoped(rmap, target, &child_keys);
+    }
+
+    /// 定理環境のカウンタを進めて番号を返す
+    ///
+    /// `\newtheorem{lem}[thm]{..}` は thm と番号を共有し,
+    /// `\newtheorem{thm}{..}[section]` は節ごとに番号を振り直して `1.2` のようにする.
+    fn step_theorem(&mut self, name: &str) -> String {
+        let def = self.macros.theorem(name).unwrap();
+        let counter = def.counter().unwrap_or(name).to_string();
+        let owner = self.macros.theorem(&counter).unwrap_or(def);
+        let prefix = owner.within().map(|within| {
+            let depth = match within {
+                "chapter" => 0,
+                "section" => 1,
+                "subsection" => 2,
+                "subsubsection" => 3,
+                _ => 4,
+            };
+            self.headings[depth]
+                .clone()
+                .unwrap_or_else(|| "0".to_string())
+        });
+
+        let (last, value) = self.theorems.entry(counter).or_insert((None, 0));
+        if *last != prefix {
+            *last = prefix.clone();
+            *value = 0;
+        }
+        *value += 1;
+
+        match prefix {
+            Some(prefix) => format!("{}.{}", prefix, value),
+            None => value.to_string(),
+        }
+    }
+
+    /// ラベルを登録する. `site` はラベルの書かれた項目で, 重複の報告に使う
+    fn define(&mut self, label: String, mut target: Target, site: &Key) {
+        if target.anchor.is_none() {
+            target.anchor = Some(label.clone());
+        }
+        if self.labels.contains_key(&label) {
+            self.diagnostics.push(Diagnostic::warning(
+                "duplicate_label",
+                format!("\\label{{{}}} is defined more than once", label),
+                Some(site.clone()),
+            ));
+            return;
+        }
+        self.labels.insert(label, target);
+    }
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(kind: TargetKind, number: &str) -> Target {
+        Target {
+            key: crate::key::KeyCounter::new().count(),
+            kind,
+            number: Some(number.to_string()),
+            anchor: None,
+            name: Some("Fermat".to_string()),
+        }
+    }
+
+    #[test]
+    fn 参照の表記() {
+        let eq = target(TargetKind::Equation, "3");
+        assert_eq!(eq.format("ref"), "3");
+        assert_eq!(eq.format("eqref"), "(3)");
+        assert_eq!(eq.format("cref"), "eq. (3)");
+        assert_eq!(eq.format("Cref"), "Eq. (3)");
+        assert_eq!(eq.format("autoref"), "Equation 3");
+
+        let thm = target(TargetKind::Theorem("Theorem".to_string()), "1.2");
+        assert_eq!(thm.format("cref"), "theorem 1.2");
+        assert_eq!(thm.format("Cref"), "Theorem 1.2");
+        assert_eq!(thm.format("nameref"), "Fermat");
+
+        let section = target(TargetKind::Heading(HeadingLevel::Subsection), "2.1");
+        assert_eq!(section.format("autoref"), "subsection 2.1");
+        assert_eq!(section.format("Cref"), "Section 2.1");
+
+        let other = Target {
+            number: None,
+            ..target(TargetKind::Other, "")
+        };
+        assert_eq!(other.format("cref"), "??");
+    }
+
+    #[test]
+    fn 複数のラベル() {
+        let info = ReferenceInfo::new("cref".to_string(), " a, b,,c ");
+        assert_eq!(info.label(), "a,b,c");
+        let info = ReferenceInfo::new("ref".to_string(), "a,b");
+        assert_eq!(info.label(), "a,b");
+
+        let thm = TargetKind::Theorem("Theorem".to_string());
+        let a = target(thm.clone(), "1");
+        let b = target(thm.clone(), "2");
+        let c = target(thm, "3");
+        let eq = target(TargetKind::Equation, "4");
+        assert_eq!(
+            format_cref(&[Some(&a), Some(&b)], false),
+            "theorems 1 and 2"
+        );
+        assert_eq!(
+            format_cref(&[Some(&a), Some(&b), Some(&c), Some(&eq)], true),
+            "Theorems 1, 2 and 3 and Eq. (4)"
+        );
+        assert_eq!(
+            format_cref(&[Some(&eq), Some(&eq)], false),
+            "eqs. (4) and (4)"
+        );
+        assert_eq!(format_cref(&[Some(&a), None], false), "theorem 1 and ??");
+    }
+}
@@ -1,7 +1,8 @@
 use crate::key::Key;
+use crate::macros::Dependencies;
 use crate::node::Node;
 use std::collections::hash_map::DefaultHasher;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 
 #[derive(Debug)]
@@ -22,21 +23,115 @@ impl ResultMap {
         self.root.clone()
     }
 
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&Key, &Node)> {
+        self.entries.iter()
+    }
+
+    pub(crate) fn get(&self, key: &Key) -> Option<&Node> {
+        self.entries.get(key)
+    }
+
+    pub(crate) fn get_mut(&mut self, key: &Key) -> Option<&mut Node> {
+        self.entries.get_mut(key)
+    }
+
+    /// key 以下の部分木を装飾なしの文字列に変換する
+    pub(crate) fn plain_text_at(&self, key: &Key) -> String {
+        let join = |ks: &[Key]| {
+            ks.iter()
+                .map(|k| self.plain_text_at(k))
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        match self.entries.get(key) {
+            Some(Node::ParagraphList(Some(ks))) => join(ks),
+            Some(Node::Paragraph(Some(ks))) => join(ks),
+            Some(Node::RawString(s)) => s.to_owned(),
+            Some(Node::MathExpr(me)) => me.content_str().to_owned(),
+            Some(Node::Heading(info)) => join(info.title()),
+            _ => String::new(),
+        }
+    }
+
+    /// 条件に合うノードを取り除く
+    ///
+    /// 見出しや表, 環境, 取り除いたノード自身なども含め, すべてのノードからの参照も取り除き,
+    /// それにより空になった段落も取り除く.
+    /// 取り除いたノードの子は残るので, 別の場所から参照できる.
+    pub(crate) fn detach(&mut self, pred: impl Fn(&Node) -> bool) -> Vec<(Key, Node)> {
+        let keys: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, node)| pred(node))
+            .map(|(key, _)| key.clone())
+            .collect();
+        if keys.is_empty() {
+            return Vec::new();
+        }
+
+        let mut detached: Vec<_> = keys
+            .iter()
+            .filter_map(|k| self.entries.remove_entry(k))
+            .collect();
+
+        let is_empty = |node: &Node| matches!(node, Node::Paragraph(Some(ks)) if ks.is_empty());
+        let mut removed: HashSet<Key> = keys.into_iter().collect();
+        // 空になった段落を取り除くと, それを含む段落が空になることがある
+        loop {
+            let mut emptied = Vec::new();
+            for (key, node) in self.entries.iter_mut() {
+                let was_empty = is_empty(node);
+                node.retain_keys(|k| !removed.contains(k));
+                if !was_empty && is_empty(node) {
+                    emptied.push(key.clone());
+                }
+            }
+            if emptied.is_empty() {
+                break;
+            }
+            for key in emptied {
+                self.entries.remove(&key);
+                removed.insert(key);
+            }
+        }
+
+        for (_, node) in &mut detached {
+            node.retain_keys(|k| !removed.contains(k));
+        }
+
+        detached
+    }
+
     pub(crate) fn merge(&mut self, children: impl IntoIterator<Item = Self>) {
         for child in children {
             self.entries.extend(child.entries);
         }
     }
 
-    pub(crate) fn hash_table(&self) -> HashMap<Key, String> {
+    /// 中身から Key を決める
+    ///
+    /// 使っている命令の定義も含めるので, 定義を変えればそれを使う項目と親の Key が変わる.
+    pub(crate) fn hash_table(&self, dependencies: &Dependencies) -> HashMap<Key, String> {
         let mut table = HashMap::new();
 
-        self.hash_by_value_at(&self.root, &mut table);
+        self.hash_by_value_at(&self.root, dependencies, &mut table);
+
+        // 本文から参照されないノード (メタデータなど) にも Key を振る
+        for key in self.entries.keys() {
+            self.hash_by_value_at(key, dependencies, &mut table);
+        }
 
         table
     }
 
-    fn hash_by_value_at(&self, key: &Key, table: &mut HashMap<Key, String>) -> String {
+    fn hash_by_value_at(
+        &self,
+        key: &Key,
+        dependencies: &Dependencies,
+        table: &mut HashMap<Key, String>,
+    ) -> String {
         if let Some(hash) = table.get(key) {
             return hash.to_owned();
         }
@@ -47,13 +142,13 @@ impl ResultMap {
             match node {
                 Node::ParagraphList(Some(ks)) => {
                     for k in ks {
-                        let hash = self.hash_by_value_at(k, table);
+                        let hash = self.hash_by_value_at(k, dependencies, table);
                         hash.hash(&mut hasher);
                     }
                 }
                 Node::Paragraph(Some(ks)) => {
                     for k in ks {
-                        let hash = self.hash_by_value_at(k, table);
+                        let hash = self.hash_by_value_at(k, dependencies, table);
                         hash.hash(&mut hasher);
                     }
                 }
@@ -66,6 +161,56 @@ impl ResultMap {
                 Node::MathExpr(me) => {
                     me.hash(&mut hasher);
                 }
+                Node::Heading(info) => {
+                    info.hash(&mut hasher);
+                    for k in info.title() {
+                        let hash = self.hash_by_value_at(k, dependencies, table);
+                        hash.hash(&mut hasher);
+                    }
+                }
+                Node::Figure(info) => {
+                    info.hash(&mut hasher);
+                    for k in info.child_keys() {
+                        let hash = self.hash_by_value_at(&k, dependencies, table);
+                        hash.hash(&mut hasher);
+                    }
+                }
+                Node::Image(image) => {
+                    image.hash(&mut hasher);
+                }
+                Node::Table(info) => {
+                    info.hash(&mut hasher);
+                    for k in info.content_keys() {
+                        let hash = self.hash_by_value_at(k, dependencies, table);
+                        hash.hash(&mut hasher);
+                    }
+                }
+                Node::List(info) => {
+                    info.hash(&mut hasher);
+                    for k in info.content_keys() {
+                        let hash = self.hash_by_value_at(k, dependencies, table);
+                        hash.hash(&mut hasher);
+                    }
+                }
+                Node::MakeTitle => {
+                    "maketitle".hash(&mut hasher);
+                }
+                Node::Environment(info) => {
+                    info.hash(&mut hasher);
+                    for k in info.content() {
+                        let hash = self.hash_by_value_at(k, dependencies, table);
+                        hash.hash(&mut hasher);
+                    }
+                }
+                Node::Label(label) => {
+                    label.hash(&mut hasher);
+                }
+                Node::Reference(info) => {
+                    info.hash(&mut hasher);
+                }
+                Node::Citation(info) => {
+                    info.hash(&mut hasher);
+                }
                 _ => {
                     // do nothing
                 }
@@ -75,6 +220,10 @@ impl ResultMap {
             // 基本的には unreachable!() の想定
         }
 
+        if let Some(defs) = dependencies.get(key) {
+            defs.hash(&mut hasher);
+        }
+
         let hash = format!("{:x}", hasher.finish());
         table.insert(key.clone(), hash.clone());
         hash
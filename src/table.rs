@@ -0,0 +1,622 @@
+use crate::key::Key;
+use crate::tex_char::TexChar;
+use crate::tex_chars::TexChars;
+use serde::Serialize;
+use std::hash::{Hash, Hasher};
+use std::iter::Peekable;
+use std::str::Chars;
+
+pub(super) fn is_table_env(name: &str) -> bool {
+    matches!(name, "tabular" | "tabular*" | "tabularx" | "longtable")
+}
+
+/// 幅指定を第1引数に取る環境か
+pub(super) fn has_width_arg(name: &str) -> bool {
+    matches!(name, "tabular*" | "tabularx")
+}
+
+#[derive(Debug, Hash)]
+pub(super) struct TableInfo {
+    env: String,
+    width: Option<String>,
+    spec: ColumnSpec,
+    rows: Vec<TableRow>,
+    trailing_rules: Vec<TableRule>,
+}
+
+impl TableInfo {
+    pub(crate) fn new(
+        env: String,
+        width: Option<String>,
+        spec: ColumnSpec,
+        rows: Vec<TableRow>,
+        trailing_rules: Vec<TableRule>,
+    ) -> Self {
+        Self {
+            env,
+            width,
+            spec,
+            rows,
+            trailing_rules,
+        }
+    }
+
+    pub(crate) fn env(&self) -> &str {
+        &self.env
+    }
+
+    pub(crate) fn width(&self) -> Option<&str> {
+        self.width.as_deref()
+    }
+
+    pub(crate) fn spec(&self) -> &ColumnSpec {
+        &self.spec
+    }
+
+    pub(crate) fn rows(&self) -> &[TableRow] {
+        &self.rows
+    }
+
+    pub(crate) fn trailing_rules(&self) -> &[TableRule] {
+        &self.trailing_rules
+    }
+
+    pub(crate) fn content_keys(&self) -> impl Iterator<Item = &Key> {
+        self.rows
+            .iter()
+            .flat_map(|row| row.cells.iter())
+            .flat_map(|cell| cell.content.iter())
+    }
+
+    pub(crate) fn retain_keys(&mut self, f: &mut impl FnMut(&Key) -> bool) {
+        for cell in self.rows.iter_mut().flat_map(|row| row.cells.iter_mut()) {
+            cell.content.retain(|k| f(k));
+        }
+    }
+}
+
+#[derive(Debug, Hash)]
+pub(super) struct TableRow {
+    rules: Vec<TableRule>,
+    cells: Vec<TableCell>,
+}
+
+impl TableRow {
+    pub(crate) fn new(rules: Vec<TableRule>, cells: Vec<TableCell>) -> Self {
+        Self { rules, cells }
+    }
+
+    pub(crate) fn rules(&self) -> &[TableRule] {
+        &self.rules
+    }
+
+    pub(crate) fn cells(&self) -> &[TableCell] {
+        &self.cells
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct TableCell {
+    colspan: usize,
+    rowspan: usize,
+    align: Option<ColumnAlign>,
+    content: Vec<Key>,
+}
+
+// 中身の Key は出現位置で変わるので, セルの区切りが分かるよう個数だけを含める
+impl Hash for TableCell {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.colspan.hash(state);
+        self.rowspan.hash(state);
+        self.align.hash(state);
+        self.content.len().hash(state);
+    }
+}
+
+impl TableCell {
+    pub(crate) fn colspan(&self) -> usize {
+        self.colspan
+    }
+
+    pub(crate) fn rowspan(&self) -> usize {
+        self.rowspan
+    }
+
+    pub(crate) fn align(&self) -> Option<&ColumnAlign> {
+        self.align.as_ref()
+    }
+
+    pub(crate) fn content(&self) -> &[Key] {
+        &self.content
+    }
+}
+
+#[derive(Debug, Hash, Serialize, Clone, PartialEq, Eq)]
+pub(super) enum ColumnAlign {
+    #[serde(rename = "l")]
+    Left,
+    #[serde(rename = "c")]
+    Center,
+    #[serde(rename = "r")]
+    Right,
+    #[serde(rename = "p")]
+    Top,
+    #[serde(rename = "m")]
+    Middle,
+    #[serde(rename = "b")]
+    Bottom,
+    #[serde(rename = "X")]
+    Stretch,
+}
+
+#[derive(Debug, Hash, Serialize, Clone, PartialEq, Eq)]
+pub(super) struct TableColumn {
+    align: ColumnAlign,
+    width: Option<String>,
+}
+
+#[derive(Debug, Hash, Default, PartialEq, Eq)]
+pub(super) struct ColumnSpec {
+    columns: Vec<TableColumn>,
+    // 各列の境界にある縦罫線 `|` の本数 (長さは columns.len() + 1)
+    vrules: Vec<usize>,
+}
+
+impl ColumnSpec {
+    pub(crate) fn columns(&self) -> &[TableColumn] {
+        &self.columns
+    }
+
+    pub(crate) fn vrules(&self) -> &[usize] {
+        &self.vrules
+    }
+}
+
+#[derive(Debug, Hash, Serialize, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub(super) enum TableRule {
+    Hline,
+    Cline { from: usize, to: usize },
+    Toprule,
+    Midrule,
+    Bottomrule,
+    Cmidrule { from: usize, to: usize },
+}
+
+pub(super) fn parse_column_spec(spec: &str) -> ColumnSpec {
+    let spec = expand_repeats(spec);
+
+    let mut columns = Vec::new();
+    let mut vrules = vec![0];
+    let mut chars = spec.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let (align, width) = match c {
+            '|' => {
+                *vrules.last_mut().unwrap() += 1;
+                continue;
+            }
+            // 列間の挿入や前後の装飾は読み飛ばす
+            '@' | '!' | '>' | '<' => {
+                read_brace(&mut chars);
+                continue;
+            }
+            '{' => {
+                skip_until_close(&mut chars);
+                continue;
+            }
+            'l' => (ColumnAlign::Left, None),
+            'c' => (ColumnAlign::Center, None),
+            'r' => (ColumnAlign::Right, None),
+            'X' => (ColumnAlign::Stretch, None),
+            'p' => (ColumnAlign::Top, read_brace(&mut chars)),
+            'm' => (ColumnAlign::Middle, read_brace(&mut chars)),
+            'b' => (ColumnAlign::Bottom, read_brace(&mut chars)),
+            // 独自定義の列型などは中央揃えとみなす
+            c if c.is_ascii_alphabetic() => (ColumnAlign::Center, None),
+            _ => continue,
+        };
+
+        columns.push(TableColumn { align, width });
+        vrules.push(0);
+    }
+
+    ColumnSpec { columns, vrules }
+}
+
+/// `*{n}{spec}` を展開する
+fn expand_repeats(spec: &str) -> String {
+    let mut result = String::new();
+    let mut chars = spec.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '*' {
+            let n = read_brace(&mut chars)
+                .and_then(|x| x.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            let inner = expand_repeats(&read_brace(&mut chars).unwrap_or_default());
+            result.push_str(&inner.repeat(n));
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+fn read_brace(chars: &mut Peekable<Chars>) -> Option<String> {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    chars.next_if_eq(&'{')?;
+
+    let mut content = String::new();
+    let mut depth = 0;
+    for c in chars.by_ref() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => break,
+            '}' => depth -= 1,
+            _ => {}
+        }
+        content.push(c);
+    }
+
+    Some(content)
+}
+
+fn skip_until_close(chars: &mut Peekable<Chars>) {
+    let mut depth = 0;
+    for c in chars.by_ref() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => break,
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub(super) struct RawRow {
+    pub rules: Vec<TableRule>,
+    pub cells: Vec<TexChars>,
+}
+
+/// 表の中身を `\\` で行に, `&` でセルに分ける
+///
+/// 入れ子の環境 (`matrix` など) と数式の中の `&` や `\\` では分けない.
+/// 戻り値の2つ目は最終行の後ろにある罫線.
+pub(super) fn split_rows(mut cs: TexChars) -> (Vec<RawRow>, Vec<TableRule>) {
+    use TexChar::*;
+
+    let mut rows = Vec::new();
+    let mut row = RawRow::default();
+    let mut cell = Vec::new();
+    let mut depth = 0;
+    let mut in_math = false;
+    let mut at_row_start = true;
+
+    loop {
+        if at_row_start {
+            cs.skip_whitespaces();
+            if let Some(rule) = read_rule(&mut cs) {
+                row.rules.extend(rule);
+                continue;
+            }
+            at_row_start = false;
+        }
+
+        if let Some(name) = cs.peek_begin_env() {
+            // 環境はそのまま読む
+            let before = cs.clone();
+            cs.consume_begin_env();
+            cs.read_env_body(&name);
+            let consumed = before.len() - cs.len();
+            cell.extend(before.take(consumed));
+            continue;
+        }
+
+        let Some(c) = cs.next() else {
+            break;
+        };
+
+        match c {
+            Backslash if depth == 0 && !in_math && cs.next_is(Backslash) => {
+                cs.next();
+                // 行間の調整 `\\[2pt]` は読み飛ばす
+                cs.read_optional();
+                row.cells
+                    .push(std::mem::take(&mut cell).into_iter().collect());
+                rows.push(std::mem::take(&mut row));
+                at_row_start = true;
+            }
+            Backslash => {
+                cell.push(c);
+                if let Some(c) = cs.next() {
+                    match c {
+                        LParen | LBracket => in_math = true,
+                        RParen | RBracket => in_math = false,
+                        _ => {}
+                    }
+                    cell.push(c);
+                }
+            }
+            Dollar => {
+                cell.push(c);
+                if cs.next_is(Dollar) {
+                    cell.extend(cs.next());
+                }
+                in_math = !in_math;
+            }
+            Char('&') if depth == 0 && !in_math => {
+                row.cells
+                    .push(std::mem::take(&mut cell).into_iter().collect());
+            }
+            LBrace => {
+                depth += 1;
+                cell.push(c);
+            }
+            RBrace => {
+                depth -= 1;
+                cell.push(c);
+            }
+            _ => cell.push(c),
+        }
+    }
+
+    let is_blank = cell.iter().all(|c| matches!(c, Whitespace | Return));
+    if row.cells.is_empty() && is_blank {
+        return (rows, row.rules);
+    }
+
+    row.cells.push(cell.into_iter().collect());
+    rows.push(row);
+
+    (rows, Vec::new())
+}
+
+/// 行頭の罫線命令を読む
+///
+/// 罫線以外に行頭で読み飛ばす命令 (longtable の `\endhead` など) の場合は `Some(None)` を返す.
+fn read_rule(cs: &mut TexChars) -> Option<Option<TableRule>> {
+    let name = cs.peek_command_name()?;
+    if !matches!(
+        name.as_str(),
+        "hline"
+            | "cline"
+            | "toprule"
+            | "midrule"
+            | "bottomrule"
+            | "cmidrule"
+            | "endhead"
+            | "endfirsthead"
+            | "endfoot"
+            | "endlastfoot"
+    ) {
+        return None;
+    }
+
+    cs.consume_command_name();
+
+    let rule = match name.as_str() {
+        "hline" => TableRule::Hline,
+        "toprule" | "midrule" | "bottomrule" => {
+            cs.read_optional();
+            match name.as_str() {
+                "toprule" => TableRule::Toprule,
+                "midrule" => TableRule::Midrule,
+                _ => TableRule::Bottomrule,
+            }
+        }
+        "cline" => {
+            let (from, to) = read_range(cs);
+            TableRule::Cline { from, to }
+        }
+        "cmidrule" => {
+            cs.read_optional();
+            // トリム指定 `(lr)` は読み飛ばす
+            if cs.next_is(TexChar::LParen) {
+                while !matches!(cs.next(), Some(TexChar::RParen) | None) {}
+            }
+            let (from, to) = read_range(cs);
+            TableRule::Cmidrule { from, to }
+        }
+        _ => return Some(None),
+    };
+
+    Some(Some(rule))
+}
+
+fn read_range(cs: &mut TexChars) -> (usize, usize) {
+    let range = cs.read_group().unwrap_or_default().into_raw_string();
+    let mut iter = range
+        .split('-')
+        .map(|x| x.trim().parse::<usize>().unwrap_or(0));
+    let from = iter.next().unwrap_or(0);
+    let to = iter.next().unwrap_or(from);
+
+    (from, to)
+}
+
+#[derive(Debug)]
+pub(super) struct RawCell {
+    pub colspan: usize,
+    pub rowspan: usize,
+    pub align: Option<ColumnAlign>,
+    pub content: TexChars,
+}
+
+impl RawCell {
+    pub(crate) fn into_cell(self, content: Vec<Key>) -> TableCell {
+        TableCell {
+            colspan: self.colspan,
+            rowspan: self.rowspan,
+            align: self.align,
+            content,
+        }
+    }
+}
+
+/// セル先頭の `\multicolumn` / `\multirow` を読む
+pub(super) fn read_cell(mut cs: TexChars) -> RawCell {
+    cs.skip_whitespaces();
+
+    match cs.peek_command_name().as_deref() {
+        Some("multicolumn") => {
+            cs.consume_command_name();
+            let colspan = read_count(&mut cs);
+            let spec = cs.read_group().unwrap_or_default().into_raw_string();
+            let inner = read_cell(cs.read_group().unwrap_or_default());
+
+            RawCell {
+                colspan,
+                rowspan: inner.rowspan,
+                align: parse_column_spec(&spec)
+                    .columns
+                    .into_iter()
+                    .next()
+                    .map(|x| x.align),
+                content: inner.content.chain(cs).collect(),
+            }
+        }
+        Some("multirow") => {
+            cs.consume_command_name();
+            cs.read_optional();
+            let rowspan = read_count(&mut cs);
+            cs.read_optional();
+            cs.read_group(); // 幅
+            cs.read_optional();
+            let content = cs.read_group().unwrap_or_default();
+
+            RawCell {
+                colspan: 1,
+                rowspan,
+                align: None,
+                content: content.chain(cs).collect(),
+            }
+        }
+        _ => RawCell {
+            colspan: 1,
+            rowspan: 1,
+            align: None,
+            content: cs,
+        },
+    }
+}
+
+fn read_count(cs: &mut TexChars) -> usize {
+    cs.read_group()
+        .and_then(|x| x.into_raw_string().trim().parse().ok())
+        .unwrap_or(1)
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn parse_column_spec() {
+        use ColumnAlign::*;
+
+        let spec = super::parse_column_spec("|l||c|p{3cm}@{ : }r|");
+        assert_eq!(
+            spec.columns()
+                .iter()
+                .map(|x| (x.align.clone(), x.width.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Left, None),
+                (Center, None),
+                (Top, Some("3cm".to_string())),
+                (Right, None),
+            ]
+        );
+        assert_eq!(spec.vrules(), &[1, 2, 1, 0, 1]);
+    }
+
+    #[test]
+    fn 繰り返し指定の展開() {
+        let spec = super::parse_column_spec("l*{3}{c|}>{\\bfseries}X");
+        assert_eq!(spec.columns().len(), 5);
+        assert_eq!(spec.vrules(), &[0, 0, 1, 1, 1, 0]);
+        assert_eq!(spec.columns()[4].align, ColumnAlign::Stretch);
+    }
+
+    #[test]
+    fn split_rows() {
+        let cs = TexChars::from_str(
+            r"\toprule
+            a & \{b\&c\} \\[2pt] \midrule
+            {x & y} & z \\ \cline{1-2} \cmidrule(lr){2-3}
+            1 & 2 \\
+            \bottomrule",
+        )
+        .unwrap();
+        let (rows, trailing) = super::split_rows(cs);
+
+        let cells = |row: &RawRow| {
+            row.cells
+                .iter()
+                .map(|x| x.clone().into_content_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].rules, vec![TableRule::Toprule]);
+        assert_eq!(cells(&rows[0]), vec!["a", r"\{b\&c\}"]);
+        assert_eq!(rows[1].rules, vec![TableRule::Midrule]);
+        assert_eq!(cells(&rows[1]), vec!["{x & y}", "z"]);
+        assert_eq!(
+            rows[2].rules,
+            vec![
+                TableRule::Cline { from: 1, to: 2 },
+                TableRule::Cmidrule { from: 2, to: 3 }
+            ]
+        );
+        assert_eq!(trailing, vec![TableRule::Bottomrule]);
+    }
+
+    #[test]
+    fn 入れ子の環境と数式では分けない() {
+        let cs = TexChars::from_str(
+            r"\begin{matrix}a&b\\c&d\end{matrix} & $\begin{aligned}x&=1\\y&=2\end{aligned}$ \\
+            $a & b$ & \(c \\ d\) & $$e & f$$",
+        )
+        .unwrap();
+        let (rows, _) = super::split_rows(cs);
+
+        let cells: Vec<Vec<_>> = rows
+            .iter()
+            .map(|row| {
+                row.cells
+                    .iter()
+                    .map(|x| x.clone().into_content_string())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            cells,
+            vec![
+                vec![
+                    r"\begin{matrix}a&b\\c&d\end{matrix}",
+                    r"$\begin{aligned}x&=1\\y&=2\end{aligned}$"
+                ],
+                vec!["$a & b$", r"\(c \\ d\)", "$$e & f$$"],
+            ]
+        );
+    }
+
+    #[test]
+    fn read_cell() {
+        let cell = super::read_cell(
+            TexChars::from_str(r" \multicolumn{2}{|c|}{\multirow{3}{*}{$x$}}").unwrap(),
+        );
+        assert_eq!(cell.colspan, 2);
+        assert_eq!(cell.rowspan, 3);
+        assert_eq!(cell.align, Some(ColumnAlign::Center));
+        assert_eq!(cell.content.into_content_string(), "$x$");
+    }
+}
@@ -8,6 +8,8 @@ pub(super) enum TexChar {
     RParen,
     LBracket,
     RBracket,
+    LBrace,
+    RBrace,
     Dollar,
     Return,
     Period,
@@ -29,6 +31,8 @@ impl From<char> for TexChar {
             ')' => RParen,
             '[' => LBracket,
             ']' => RBracket,
+            '{' => LBrace,
+            '}' => RBrace,
             '\n' => Return,
             '$' => Dollar,
             _ => Char(c),
@@ -47,6 +51,8 @@ impl Display for TexChar {
             RParen => write!(f, ")"),
             LBracket => write!(f, "["),
             RBracket => write!(f, "]"),
+            LBrace => write!(f, "{{"),
+            RBrace => write!(f, "}}"),
             Return => writeln!(f),
             Dollar => write!(f, "$"),
             Period => write!(f, "."),
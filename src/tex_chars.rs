@@ -2,7 +2,7 @@ use crate::tex_char::TexChar;
 use std::collections::VecDeque;
 use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
 pub(super) struct TexChars {
     queue: VecDeque<TexChar>,
@@ -23,6 +23,185 @@ impl TexChars {
         self.queue.front().cloned()
     }
 
+    pub(crate) fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &TexChar> {
+        self.queue.iter()
+    }
+
+    /// 先頭が `\name` の形であれば name を返す (消費はしない)
+    pub(crate) fn peek_command_name(&self) -> Option<String> {
+        let mut iter = self.queue.iter();
+        if iter.next() != Some(&TexChar::Backslash) {
+            return None;
+        }
+
+        let name: String = iter
+            .map_while(|c| match c {
+                TexChar::Char(c) if c.is_ascii_alphabetic() => Some(*c),
+                _ => None,
+            })
+            .collect();
+
+        if name.is_empty() {
+            None
+        } else {
+            Some(name)
+        }
+    }
+
+    /// 先頭の `\name` (`\name*` も含む) の後に `{...}` が続くか (消費はしない)
+    pub(crate) fn command_has_group(&self) -> bool {
+        let Some(name) = self.peek_command_name() else {
+            return false;
+        };
+        self.queue
+            .iter()
+            .skip(name.chars().count() + 1)
+            .skip_while(|c| **c == TexChar::Char('*'))
+            .find(|c| !matches!(c, TexChar::Whitespace | TexChar::Return))
+            == Some(&TexChar::LBrace)
+    }
+
+    pub(crate) fn consume_command_name(&mut self) -> Option<String> {
+        let name = self.peek_command_name()?;
+        self.queue.drain(..name.chars().count() + 1);
+        Some(name)
+    }
+
+    pub(crate) fn skip_whitespaces(&mut self) {
+        while matches!(
+            self.read_next(),
+            Some(TexChar::Whitespace | TexChar::Return)
+        ) {
+            self.queue.pop_front();
+        }
+    }
+
+    fn next_non_whitespace_is(&self, c: TexChar) -> bool {
+        self.queue
+            .iter()
+            .find(|x| !matches!(x, TexChar::Whitespace | TexChar::Return))
+            == Some(&c)
+    }
+
+    pub(crate) fn consume_star(&mut self) -> bool {
+        if self.next_is(TexChar::Char('*')) {
+            self.queue.pop_front();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// `{...}` を読み取り, 中身を返す
+    pub(crate) fn read_group(&mut self) -> Option<TexChars> {
+        self.read_delimited(TexChar::LBrace, TexChar::RBrace)
+    }
+
+    /// `[...]` を読み取り, 中身を返す
+    pub(crate) fn read_optional(&mut self) -> Option<TexChars> {
+        self.read_delimited(TexChar::LBracket, TexChar::RBracket)
+    }
+
+    fn read_delimited(&mut self, open: TexChar, close: TexChar) -> Option<TexChars> {
+        if !self.next_non_whitespace_is(open.clone()) {
+            return None;
+        }
+        self.skip_whitespaces();
+        self.queue.pop_front();
+
+        let mut inner = VecDeque::new();
+        let mut depth = 0;
+        while let Some(c) = self.queue.pop_front() {
+            match c {
+                TexChar::Backslash => {
+                    // エスケープされた文字はそのまま読み進める
+                    inner.push_back(c);
+                    if let Some(c) = self.queue.pop_front() {
+                        inner.push_back(c);
+                    }
+                    continue;
+                }
+                TexChar::LBrace => depth += 1,
+                TexChar::RBrace if depth > 0 => depth -= 1,
+                _ if c == close && depth == 0 => break,
+                _ => {}
+            }
+            inner.push_back(c);
+        }
+
+        Some(Self { queue: inner })
+    }
+
+    pub(crate) fn starts_with(&self, s: &str) -> bool {
+        let mut iter = self.queue.iter();
+        s.chars().all(|c| iter.next() == Some(&TexChar::from(c)))
+    }
+
+    /// 先頭が `\begin{name}` の形であれば name を返す (消費はしない)
+    pub(crate) fn peek_begin_env(&self) -> Option<String> {
+        if self.peek_command_name()? != "begin" {
+            return None;
+        }
+
+        let mut iter = self
+            .queue
+            .iter()
+            .skip("\\begin".len())
+            .skip_while(|c| matches!(c, TexChar::Whitespace));
+        if iter.next() != Some(&TexChar::LBrace) {
+            return None;
+        }
+
+        let mut name = String::new();
+        for c in iter {
+            match c {
+                TexChar::RBrace => return Some(name),
+                TexChar::Char(c) => name.push(*c),
+                _ => return None,
+            }
+        }
+
+        None
+    }
+
+    pub(crate) fn consume_begin_env(&mut self) -> Option<String> {
+        self.peek_begin_env()?;
+        self.consume_command_name();
+        self.read_group().map(|x| x.into_raw_string())
+    }
+
+    /// `\end{name}` までを読み取り, 中身を返す (`\end{name}` は消費する)
+    pub(crate) fn read_env_body(&mut self, name: &str) -> TexChars {
+        let begin = format!("\\begin{{{}}}", name);
+        let end = format!("\\end{{{}}}", name);
+
+        let mut inner = VecDeque::new();
+        let mut depth = 0;
+        while !self.queue.is_empty() {
+            if self.starts_with(&begin) {
+                depth += 1;
+            } else if self.starts_with(&end) {
+                if depth == 0 {
+                    self.queue.drain(..end.chars().count());
+                    break;
+                }
+                depth -= 1;
+            }
+
+            inner.push_back(self.queue.pop_front().unwrap());
+        }
+
+        Self { queue: inner }
+    }
+
+    pub(crate) fn into_raw_string(self) -> String {
+        self.queue.into_iter().map(|c| c.to_string()).collect()
+    }
+
     pub(crate) fn into_content_string(self) -> String {
         use TexChar::*;
 
@@ -55,10 +234,8 @@ impl TexChars {
                         Comma | Period => {
                             new_cs.push(Whitespace);
                         }
-                        Char(c) => {
-                            if c.is_ascii_alphanumeric() || *c == '?' || *c == '!' {
-                                new_cs.push(Whitespace);
-                            }
+                        Char(c) if c.is_ascii_alphanumeric() || *c == '?' || *c == '!' => {
+                            new_cs.push(Whitespace);
                         }
                         _ => {}
                     }
@@ -216,4 +393,32 @@ mod tests {
 
         assert_content_string!("foo, \nbar", "foo, bar");
     }
+
+    #[test]
+    fn read_group() {
+        let mut cs: TexChars = r" {a{b}\}c}d".parse().unwrap();
+        assert_eq!(cs.read_group().unwrap().into_content_string(), r"a{b}\}c");
+        assert_eq!(cs.into_content_string(), "d");
+
+        let mut cs: TexChars = "x{a}".parse().unwrap();
+        assert!(cs.read_group().is_none());
+        assert_eq!(cs.into_content_string(), "x{a}");
+    }
+
+    #[test]
+    fn read_optional() {
+        let mut cs: TexChars = "[a{]}b]{c}".parse().unwrap();
+        assert_eq!(cs.read_optional().unwrap().into_content_string(), "a{]}b");
+        assert!(cs.read_optional().is_none());
+        assert_eq!(cs.read_group().unwrap().into_content_string(), "c");
+    }
+
+    #[test]
+    fn peek_command_name() {
+        let cs: TexChars = r"\section*{x}".parse().unwrap();
+        assert_eq!(cs.peek_command_name(), Some("section".to_string()));
+
+        let cs: TexChars = r"\(x\)".parse().unwrap();
+        assert_eq!(cs.peek_command_name(), None);
+    }
 }
@@ -0,0 +1,260 @@
+use crate::key::Key;
+use crate::macros::Macros;
+use crate::metadata::MetadataDecl;
+use crate::node::Node;
+use crate::reference::Labels;
+use crate::result_map::ResultMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// 定理の依存関係のグラフ
+///
+/// 定理 (補題なども含む) やその証明の中で参照しているラベルへ辺を張る.
+/// 証明は `\begin{proof}[Proof of \ref{..}]` の参照先, なければ直前の定理のものとする.
+/// ラベルの付いた定理はどこからも参照されなくても頂点に含める.
+#[derive(Debug, Serialize, Default, Clone, PartialEq, Eq)]
+pub struct TheoremGraph {
+    /// ラベルから頂点
+    nodes: BTreeMap<String, GraphNode>,
+    edges: Vec<GraphEdge>,
+}
+
+/// ラベルの付いた定理や数式など
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct GraphNode {
+    /// 定理環境はその環境名 (`thm` や `lem`), それ以外は `section` や `equation` など
+    pub env: String,
+    /// 定理環境の表示名 (`Theorem` など)
+    pub title: Option<String>,
+    pub number: Option<String>,
+}
+
+/// `from` が `to` を参照している
+#[derive(Debug, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+}
+
+impl TheoremGraph {
+    pub fn nodes(&self) -> &BTreeMap<String, GraphNode> {
+        &self.nodes
+    }
+
+    pub fn edges(&self) -> &[GraphEdge] {
+        &self.edges
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// Graphviz の DOT 形式にする. 頂点の表示は `Theorem 1.2` のような名前と番号
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph theorems {\n");
+        for (label, node) in &self.nodes {
+            dot.push_str(&format!(
+                "  {} [label={}];\n",
+                quote(label),
+                quote(&node.display_name(label))
+            ));
+        }
+        for edge in &self.edges {
+            dot.push_str(&format!(
+                "  {} -> {};\n",
+                quote(&edge.from),
+                quote(&edge.to)
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl GraphNode {
+    fn display_name(&self, label: &str) -> String {
+        let name = self.title.as_deref().unwrap_or(&self.env);
+        match &self.number {
+            Some(number) => format!("{} {}", name, number),
+            None if self.title.is_some() => name.to_string(),
+            None => label.to_string(),
+        }
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// 参照を解決した文書からグラフを作る
+pub(super) fn build(rmap: &ResultMap, labels: &Labels, macros: &Macros) -> TheoremGraph {
+    // 環境の Key から, それに付いたラベル
+    let mut label_of: HashMap<&Key, &str> = HashMap::new();
+    for (label, target) in labels {
+        if matches!(rmap.get(target.key()), Some(Node::Environment(_))) {
+            let entry = label_of.entry(target.key()).or_insert(label);
+            *entry = (*entry).min(label.as_str());
+        }
+    }
+
+    let mut edges = BTreeSet::new();
+    // Key の順は文書の順なので, 直前の定理は順にたどれば分かる
+    let mut last_theorem: Option<&str> = None;
+    for (key, node) in rmap.iter() {
+        let Node::Environment(info) = node else {
+            continue;
+        };
+        let source = if macros.theorem(info.name()).is_some() {
+            last_theorem = label_of.get(key).copied();
+            last_theorem
+        } else if info.name() == "proof" {
+            let proved = info
+                .args()
+                .first()
+                .and_then(|x| referenced_label(x))
+                .filter(|x| labels.contains_key(*x));
+            label_of.get(key).copied().or(proved).or(last_theorem)
+        } else {
+            continue;
+        };
+        let Some(source) = source else {
+            continue;
+        };
+
+        let mut referenced = Vec::new();
+        collect_references(rmap, macros, info.content(), &mut referenced);
+        for label in referenced {
+            if label != source && labels.contains_key(label) {
+                edges.insert(GraphEdge {
+                    from: source.to_string(),
+                    to: label.to_string(),
+                });
+            }
+        }
+    }
+
+    let theorems = labels
+        .iter()
+        .filter_map(|(label, target)| match rmap.get(target.key()) {
+            Some(Node::Environment(info)) if macros.theorem(info.name()).is_some() => Some(label),
+            _ => None,
+        });
+    let nodes = theorems
+        .chain(edges.iter().flat_map(|x| [&x.from, &x.to]))
+        .filter_map(|label| {
+            let target = labels.get(label)?;
+            let node = match rmap.get(target.key()) {
+                Some(Node::Environment(info)) => GraphNode {
+                    env: info.name().to_string(),
+                    title: macros.theorem(info.name()).map(|x| x.title().to_string()),
+                    number: info.number().map(|x| x.to_string()),
+                },
+                _ => GraphNode {
+                    env: target.kind_name().to_string(),
+                    title: None,
+                    number: target.number().map(|x| x.to_string()),
+                },
+            };
+            Some((label.clone(), node))
+        })
+        .collect();
+
+    TheoremGraph {
+        nodes,
+        edges: edges.into_iter().collect(),
+    }
+}
+
+/// 中身の参照のラベルを集める. 入れ子の定理と証明はそれ自身で辺を張るので除く
+fn collect_references<'a>(
+    rmap: &'a ResultMap,
+    macros: &Macros,
+    keys: &[Key],
+    labels: &mut Vec<&'a str>,
+) {
+    for key in keys {
+        let children: Vec<Key> = match rmap.get(key) {
+            Some(Node::Reference(info)) => {
+                labels.extend(
+                    info.targets()
+                        .filter(|(_, x)| x.is_some_and(|x| x.document().is_none()))
+                        .map(|(label, _)| label),
+                );
+                continue;
+            }
+            Some(Node::Environment(info))
+                if info.name() == "proof" || macros.theorem(info.name()).is_some() =>
+            {
+                continue;
+            }
+            Some(Node::Environment(info)) => info.content().to_vec(),
+            Some(Node::ParagraphList(Some(ks)) | Node::Paragraph(Some(ks))) => ks.clone(),
+            Some(Node::Metadata(MetadataDecl::Abstract(ks))) => ks.clone(),
+            Some(Node::Heading(info)) => info.title().to_vec(),
+            Some(Node::Table(info)) => info.content_keys().cloned().collect(),
+            Some(Node::List(info)) => info.content_keys().cloned().collect(),
+            Some(Node::Figure(info)) => info.child_keys(),
+            _ => continue,
+        };
+        collect_references(rmap, macros, &children, labels);
+    }
+}
+
+/// `Proof of \ref{thm:a}` のような注釈から参照先のラベルを取り出す
+fn referenced_label(note: &str) -> Option<&str> {
+    let start = ["\\ref{", "\\cref{", "\\Cref{", "\\autoref{"]
+        .iter()
+        .filter_map(|x| Some(note.find(x)? + x.len()))
+        .min()?;
+    let end = note[start..].find('}')? + start;
+    Some(note[start..end].trim())
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn 注釈の参照() {
+        assert_eq!(referenced_label(r"Proof of \cref{thm:a}"), Some("thm:a"));
+        assert_eq!(referenced_label(r"\ref{ x }"), Some("x"));
+        assert_eq!(referenced_label("Sketch"), None);
+    }
+
+    #[test]
+    fn dot() {
+        let mut graph = TheoremGraph::default();
+        graph.nodes.insert(
+            "thm:a".to_string(),
+            GraphNode {
+                env: "thm".to_string(),
+                title: Some("Theorem".to_string()),
+                number: Some("1".to_string()),
+            },
+        );
+        graph.nodes.insert(
+            "eq:\"x\"".to_string(),
+            GraphNode {
+                env: "equation".to_string(),
+                title: None,
+                number: None,
+            },
+        );
+        graph.edges.push(GraphEdge {
+            from: "thm:a".to_string(),
+            to: "eq:\"x\"".to_string(),
+        });
+
+        assert_eq!(
+            graph.to_dot(),
+            "digraph theorems {
+  \"eq:\\\"x\\\"\" [label=\"eq:\\\"x\\\"\"];
+  \"thm:a\" [label=\"Theorem 1\"];
+  \"thm:a\" -> \"eq:\\\"x\\\"\";
+}
+"
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use crate::parser::ParseOk;
+use crate::reference::{self, ExternalTarget, Labels};
+use crate::tex_chars::TexChars;
+
+/// 互いに参照し合う複数の文書
+///
+/// `\externaldocument[prefix]{name}` (xr パッケージ) を書いた文書では `\ref{prefix label}` で,
+/// どの文書からも `\ref{name:label}` で, 文書 `name` のラベルを参照できる.
+#[derive(Debug, Clone, Default)]
+pub struct Workspace {
+    documents: Vec<(String, String)>,
+}
+
+impl Workspace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 文書を加える. 同じ名前の文書は置き換える
+    pub fn add_document(&mut self, name: impl Into<String>, source: impl Into<String>) {
+        let name = name.into();
+        let source = source.into();
+        match self.documents.iter_mut().find(|(x, _)| *x == name) {
+            Some(document) => document.1 = source,
+            None => self.documents.push((name, source)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    pub(crate) fn documents(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.documents
+            .iter()
+            .map(|(name, source)| (name.as_str(), source.as_str()))
+    }
+}
+
+/// `\externaldocument[prefix]{name}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct ExternalDocument {
+    prefix: String,
+    name: String,
+}
+
+impl ExternalDocument {
+    /// `\externaldocument` を読む. xr-hyper の2つ目の省略可能引数は読み捨てる
+    pub(crate) fn read(cs: &mut TexChars) -> Option<Self> {
+        cs.consume_command_name();
+        let prefix = cs.read_optional().map(|x| x.into_raw_string());
+        cs.read_optional();
+        let name = cs.read_group()?.into_raw_string();
+
+        Some(Self {
+            prefix: prefix.unwrap_or_default().trim().to_string(),
+            name: name.trim().to_string(),
+        })
+    }
+}
+
+/// 各文書で解決できなかった参照を, 全文書のラベルから解決する
+///
+/// `\externaldocument` の接頭辞を優先し, 次に `name:label` の形を試す.
+pub(super) fn resolve(documents: &mut [(&str, ParseOk)]) {
+    let labels: Vec<(&str, Labels)> = documents
+        .iter_mut()
+        .map(|(name, ok)| (*name, std::mem::take(&mut ok.labels)))
+        .collect();
+    let find = |name: &str| {
+        let name = name.strip_suffix(".tex").unwrap_or(name);
+        labels.iter().find(|(x, _)| *x == name)
+    };
+
+    for (_, ok) in documents.iter_mut() {
+        let externals = &ok.external_documents;
+        let lookup = |label: &str| {
+            let qualified = externals
+                .iter()
+                .filter_map(|x| Some((x.name.as_str(), label.strip_prefix(x.prefix.as_str())?)))
+                .chain(label.split_once(':'));
+
+            let mut dangling = None;
+            for (name, label) in qualified {
+                let Some((name, labels)) = find(name) else {
+                    continue;
+                };
+                match labels.get(label) {
+                    Some(target) => return ExternalTarget::Found(name, target),
+                    None => dangling = dangling.or(Some(*name)),
+                }
+            }
+            match dangling {
+                Some(name) => ExternalTarget::Dangling(name),
+                None => ExternalTarget::NotFound,
+            }
+        };
+        reference::resolve_external(&mut ok.rmap, &mut ok.diagnostics, lookup);
+    }
+
+    for ((_, ok), (_, labels)) in documents.iter_mut().zip(labels) {
+        ok.labels = labels;
+    }
+}
+
+//noinspection ALL
+#[allow(non_snake_case)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Node;
+    use crate::options::ParseOptions;
+    use crate::parser::parse_paragraphs;
+
+    /// 各文書の参照 (表記, 参照先の文書) と診断の種類
+    type Resolved = (Vec<(String, Option<String>)>, Vec<&'static str>);
+
+    /// 文書を読んで参照を解決する
+    fn resolve_all(sources: &[(&'static str, &str)]) -> Vec<Resolved> {
+        let mut documents: Vec<_> = sources
+            .iter()
+            .map(|(name, source)| {
+                (
+                    *name,
+                    parse_paragraphs(source, &ParseOptions::new()).unwrap(),
+                )
+            })
+            .collect();
+        resolve(&mut documents);
+
+        documents
+            .iter()
+            .map(|(_, ok)| {
+                let refs = ok
+                    .rmap
+                    .iter()
+                    .filter_map(|(_, node)| match node {
+                        Node::Reference(info) => Some((
+                            info.display_text(),
+                            info.targets()
+                                .find_map(|(_, x)| x?.document())
+                                .map(|x| x.to_string()),
+                        )),
+                        _ => None,
+                    })
+                    .collect();
+                let codes = ok.diagnostics.iter().map(|x| x.code()).collect();
+                (refs, codes)
+            })
+            .collect()
+    }
+
+    fn reference(text: &str, document: Option<&str>) -> (String, Option<String>) {
+        (text.to_string(), document.map(|x| x.to_string()))
+    }
+
+    #[test]
+    fn 接頭辞と文書名() {
+        let results = resolve_all(&[
+            (
+                "note",
+                r"\section{A}\section{B}\label{sec:b}
+                \newtheorem{thm}{Theorem}\begin{thm}\label{G-x} 本文 \end{thm}",
+            ),
+            (
+                "main",
+                r"\externaldocument[G-]{note}
+                \ref{G-sec:b}, \ref{note:sec:b}, \ref{G-x}",
+            ),
+        ]);
+
+        // `G-x` は接頭辞を除いた `x` を探すので, `note` のラベル `G-x` とは別
+        let (refs, codes) = &results[1];
+        assert_eq!(
+            refs,
+            &vec![
+                reference("2", Some("note")),
+                reference("2", Some("note")),
+                reference("??", None),
+            ]
+        );
+        assert_eq!(codes, &vec!["dangling_reference"]);
+    }
+
+    #[test]
+    fn 同じラベル() {
+        let results = resolve_all(&[
+            ("a", r"\section{A}\label{intro}"),
+            (
+                "b",
+                r"\section{B}\section{C}\label{intro} \ref{intro}, \ref{a:intro}",
+            ),
+            ("c", r"\ref{a:intro}, \ref{b:intro}, \ref{intro}"),
+        ]);
+
+        // 文書の中のラベルが優先され, 他の文書の同じラベルは名前を付けて参照する
+        assert_eq!(
+            results[1].0,
+            vec![reference("2", None), reference("1", Some("a"))]
+        );
+        assert_eq!(
+            results[2].0,
+            vec![
+                reference("1", Some("a")),
+                reference("2", Some("b")),
+                reference("??", None),
+            ]
+        );
+        assert_eq!(results[2].1, vec!["unresolved_reference"]);
+    }
+
+    #[test]
+    fn 見つからない参照() {
+        let results = resolve_all(&[
+            ("a", r"\section{A}\label{sec:a}"),
+            ("b", r"\ref{a:sec:none}, \ref{z:sec:a}, \ref{a.tex:sec:a}"),
+        ]);
+
+        // 文書 `a` はあるがラベルがなければ dangling, 文書がなければ未解決のまま
+        let (refs, codes) = &results[1];
+        assert_eq!(
+            refs,
+            &vec![
+                reference("??", None),
+                reference("??", None),
+                reference("1", Some("a")),
+            ]
+        );
+        assert_eq!(codes, &vec!["dangling_reference", "unresolved_reference"]);
+    }
+}